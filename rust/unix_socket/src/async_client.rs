@@ -0,0 +1,444 @@
+use std::future::poll_fn;
+use std::io::{self, Read};
+use std::pin::Pin;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::body::CHUNK_SIZE;
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::proto::{self, Event, ResponseDecoder};
+use crate::url::Url;
+use crate::{encoding, headers, HttpHeader, Request, Response};
+
+// NOTE: the traits of futures-io are used so that any runtime can be plugged
+// in, e.g. smol's UnixStream. tokio streams are adapted by TokioIo
+pub trait AsyncReadWriter: AsyncRead + AsyncWrite + Unpin {}
+
+impl<T> AsyncReadWriter for T where T: AsyncRead + AsyncWrite + Unpin {}
+
+pub type AsyncTransport = Box<dyn AsyncReadWriter + Send>;
+
+async fn read<R: AsyncRead + Unpin + ?Sized>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    poll_fn(|cx| Pin::new(&mut *r).poll_read(cx, buf)).await
+}
+
+async fn write_all<W: AsyncWrite + Unpin + ?Sized>(w: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = poll_fn(|cx| Pin::new(&mut *w).poll_write(cx, buf)).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+    }
+    Ok(())
+}
+
+async fn flush<W: AsyncWrite + Unpin + ?Sized>(w: &mut W) -> io::Result<()> {
+    poll_fn(|cx| Pin::new(&mut *w).poll_flush(cx)).await
+}
+
+// async twin of HttpClient. the protocol is parsed by ResponseDecoder, which is
+// shared with the other clients
+pub struct AsyncHttpClient<T: AsyncReadWriter> {
+    conn: T,
+    // read from the connection but not decoded yet
+    buf: Vec<u8>,
+    eof: bool,
+    base_url: Option<Url>,
+    keep_alive: bool,
+    decompress: bool,
+    limits: Limits,
+}
+
+// response body which is read from the connection as it is consumed, see Body
+pub struct AsyncBody<'a, T: AsyncReadWriter> {
+    client: &'a mut AsyncHttpClient<T>,
+    decoder: ResponseDecoder,
+}
+
+impl<T: AsyncReadWriter> AsyncBody<'_, T> {
+    // next piece of the body, None at the end. a piece doesn't span transfer
+    // chunks of a chunked body
+    // NOTE: the body is not decoded by Content-Encoding, see Response::buffered
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>, HttpError> {
+        match self.client.next_event(&mut self.decoder).await? {
+            Event::Data(data) => Ok(Some(data)),
+            Event::End => {
+                self.client.keep_alive = self.decoder.is_keep_alive();
+                Ok(None)
+            }
+            Event::Informational(_) | Event::Head(_) => {
+                Err(HttpError::Parse("unexpected response head".into()))
+            }
+        }
+    }
+
+    // read the whole body into memory
+    pub async fn bytes(mut self) -> Result<Vec<u8>, HttpError> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+impl<T: AsyncReadWriter> Response<AsyncBody<'_, T>> {
+    // read the whole body like execute_request does
+    pub async fn buffered(mut self) -> Result<Response, HttpError> {
+        let (decompress, max_body_size) = match &self.body {
+            Some(body) => (body.client.decompress, body.client.limits.max_body_size),
+            None => (false, None),
+        };
+        let mut body = Vec::new();
+        if let Some(mut b) = self.body.take() {
+            while let Some(chunk) = b.chunk().await? {
+                body.extend_from_slice(&chunk);
+            }
+            self.trailers = b.decoder.take_trailers();
+        }
+        let body = match decompress {
+            true => proto::decode_body(&mut self.header, body, max_body_size)?,
+            false => body,
+        };
+        Ok(self.with_body(Some(body.into())))
+    }
+}
+
+// source of the streamed request body
+enum BodyReader {
+    Blocking(Box<dyn Read + Send>),
+    Async(Pin<Box<dyn AsyncRead + Send>>),
+}
+
+impl<T: AsyncReadWriter> AsyncHttpClient<T> {
+    pub fn new(conn: T) -> Self {
+        Self {
+            conn,
+            buf: Vec::new(),
+            eof: false,
+            base_url: None,
+            keep_alive: true,
+            decompress: true,
+            limits: Limits::default(),
+        }
+    }
+
+    // relative request urls are resolved against this url
+    pub fn base_url(&mut self, p: Url) -> &mut Self {
+        self.base_url = Some(p);
+        self
+    }
+
+    pub fn decompress(&mut self, p: bool) -> &mut Self {
+        self.decompress = p;
+        self
+    }
+
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // NOTE: Request::timeout is not applied since timers depend on the
+    // runtime. wrap the future with the timeout of the runtime instead
+    pub async fn execute_request(&mut self, req: &mut Request) -> Result<Response, HttpError> {
+        self.execute_streaming(req).await?.buffered().await
+    }
+
+    // like execute_request, but the body is not read. the connection can't be
+    // used for the next request until the body is read to the end
+    pub async fn execute_streaming(
+        &mut self,
+        req: &mut Request,
+    ) -> Result<Response<AsyncBody<'_, T>>, HttpError> {
+        if !self.keep_alive {
+            return Err(HttpError::ConnectionClosed);
+        }
+        if let Some(base_url) = &self.base_url {
+            if !req.url.is_absolute() {
+                req.url = base_url.resolve(&req.url);
+            }
+        }
+        if self.decompress && !req.has_header(headers::ACCEPT_ENCODING) {
+            req.header
+                .get_or_insert_with(HttpHeader::new)
+                .add(headers::ACCEPT_ENCODING, &encoding::accept_encoding());
+        }
+        let head = req.build()?;
+        // NOTE: the connection is reusable only after the whole body is read
+        self.keep_alive = false;
+        self.write_request(req, &head).await?;
+
+        let close = req.has_token(headers::CONNECTION, "close");
+        let mut decoder = ResponseDecoder::new(self.limits, close);
+        decoder.method(req.method.clone());
+        let resp = loop {
+            match self.next_event(&mut decoder).await? {
+                Event::Informational(_) => continue,
+                Event::Head(resp) => break resp,
+                _ => return Err(HttpError::Parse("missing response head".into())),
+            }
+        };
+        Ok(resp.with_body(Some(AsyncBody {
+            client: self,
+            decoder,
+        })))
+    }
+
+    async fn next_event(&mut self, decoder: &mut ResponseDecoder) -> Result<Event, HttpError> {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            if let Some(event) = decoder.decode(&mut self.buf, self.eof)? {
+                return Ok(event);
+            }
+            let n = read(&mut self.conn, &mut buf).await?;
+            self.eof = n == 0;
+            self.buf.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    async fn write_request(&mut self, req: &mut Request, head: &[u8]) -> Result<(), HttpError> {
+        let length = req.reader_length();
+        let reader = match (req.async_body_reader.take(), req.take_body_reader()) {
+            (Some(r), _) => Some(BodyReader::Async(r)),
+            // NOTE: a blocking reader blocks the runtime while it's read, which
+            // is fine for files but not for pipes or sockets
+            (None, Some(r)) => Some(BodyReader::Blocking(match req.compress {
+                Some(encoding) => encoding.encoder(r),
+                None => r,
+            })),
+            (None, None) => None,
+        };
+        write_all(&mut self.conn, head).await?;
+        let Some(mut reader) = reader else {
+            flush(&mut self.conn).await?;
+            return Ok(());
+        };
+
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut sent = 0u64;
+        loop {
+            let n = match &mut reader {
+                BodyReader::Blocking(r) => match r.read(&mut buf) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    n => n?,
+                },
+                BodyReader::Async(r) => read(r, &mut buf).await?,
+            };
+            if n == 0 {
+                break;
+            }
+            match length {
+                Some(length) if sent + n as u64 > length => {
+                    return Err("body is longer than content-length".into());
+                }
+                Some(_) => write_all(&mut self.conn, &buf[..n]).await?,
+                None => {
+                    let mut chunk = format!("{:x}\r\n", n).into_bytes();
+                    chunk.extend_from_slice(&buf[..n]);
+                    chunk.extend_from_slice(b"\r\n");
+                    write_all(&mut self.conn, &chunk).await?;
+                }
+            }
+            sent += n as u64;
+            if let Some(progress) = req.progress.as_mut() {
+                progress(sent);
+            }
+        }
+        match length {
+            Some(length) if sent < length => {
+                return Err("body is shorter than content-length".into());
+            }
+            Some(_) => {}
+            None => write_all(&mut self.conn, b"0\r\n\r\n").await?,
+        }
+        flush(&mut self.conn).await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::tokio_io::TokioIo;
+
+#[cfg(feature = "tokio")]
+mod tokio_io {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+
+    use super::{AsyncHttpClient, AsyncTransport};
+    use crate::error::HttpError;
+    use crate::transport;
+    use crate::url::Url;
+
+    // adapts tokio's AsyncRead and AsyncWrite to the ones of futures-io
+    pub struct TokioIo<T>(pub T);
+
+    impl<T: tokio::io::AsyncRead + Unpin> futures_io::AsyncRead for TokioIo<T> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buf = ReadBuf::new(buf);
+            match Pin::new(&mut self.get_mut().0).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl<T: tokio::io::AsyncWrite + Unpin> futures_io::AsyncWrite for TokioIo<T> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+        }
+    }
+
+    impl AsyncHttpClient<AsyncTransport> {
+        // e.g. AsyncHttpClient::connect("unix:///var/run/docker.sock").await
+        pub async fn connect(url: &str) -> Result<Self, HttpError> {
+            let url = Url::parse(url)?;
+            let conn: AsyncTransport = match url.scheme.as_deref() {
+                #[cfg(unix)]
+                Some("unix") => {
+                    let path = url
+                        .socket_path
+                        .as_ref()
+                        .ok_or_else(|| "missing socket path".to_string())?;
+                    let conn = tokio::net::UnixStream::connect(path)
+                        .await
+                        .map_err(|e| HttpError::Connect(path.clone(), e))?;
+                    Box::new(TokioIo(conn))
+                }
+                #[cfg(windows)]
+                Some("npipe") => {
+                    let path = url
+                        .socket_path
+                        .as_ref()
+                        .ok_or_else(|| "missing pipe path".to_string())?;
+                    let name = crate::npipe::pipe_name(path);
+                    let conn = tokio::net::windows::named_pipe::ClientOptions::new()
+                        .open(&name)
+                        .map_err(|e| HttpError::Connect(name, e))?;
+                    Box::new(TokioIo(conn))
+                }
+                Some("tcp") | Some("http") => {
+                    let host = url.host.as_ref().ok_or("missing host")?;
+                    let port = url.port.or(url.default_port()).unwrap_or(80);
+                    let addr = format!("{}:{}", host, port);
+                    let conn = tokio::net::TcpStream::connect(&addr)
+                        .await
+                        .map_err(|e| HttpError::Connect(addr, e))?;
+                    Box::new(TokioIo(conn))
+                }
+                Some(scheme) => {
+                    return Err(format!("unsupported scheme for async client: {}", scheme).into())
+                }
+                None => return Err(format!("missing scheme: {}", url).into()),
+            };
+            let mut client = Self::new(conn);
+            client.base_url(transport::base_url(&url));
+            Ok(client)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockConn;
+    use crate::HttpMethod;
+
+    #[tokio::test]
+    async fn async_execute_request() {
+        let conn = MockConn::new(concat!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+            "HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\n{}",
+        ));
+        let mut client = AsyncHttpClient::new(conn);
+        let resp = client
+            .execute_request(&mut Request::get("/_ping"))
+            .await
+            .unwrap();
+        assert_eq!(resp.body.unwrap(), b"hello world");
+
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post).body(b"{}".to_vec());
+        let resp = client.execute_request(&mut req).await.unwrap();
+        assert_eq!(resp.status.as_u16(), 201);
+        assert_eq!(resp.body.unwrap(), b"{}");
+
+        let output = String::from_utf8(client.conn.output.clone()).unwrap();
+        assert!(output.starts_with("GET /_ping HTTP/1.1\r\nHost: localhost\r\n"));
+        assert!(output.ends_with("Content-Length: 2\r\n\r\n{}"));
+    }
+
+    #[tokio::test]
+    async fn async_streaming() {
+        let conn = MockConn::new(concat!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "3\r\none\r\n3\r\ntwo\r\n0\r\n\r\n",
+        ));
+        let mut client = AsyncHttpClient::new(conn);
+        let mut req = Request::new("/build");
+        req.method(HttpMethod::Post)
+            .async_body_reader(&b"context"[..]);
+        let mut resp = client.execute_streaming(&mut req).await.unwrap();
+        let mut body = resp.body.take().unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = body.chunk().await.unwrap() {
+            chunks.push(String::from_utf8(chunk).unwrap());
+        }
+        assert_eq!(chunks, ["one", "two"]);
+        assert!(client.keep_alive);
+
+        let output = String::from_utf8(client.conn.output.clone()).unwrap();
+        assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(output.ends_with("\r\n\r\n7\r\ncontext\r\n0\r\n\r\n"));
+
+        let err = client
+            .execute_request(&mut Request::get("/_ping"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, HttpError::ConnectionClosed), "{:?}", err);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_tokio_io() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let server = async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            let mut buf = [0u8; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert!(buf[..n].starts_with(b"GET /_ping HTTP/1.1\r\n"));
+            server
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")
+                .await
+                .unwrap();
+        };
+        let mut client = AsyncHttpClient::new(TokioIo(client));
+        let mut req = Request::get("/_ping");
+        let (resp, _) = tokio::join!(client.execute_request(&mut req), server);
+        assert_eq!(resp.unwrap().body.unwrap(), b"OK");
+    }
+}
@@ -0,0 +1,520 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::CancelToken;
+use crate::error::HttpError;
+use crate::sockopt::SocketOptions;
+use crate::transport::{self, Timeouts, Transport};
+use crate::url::Url;
+use crate::{HttpClient, Request, Response};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    #[default]
+    RoundRobin,
+    // the backend with the fewest leases, round robin among equals
+    LeastConnections,
+}
+
+#[derive(Debug)]
+struct Health {
+    healthy: bool,
+    // consecutive failures and successes
+    failures: u32,
+    successes: u32,
+}
+
+#[derive(Debug)]
+struct Backend {
+    url: Url,
+    active: AtomicUsize,
+    health: Mutex<Health>,
+}
+
+// distributes requests across backends serving the same API, e.g. several
+// daemons or replicas of a service:
+//
+//     let mut balancer = Balancer::new(&["unix:///run/a.sock", "tcp://10.0.0.2:2375"])?;
+//     balancer.strategy(Strategy::LeastConnections).health_check("/_ping");
+//     let balancer = Arc::new(balancer);
+//     Balancer::spawn_health_checks(&balancer);
+//     let resp = balancer.execute_request(&mut Request::get("/containers/json"))?;
+//
+// a backend is ejected after unhealthy_threshold consecutive failures, of
+// connections or of health checks, and re-added after healthy_threshold
+// consecutive successes
+// NOTE: when every backend is ejected, all of them are tried, so that a
+// balancer without health checks recovers by the next successful request
+#[derive(Debug)]
+pub struct Balancer {
+    backends: Vec<Backend>,
+    strategy: Strategy,
+    next: AtomicUsize,
+    timeouts: Timeouts,
+    unhealthy_threshold: u32,
+    healthy_threshold: u32,
+    health_path: Option<String>,
+    health_interval: Duration,
+    health_timeout: Duration,
+    hedge: Option<Duration>,
+}
+
+impl Balancer {
+    pub fn new(urls: &[&str]) -> Result<Self, HttpError> {
+        if urls.is_empty() {
+            return Err(HttpError::Other("no backends".into()));
+        }
+        let backends = urls
+            .iter()
+            .map(|url| {
+                Ok(Backend {
+                    url: Url::parse(url).map_err(HttpError::Parse)?,
+                    active: AtomicUsize::new(0),
+                    health: Mutex::new(Health {
+                        healthy: true,
+                        failures: 0,
+                        successes: 0,
+                    }),
+                })
+            })
+            .collect::<Result<_, HttpError>>()?;
+        Ok(Self {
+            backends,
+            strategy: Strategy::default(),
+            next: AtomicUsize::new(0),
+            timeouts: Timeouts::default(),
+            unhealthy_threshold: 3,
+            healthy_threshold: 2,
+            health_path: None,
+            health_interval: Duration::from_secs(10),
+            health_timeout: Duration::from_secs(5),
+            hedge: None,
+        })
+    }
+
+    pub fn strategy(&mut self, p: Strategy) -> &mut Self {
+        self.strategy = p;
+        self
+    }
+
+    // of the connections to backends
+    pub fn timeouts(&mut self, p: Timeouts) -> &mut Self {
+        self.timeouts = p;
+        self
+    }
+
+    pub fn unhealthy_threshold(&mut self, p: u32) -> &mut Self {
+        self.unhealthy_threshold = p.max(1);
+        self
+    }
+
+    pub fn healthy_threshold(&mut self, p: u32) -> &mut Self {
+        self.healthy_threshold = p.max(1);
+        self
+    }
+
+    // GET path of each backend by spawn_health_checks, which is healthy if it
+    // answers with 2xx
+    pub fn health_check(&mut self, path: &str) -> &mut Self {
+        self.health_path = Some(path.into());
+        self
+    }
+
+    pub fn health_interval(&mut self, p: Duration) -> &mut Self {
+        self.health_interval = p;
+        self
+    }
+
+    // of connecting, sending and reading a health check
+    pub fn health_timeout(&mut self, p: Duration) -> &mut Self {
+        self.health_timeout = p;
+        self
+    }
+
+    // send a second attempt of a safe request, e.g. GET, to another backend
+    // when the first hasn't answered within delay. the first response wins,
+    // and the other attempt is cancelled
+    // NOTE: only unix and tcp connections are shut down on cancel, others
+    // are read to the end
+    pub fn hedge(&mut self, delay: Option<Duration>) -> &mut Self {
+        self.hedge = delay;
+        self
+    }
+
+    pub fn is_healthy(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        self.backends
+            .iter()
+            .any(|b| b.url == url && b.health.lock().unwrap().healthy)
+    }
+
+    // lease the next backend by the strategy. the lease counts as a
+    // connection until dropped
+    pub fn pick(&self) -> Lease<'_> {
+        let healthy = self
+            .backends
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.health.lock().unwrap().healthy)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let candidates = if healthy.is_empty() {
+            (0..self.backends.len()).collect()
+        } else {
+            healthy
+        };
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let rotated = (0..candidates.len()).map(|i| candidates[(start + i) % candidates.len()]);
+        let index = match self.strategy {
+            Strategy::RoundRobin => candidates[start % candidates.len()],
+            Strategy::LeastConnections => rotated
+                .min_by_key(|&i| self.backends[i].active.load(Ordering::Relaxed))
+                .unwrap(),
+        };
+        self.backends[index].active.fetch_add(1, Ordering::Relaxed);
+        Lease {
+            balancer: self,
+            index,
+        }
+    }
+
+    // send req to a backend. backends which can't be connected are reported
+    // and the next one is tried, as the request was not sent yet
+    pub fn execute_request(&self, req: &mut Request) -> Result<Response, HttpError> {
+        if let Some(delay) = self.hedge.filter(|_| req.method.is_safe()) {
+            if let Some(mut hedge) = req.try_clone() {
+                return self.execute_hedged(req, &mut hedge, delay);
+            }
+        }
+        let mut last_err = None;
+        for _ in 0..self.backends.len() {
+            let lease = self.pick();
+            let mut client = match lease.connect() {
+                Ok(conn) => HttpClient::new(conn),
+                Err(e) => {
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            client.base_url(transport::base_url(lease.url()));
+            return match client.execute_request(req) {
+                Ok(resp) => {
+                    lease.success();
+                    Ok(resp)
+                }
+                Err(e) => {
+                    lease.failure();
+                    Err(e)
+                }
+            };
+        }
+        Err(last_err.unwrap())
+    }
+
+    fn execute_hedged(
+        &self,
+        req: &mut Request,
+        hedge: &mut Request,
+        delay: Duration,
+    ) -> Result<Response, HttpError> {
+        let cancels = [CancelToken::new(), CancelToken::new()];
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|s| {
+            let first = tx.clone();
+            let cancel = &cancels[0];
+            s.spawn(move || first.send((0, self.attempt(req, cancel))));
+            let mut pending = 1;
+            let (mut i, mut result) = match rx.recv_timeout(delay) {
+                Ok(done) => done,
+                Err(_) => {
+                    let cancel = &cancels[1];
+                    s.spawn(move || tx.send((1, self.attempt(hedge, cancel))));
+                    pending += 1;
+                    rx.recv().unwrap()
+                }
+            };
+            pending -= 1;
+            // an error waits for the other attempt
+            if result.is_err() && pending > 0 {
+                (i, result) = rx.recv().unwrap();
+            }
+            cancels[1 - i].cancel();
+            result
+        })
+    }
+
+    // send req to the next backend, on a connection shut down by cancel
+    fn attempt(&self, req: &mut Request, cancel: &CancelToken) -> Result<Response, HttpError> {
+        let lease = self.pick();
+        let mut client = HttpClient::new(lease.connect_cancellable(cancel)?);
+        client.base_url(transport::base_url(lease.url()));
+        let result = client.execute_request(req);
+        match &result {
+            Ok(_) => lease.success(),
+            // the loser
+            Err(_) if cancel.is_cancelled() => {}
+            Err(_) => lease.failure(),
+        }
+        result
+    }
+
+    // run the health check of every backend once
+    pub fn check(&self) {
+        let Some(path) = &self.health_path else {
+            return;
+        };
+        let timeouts = Timeouts {
+            connect: Some(self.health_timeout),
+            read: Some(self.health_timeout),
+            write: Some(self.health_timeout),
+        };
+        for backend in &self.backends {
+            let ok = transport::connect_with(&backend.url, &timeouts)
+                .and_then(|conn| {
+                    let mut client = HttpClient::new(conn);
+                    client.base_url(transport::base_url(&backend.url));
+                    client.execute_request(&mut Request::get(path))
+                })
+                .is_ok_and(|resp| resp.status.is_success());
+            self.report(backend, ok);
+        }
+    }
+
+    // check the backends every health_interval, until the balancer is dropped
+    pub fn spawn_health_checks(balancer: &Arc<Self>) -> thread::JoinHandle<()> {
+        let weak = Arc::downgrade(balancer);
+        thread::spawn(move || loop {
+            let interval = match weak.upgrade() {
+                Some(balancer) => {
+                    balancer.check();
+                    balancer.health_interval
+                }
+                None => return,
+            };
+            thread::sleep(interval);
+        })
+    }
+
+    fn report(&self, backend: &Backend, ok: bool) {
+        let mut health = backend.health.lock().unwrap();
+        if ok {
+            health.failures = 0;
+            health.successes += 1;
+            if health.successes >= self.healthy_threshold {
+                health.healthy = true;
+            }
+        } else {
+            health.successes = 0;
+            health.failures += 1;
+            if health.failures >= self.unhealthy_threshold {
+                health.healthy = false;
+            }
+        }
+    }
+}
+
+// a backend picked by Balancer::pick
+pub struct Lease<'a> {
+    balancer: &'a Balancer,
+    index: usize,
+}
+
+impl Lease<'_> {
+    pub fn url(&self) -> &Url {
+        &self.backend().url
+    }
+
+    // open a connection to the backend, reporting a failure
+    pub fn connect(&self) -> Result<Transport, HttpError> {
+        let conn = transport::connect_with(self.url(), &self.balancer.timeouts);
+        if conn.is_err() {
+            self.failure();
+        }
+        conn
+    }
+
+    fn connect_cancellable(&self, cancel: &CancelToken) -> Result<Transport, HttpError> {
+        let (url, timeouts) = (self.url(), &self.balancer.timeouts);
+        let conn = match url.scheme.as_deref() {
+            #[cfg(unix)]
+            Some("unix") | Some("unix-abstract") => {
+                transport::connect_unix(url, timeouts, &SocketOptions::default()).and_then(|c| {
+                    cancel.shutdown_on_cancel(c.try_clone()?);
+                    Ok(Box::new(c) as Transport)
+                })
+            }
+            Some("tcp") | Some("http") => transport::connect_tcp(url, timeouts).and_then(|c| {
+                cancel.shutdown_on_cancel(c.try_clone()?);
+                Ok(Box::new(c) as Transport)
+            }),
+            _ => transport::connect_with(url, timeouts),
+        };
+        if conn.is_err() {
+            self.failure();
+        }
+        conn
+    }
+
+    pub fn success(&self) {
+        self.balancer.report(self.backend(), true);
+    }
+
+    pub fn failure(&self) {
+        self.balancer.report(self.backend(), false);
+    }
+
+    fn backend(&self) -> &Backend {
+        &self.balancer.backends[self.index]
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        self.backend().active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::server::{HttpServer, ServerResponse};
+    use crate::status::StatusCode;
+
+    // answers its name, and 503 to /_ping unless up
+    fn backend(name: &'static str, up: Arc<Mutex<bool>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let server = HttpServer::new(listener);
+        thread::spawn(move || {
+            server.serve(move |req| {
+                let up = *up.lock().unwrap();
+                let mut resp = match req.path() {
+                    "/_ping" if !up => ServerResponse::new(StatusCode::SERVICE_UNAVAILABLE),
+                    _ => ServerResponse::new(StatusCode::OK),
+                };
+                resp.body(name);
+                resp
+            })
+        });
+        url
+    }
+
+    fn closed() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("tcp://{}", listener.local_addr().unwrap())
+    }
+
+    fn get(balancer: &Balancer) -> String {
+        let resp = balancer.execute_request(&mut Request::get("/")).unwrap();
+        resp.text().unwrap()
+    }
+
+    #[test]
+    fn balancer_round_robin() {
+        let up = Arc::new(Mutex::new(true));
+        let a = backend("a", up.clone());
+        let b = backend("b", up.clone());
+        let balancer = Balancer::new(&[&a, &b]).unwrap();
+        let got = (0..4).map(|_| get(&balancer)).collect::<Vec<_>>();
+        assert_eq!(got, ["a", "b", "a", "b"]);
+
+        // a backend which refuses connections is skipped, and ejected
+        let down = closed();
+        let balancer = Balancer::new(&[&a, &down]).unwrap();
+        let got = (0..4).map(|_| get(&balancer)).collect::<Vec<_>>();
+        assert_eq!(got, ["a", "a", "a", "a"]);
+        assert!(!balancer.is_healthy(&down));
+        assert!(balancer.is_healthy(&a));
+    }
+
+    #[test]
+    fn balancer_least_connections() {
+        let mut balancer = Balancer::new(&["tcp://127.0.0.1:1", "tcp://127.0.0.1:2"]).unwrap();
+        balancer.strategy(Strategy::LeastConnections);
+        let first = balancer.pick();
+        // the other backend until both are leased equally
+        let second = balancer.pick();
+        assert_ne!(first.url(), second.url());
+        drop(first);
+        let third = balancer.pick();
+        assert_ne!(third.url(), second.url());
+        let fourth = balancer.pick();
+        let fifth = balancer.pick();
+        assert_ne!(fourth.url(), fifth.url());
+    }
+
+    #[test]
+    fn balancer_health_check() {
+        let a_up = Arc::new(Mutex::new(true));
+        let b_up = Arc::new(Mutex::new(false));
+        let a = backend("a", a_up.clone());
+        let b = backend("b", b_up.clone());
+        let mut balancer = Balancer::new(&[&a, &b]).unwrap();
+        balancer
+            .health_check("/_ping")
+            .unhealthy_threshold(2)
+            .healthy_threshold(2);
+
+        balancer.check();
+        assert!(balancer.is_healthy(&b));
+        balancer.check();
+        assert!(!balancer.is_healthy(&b));
+        let got = (0..3).map(|_| get(&balancer)).collect::<Vec<_>>();
+        assert_eq!(got, ["a", "a", "a"]);
+
+        // re-added after recovering
+        *b_up.lock().unwrap() = true;
+        balancer.check();
+        assert!(!balancer.is_healthy(&b));
+        balancer.check();
+        assert!(balancer.is_healthy(&b));
+        let got = (0..2).map(|_| get(&balancer)).collect::<Vec<_>>();
+        assert!(got.contains(&"b".to_string()));
+
+        // all ejected, both are still tried
+        *a_up.lock().unwrap() = false;
+        *b_up.lock().unwrap() = false;
+        balancer.check();
+        balancer.check();
+        assert!(!balancer.is_healthy(&a) && !balancer.is_healthy(&b));
+        let got = (0..2).map(|_| get(&balancer)).collect::<Vec<_>>();
+        assert!(got.contains(&"a".to_string()) && got.contains(&"b".to_string()));
+    }
+
+    #[test]
+    fn balancer_hedge() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow = format!("tcp://{}", listener.local_addr().unwrap());
+        let server = HttpServer::new(listener);
+        thread::spawn(move || {
+            server.serve(|_| {
+                thread::sleep(Duration::from_secs(3));
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.body("slow");
+                resp
+            })
+        });
+        let fast = backend("fast", Arc::new(Mutex::new(true)));
+        let mut balancer = Balancer::new(&[&slow, &fast]).unwrap();
+        balancer
+            .hedge(Some(Duration::from_millis(50)))
+            .unhealthy_threshold(1);
+
+        let start = std::time::Instant::now();
+        assert_eq!(get(&balancer), "fast");
+        // the slow attempt was cancelled, and is not a failure of its backend
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(balancer.is_healthy(&slow));
+
+        // a request with a streamed body is sent once
+        let mut req = Request::get("/");
+        req.body_reader(std::io::Cursor::new(b"x".to_vec()));
+        assert!(req.try_clone().is_none());
+    }
+}
@@ -0,0 +1,434 @@
+use std::cell::{Cell, RefCell};
+use std::fmt::Debug;
+use std::io::{self, BufRead, Read};
+use std::rc::Rc;
+
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::{proto, HttpHeader};
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// response body which is read from the connection as it is consumed. reading
+// it to the end makes the connection reusable, dropping it early closes it
+pub struct Body<'a> {
+    r: Box<dyn Read + 'a>,
+    // set by ChunkedReader at the end of each transfer chunk
+    chunk_end: Option<Rc<Cell<bool>>>,
+    // set by ChunkedReader at the end of the body
+    trailers: Option<Rc<RefCell<HttpHeader>>>,
+}
+
+impl<'a> Body<'a> {
+    pub fn new(r: impl Read + 'a) -> Self {
+        Self {
+            r: Box::new(r),
+            chunk_end: None,
+            trailers: None,
+        }
+    }
+
+    pub fn with_chunk_end(mut self, p: Rc<Cell<bool>>) -> Self {
+        self.chunk_end = Some(p);
+        self
+    }
+
+    pub fn with_trailers(mut self, p: Rc<RefCell<HttpHeader>>) -> Self {
+        self.trailers = Some(p);
+        self
+    }
+
+    // the trailer fields after a chunked body. empty until the body is read to
+    // the end
+    pub fn trailers(&self) -> HttpHeader {
+        self.trailers
+            .as_ref()
+            .map(|t| t.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    // iterate over transfer chunks of a chunked body. other bodies are read in
+    // pieces of up to CHUNK_SIZE bytes
+    pub fn chunks(self) -> Chunks<'a> {
+        Chunks {
+            body: self,
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(io::empty())
+    }
+
+    // read the whole body into memory
+    pub fn bytes(mut self) -> Result<Vec<u8>, HttpError> {
+        let mut body = Vec::new();
+        self.r.read_to_end(&mut body)?;
+        Ok(body)
+    }
+}
+
+impl Read for Body<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.r.read(buf)
+    }
+}
+
+impl Debug for Body<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Body").finish_non_exhaustive()
+    }
+}
+
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+pub struct Chunks<'a> {
+    body: Body<'a>,
+    // read buffer shared by all chunks
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl Chunks<'_> {
+    fn next_chunk(&mut self) -> io::Result<Vec<u8>> {
+        self.buf.resize(CHUNK_SIZE, 0);
+        let Some(chunk_end) = self.body.chunk_end.clone() else {
+            let n = self.body.read(&mut self.buf)?;
+            return Ok(self.buf[..n].to_vec());
+        };
+
+        chunk_end.set(false);
+        let mut chunk = Vec::new();
+        while !chunk_end.get() {
+            let n = self.body.read(&mut self.buf)?;
+            if n == 0 {
+                break;
+            }
+            chunk.extend_from_slice(&self.buf[..n]);
+        }
+        Ok(chunk)
+    }
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = Result<Vec<u8>, HttpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_chunk() {
+            Ok(chunk) if chunk.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+// body delimited by Content-Length
+pub struct LengthReader<R> {
+    r: R,
+    remaining: u64,
+}
+
+impl<R: Read> LengthReader<R> {
+    pub fn new(r: R, length: u64) -> Self {
+        Self {
+            r,
+            remaining: length,
+        }
+    }
+}
+
+impl<R: Read> Read for LengthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.r.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "body is {} bytes shorter than content-length",
+                    self.remaining
+                ),
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+// body with chunked transfer coding. chunk extensions are ignored
+pub struct ChunkedReader<R> {
+    r: R,
+    // reused for the size line of every chunk
+    line: Vec<u8>,
+    remaining: u64,
+    done: bool,
+    chunk_end: Rc<Cell<bool>>,
+    trailers: Rc<RefCell<HttpHeader>>,
+    limits: Limits,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(r: R) -> Self {
+        Self {
+            r,
+            line: Vec::new(),
+            remaining: 0,
+            done: false,
+            chunk_end: Rc::new(Cell::new(false)),
+            trailers: Rc::default(),
+            limits: Limits::default(),
+        }
+    }
+
+    // max_header_line bounds the size lines and trailers, max_headers the
+    // number of trailers
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // becomes true when the current chunk has been read, see Body::chunks
+    pub fn chunk_end(&self) -> Rc<Cell<bool>> {
+        self.chunk_end.clone()
+    }
+
+    // filled after the last chunk, see Body::trailers
+    pub fn trailers(&self) -> Rc<RefCell<HttpHeader>> {
+        self.trailers.clone()
+    }
+
+    fn read_line(&mut self) -> io::Result<&[u8]> {
+        if self.read_bounded_line()? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected endof",
+            ));
+        }
+        Ok(&self.line)
+    }
+
+    fn read_bounded_line(&mut self) -> io::Result<usize> {
+        let max = self.limits.max_header_line;
+        self.line.clear();
+        let readed = (&mut self.r)
+            .take(max as u64 + 1)
+            .read_until(b'\n', &mut self.line)?;
+        if readed > max {
+            return Err(io::Error::other(HttpError::HeaderLineTooLong(max)));
+        }
+        Ok(readed)
+    }
+
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line()?;
+        proto::parse_chunk_size(line)
+    }
+
+    // read trailers until the empty line
+    fn read_trailers(&mut self) -> io::Result<()> {
+        loop {
+            let readed = self.read_bounded_line()?;
+            // a missing end of trailers is tolerated
+            if readed == 0 || self.line == b"\r\n" || self.line == b"\n" {
+                return Ok(());
+            }
+            let mut trailers = self.trailers.borrow_mut();
+            match proto::append_trailer(&mut trailers, &self.line, &self.limits) {
+                Ok(()) => {}
+                Err(HttpError::Parse(msg)) => return Err(invalid_data(msg)),
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.remaining = self.read_chunk_size()?;
+            if let Some(max) = self
+                .limits
+                .max_chunk_size
+                .filter(|max| self.remaining > *max)
+            {
+                return Err(io::Error::other(HttpError::ChunkTooLarge(max)));
+            }
+            if self.remaining == 0 {
+                self.read_trailers()?;
+                self.done = true;
+                self.chunk_end.set(true);
+                return Ok(0);
+            }
+        }
+
+        let max = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.r.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected endof",
+            ));
+        }
+        self.remaining -= n as u64;
+
+        // consume \r\n
+        if self.remaining == 0 {
+            let line = self.read_line()?;
+            if line != b"\r\n" && line != b"\n" {
+                return Err(invalid_data("invalid chunk terminator".into()));
+            }
+            self.chunk_end.set(true);
+        }
+        Ok(n)
+    }
+}
+
+// sets the flag to value when r reached the end without an error
+pub struct OnEof<'a, R> {
+    r: R,
+    flag: &'a mut bool,
+    value: bool,
+}
+
+impl<'a, R: Read> OnEof<'a, R> {
+    pub fn new(r: R, flag: &'a mut bool, value: bool) -> Self {
+        Self { r, flag, value }
+    }
+}
+
+impl<R: Read> Read for OnEof<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.r.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            *self.flag = self.value;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn chunked_reader() {
+        let input = "5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\nnext";
+        let mut r = Cursor::new(input.as_bytes());
+        let mut body = Vec::new();
+        let mut chunked = ChunkedReader::new(&mut r);
+        chunked.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"hello world");
+        assert_eq!(chunked.trailers().borrow().get("trailer").unwrap(), "x");
+        assert_eq!(r.position() as usize, input.len() - 4);
+
+        let mut r = ChunkedReader::new(Cursor::new(b"0\r\ninvalid\r\n\r\n".to_vec()));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut r = ChunkedReader::new(Cursor::new(b"5\r\nhel".to_vec()));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut r = ChunkedReader::new(Cursor::new(b"5\r\nhelloXX".to_vec()));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunked_reader_limits() {
+        let limits = Limits {
+            max_headers: 2,
+            max_header_line: 16,
+            ..Limits::default()
+        };
+        let read = |input: Vec<u8>| {
+            let mut r = ChunkedReader::new(Cursor::new(input));
+            r.limits(limits);
+            r.read_to_end(&mut Vec::new()).map_err(HttpError::from)
+        };
+
+        let size = [b"5".repeat(17), b"\r\nhello".to_vec()].concat();
+        assert!(matches!(read(size), Err(HttpError::HeaderLineTooLong(16))));
+        let trailer = [
+            b"0\r\nA: ".to_vec(),
+            b"x".repeat(1024),
+            b"\r\n\r\n".to_vec(),
+        ]
+        .concat();
+        assert!(matches!(
+            read(trailer),
+            Err(HttpError::HeaderLineTooLong(16))
+        ));
+        let trailers = b"0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n".to_vec();
+        assert!(matches!(read(trailers), Err(HttpError::TooManyHeaders(2))));
+        let trailers = b"0\r\nA: 1\r\nB: 2\r\n\r\n".to_vec();
+        assert!(read(trailers).is_ok());
+    }
+
+    #[test]
+    fn body_chunks() {
+        // chunks are not merged even if they are read at once
+        let input = "5\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\n\r\n";
+        let r = ChunkedReader::new(Cursor::new(input.as_bytes()));
+        let chunk_end = r.chunk_end();
+        let chunks = Body::new(r)
+            .with_chunk_end(chunk_end)
+            .chunks()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            chunks,
+            [b"hello".to_vec(), b" ".to_vec(), b"world".to_vec()]
+        );
+
+        let data = vec![1u8; CHUNK_SIZE + 1];
+        let r = LengthReader::new(Cursor::new(data), CHUNK_SIZE as u64 + 1);
+        let sizes = Body::new(r)
+            .chunks()
+            .map(|chunk| chunk.unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [CHUNK_SIZE, 1]);
+
+        let r = ChunkedReader::new(Cursor::new(b"5\r\nhel".to_vec()));
+        let mut chunks = Body::new(r).chunks();
+        assert_eq!(chunks.next().unwrap().unwrap(), b"hel");
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn length_reader_on_eof() {
+        let mut done = false;
+        let r = LengthReader::new(Cursor::new(b"hello world".to_vec()), 5);
+        let body = Body::new(OnEof::new(r, &mut done, true)).bytes().unwrap();
+        assert_eq!(body, b"hello");
+        assert!(done);
+
+        let r = LengthReader::new(Cursor::new(b"hel".to_vec()), 5);
+        assert!(Body::new(r).bytes().is_err());
+    }
+}
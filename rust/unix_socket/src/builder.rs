@@ -0,0 +1,205 @@
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::bytes::Bytes;
+use crate::cancel::CancelToken;
+use crate::error::HttpError;
+use crate::multipart::Multipart;
+use crate::{HttpHeader, HttpMethod, HttpParams, Request};
+
+// the method of the request may have a body, e.g. POST
+pub struct WithBody;
+// the method of the request has no body, e.g. GET
+pub struct NoBody;
+
+// an owned builder of Request, e.g.
+//
+//     let req = RequestBuilder::post("/containers/create")
+//         .header("X-Registry-Auth", token)
+//         .body(config)
+//         .build()?;
+//
+// the body setters only exist for WithBody, so `RequestBuilder::get(url).body(..)`
+// doesn't compile. errors of the url or the body are returned by build
+pub struct RequestBuilder<B> {
+    req: Request,
+    _body: PhantomData<B>,
+}
+
+impl RequestBuilder<NoBody> {
+    pub fn get(url: &str) -> Self {
+        Self::new(HttpMethod::Get, url)
+    }
+
+    pub fn head(url: &str) -> Self {
+        Self::new(HttpMethod::Head, url)
+    }
+
+    pub fn delete(url: &str) -> Self {
+        Self::new(HttpMethod::Delete, url)
+    }
+
+    pub fn options(url: &str) -> Self {
+        Self::new(HttpMethod::Options, url)
+    }
+}
+
+impl RequestBuilder<WithBody> {
+    pub fn post(url: &str) -> Self {
+        Self::new(HttpMethod::Post, url)
+    }
+
+    pub fn put(url: &str) -> Self {
+        Self::new(HttpMethod::Put, url)
+    }
+
+    pub fn patch(url: &str) -> Self {
+        Self::new(HttpMethod::Patch, url)
+    }
+
+    pub fn body(mut self, p: impl Into<Bytes>) -> Self {
+        self.req.body(p);
+        self
+    }
+
+    pub fn body_reader(mut self, p: impl Read + Send + 'static) -> Self {
+        self.req.body_reader(p);
+        self
+    }
+
+    pub fn body_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.req.body_file(path);
+        self
+    }
+
+    pub fn body_factory<R: Read + Send + 'static>(
+        mut self,
+        f: impl FnMut() -> std::io::Result<R> + Send + 'static,
+    ) -> Self {
+        self.req.body_factory(f);
+        self
+    }
+
+    pub fn form(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.req.form(pairs);
+        self
+    }
+
+    pub fn multipart(mut self, p: Multipart) -> Self {
+        self.req.multipart(p);
+        self
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Self {
+        self.req.json(value);
+        self
+    }
+}
+
+impl<B> RequestBuilder<B> {
+    fn new(method: HttpMethod, url: &str) -> Self {
+        let mut req = Request::new(url);
+        req.method(method);
+        Self {
+            req,
+            _body: PhantomData,
+        }
+    }
+
+    // add a value of key, existing ones are kept
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.req
+            .header
+            .get_or_insert_with(HttpHeader::new)
+            .append(key, value);
+        self
+    }
+
+    // add a query parameter, existing ones of key are kept
+    pub fn param(mut self, key: &str, value: &str) -> Self {
+        self.req
+            .params
+            .get_or_insert_with(HttpParams::new)
+            .append(key, value);
+        self
+    }
+
+    pub fn basic_auth(mut self, user: &str, pass: Option<&str>) -> Self {
+        self.req.basic_auth(user, pass);
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.req.bearer_auth(token);
+        self
+    }
+
+    pub fn timeout(mut self, p: Duration) -> Self {
+        self.req.timeout(p);
+        self
+    }
+
+    pub fn retryable(mut self, p: bool) -> Self {
+        self.req.retryable(p);
+        self
+    }
+
+    pub fn cancel_token(mut self, p: CancelToken) -> Self {
+        self.req.cancel_token(p);
+        self
+    }
+
+    pub fn build(self) -> Result<Request, HttpError> {
+        self.req.validate()?;
+        Ok(self.req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builder_get() {
+        let mut req = RequestBuilder::get("http://example.com/items")
+            .header("Accept", "text/plain")
+            .param("tag", "a")
+            .param("tag", "b")
+            .build()
+            .unwrap();
+        let want = [
+            "GET /items?tag=a&tag=b HTTP/1.1",
+            "Host: example.com",
+            "Accept: text/plain",
+            "",
+            "",
+        ]
+        .join("\r\n");
+        assert_eq!(String::from_utf8(req.build().unwrap()).unwrap(), want);
+    }
+
+    #[test]
+    fn builder_post() {
+        let mut req = RequestBuilder::post("/containers/create")
+            .form(&[("name", "web app")])
+            .build()
+            .unwrap();
+        let got = String::from_utf8(req.build().unwrap()).unwrap();
+        assert!(got.starts_with("POST /containers/create HTTP/1.1\r\n"));
+        assert!(got.ends_with("\r\n\r\nname=web%20app"));
+    }
+
+    #[test]
+    fn builder_invalid() {
+        let err = RequestBuilder::put("/upload")
+            .body_file("/nonexistent/file")
+            .build();
+        assert!(err.is_err());
+        assert!(RequestBuilder::get("unix://relative.sock:/_ping")
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::status::StatusCode;
+use crate::{headers, HttpHeader, HttpMethod, Request, Response};
+
+const CACHE_CONTROL: &str = "Cache-Control";
+const EXPIRES: &str = "Expires";
+const AGE: &str = "Age";
+const VARY: &str = "Vary";
+
+// the headers of a 304 which must not replace the ones of the cached response
+const NOT_UPDATED: [&str; 3] = [
+    headers::CONTENT_LENGTH,
+    headers::CONTENT_ENCODING,
+    headers::TRANSFER_ENCODING,
+];
+
+// where HttpCache keeps the responses, keyed by the url of the request
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+    fn put(&self, key: &str, entry: CacheEntry);
+    fn remove(&self, key: &str);
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub response: Response,
+    // after this the response is revalidated before it is used
+    pub fresh_until: SystemTime,
+    // the request headers named by Vary, which must match to use the response
+    pub vary: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().ok()?.get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key.into(), entry);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(key);
+        }
+    }
+}
+
+// caches the responses of GET requests by Cache-Control and Expires (RFC 9111).
+// a fresh response is answered without sending the request, a stale one is
+// revalidated with its ETag or Last-Modified and a 304 is answered with the
+// cached response:
+//
+//     client.middleware(HttpCache::new());
+//
+// a successful unsafe request, e.g. POST, drops the response of its url.
+// NOTE: this is a private cache, responses with Authorization or
+// `Cache-Control: private` are stored too. there is no heuristic freshness,
+// a response without max-age or Expires is always revalidated
+pub struct HttpCache {
+    store: Box<dyn CacheStore>,
+}
+
+impl Default for HttpCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpCache {
+    pub fn new() -> Self {
+        Self::with_store(Box::new(MemoryStore::default()))
+    }
+
+    pub fn with_store(store: Box<dyn CacheStore>) -> Self {
+        Self { store }
+    }
+
+    fn lookup(&self, key: &str, req: &Request) -> Option<CacheEntry> {
+        let entry = self.store.get(key)?;
+        let matched = entry
+            .vary
+            .iter()
+            .all(|(name, value)| request_header(req, name) == value.as_deref());
+        matched.then_some(entry)
+    }
+
+    // store resp when it may be used again, otherwise drop the old response
+    fn store(&self, key: &str, req: &Request, resp: &Response, now: SystemTime) {
+        let cc = CacheControl::parse(&resp.header);
+        let cacheable = matches!(
+            resp.status,
+            StatusCode::OK | StatusCode::NO_CONTENT | StatusCode::NOT_FOUND
+        );
+        let vary = resp.header.get_all(VARY).flat_map(|v| v.split(','));
+        let vary = vary
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        if !cacheable || cc.no_store || vary.iter().any(|name| name == "*") {
+            self.store.remove(key);
+            return;
+        }
+        let vary = vary
+            .into_iter()
+            .map(|name| {
+                let value = request_header(req, &name).map(String::from);
+                (name, value)
+            })
+            .collect();
+        let entry = CacheEntry {
+            response: resp.clone(),
+            fresh_until: fresh_until(&resp.header, &cc, now),
+            vary,
+        };
+        self.store.put(key, entry);
+    }
+}
+
+// the url with the params of req, e.g. `http://localhost/containers/json?all=1`
+fn cache_key(req: &Request) -> String {
+    let target = req.target().unwrap_or_else(|_| req.url.request_target());
+    match (&req.url.scheme, req.url.authority()) {
+        (Some(scheme), Some(authority)) => format!("{}://{}{}", scheme, authority, target),
+        _ => target,
+    }
+}
+
+impl Middleware for HttpCache {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let key = cache_key(req);
+        if req.method != HttpMethod::Get {
+            let resp = next.run(req)?;
+            if !req.method.is_safe() && resp.status.is_success() {
+                self.store.remove(&key);
+            }
+            return Ok(resp);
+        }
+        // the caller revalidates by itself
+        let conditional =
+            req.has_header(headers::IF_NONE_MATCH) || req.has_header(headers::IF_MODIFIED_SINCE);
+        let request_cc = req
+            .header
+            .as_ref()
+            .map(CacheControl::parse)
+            .unwrap_or_default();
+        if conditional || request_cc.no_store {
+            return next.run(req);
+        }
+
+        let now = SystemTime::now();
+        let cached = self.lookup(&key, req);
+        if let Some(entry) = &cached {
+            if !request_cc.no_cache && now < entry.fresh_until {
+                return Ok(entry.response.clone());
+            }
+        }
+
+        let validators = cached.as_ref().map(|entry| {
+            let header = &entry.response.header;
+            (
+                header.get(headers::ETAG).cloned(),
+                header.get(headers::LAST_MODIFIED).cloned(),
+            )
+        });
+        let header = req.header.get_or_insert_with(HttpHeader::new);
+        if let Some((etag, last_modified)) = &validators {
+            if let Some(etag) = etag {
+                header.add(headers::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                header.add(headers::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = next.run(req);
+        // the request may be sent again by the caller
+        if let Some(header) = req.header.as_mut() {
+            header.remove(headers::IF_NONE_MATCH);
+            header.remove(headers::IF_MODIFIED_SINCE);
+        }
+        let resp = resp?;
+
+        match cached {
+            Some(mut entry) if resp.status == StatusCode::NOT_MODIFIED => {
+                for (k, v) in resp.header.iter() {
+                    if !NOT_UPDATED.iter().any(|h| k.eq_ignore_ascii_case(h)) {
+                        entry.response.header.add(k, v);
+                    }
+                }
+                let cc = CacheControl::parse(&entry.response.header);
+                entry.fresh_until = fresh_until(&entry.response.header, &cc, now);
+                self.store.put(&key, entry.clone());
+                Ok(entry.response)
+            }
+            _ => {
+                self.store(&key, req, &resp, now);
+                Ok(resp)
+            }
+        }
+    }
+}
+
+fn request_header<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.header
+        .as_ref()
+        .and_then(|h| h.get(name))
+        .map(|v| v.as_str())
+}
+
+// the largest delta-seconds, RFC 9111 section 1.2.2
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+// the time until resp is fresh, by max-age or Expires minus its age
+fn fresh_until(header: &HttpHeader, cc: &CacheControl, now: SystemTime) -> SystemTime {
+    if cc.no_cache {
+        return now;
+    }
+    let lifetime = match cc.max_age {
+        Some(secs) => Duration::from_secs(secs),
+        None => {
+            let expires = header.get(EXPIRES).and_then(|s| headers::parse_date(s));
+            let date = header
+                .get(headers::DATE)
+                .and_then(|s| headers::parse_date(s))
+                .unwrap_or(now);
+            // an invalid Expires like `0` means already expired
+            expires
+                .and_then(|t| t.duration_since(date).ok())
+                .unwrap_or_default()
+        }
+    };
+    let age = header
+        .get(AGE)
+        .and_then(|s| s.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+    // stale rather than panic for an Expires beyond what SystemTime holds
+    now.checked_add(lifetime.saturating_sub(age)).unwrap_or(now)
+}
+
+// the directives of Cache-Control this cache knows
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn parse(header: &HttpHeader) -> Self {
+        let mut cc = Self::default();
+        for directive in header.get_all(CACHE_CONTROL).flat_map(|v| v.split(',')) {
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            let value = value.trim().trim_matches('"');
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" => cc.no_store = true,
+                // `no-cache="Set-Cookie"` only restricts the named headers,
+                // it is treated as no-cache to keep it simple
+                "no-cache" => cc.no_cache = true,
+                "max-age" => {
+                    let digits = !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+                    cc.max_age = Some(match value.parse::<u64>() {
+                        Ok(secs) => secs.min(MAX_DELTA_SECONDS),
+                        Err(_) if digits => MAX_DELTA_SECONDS,
+                        Err(_) => 0,
+                    });
+                }
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockTransport, ResponseBuilder};
+    use crate::url::Url;
+    use crate::{HttpClient, HttpParams};
+
+    fn sent(client: &HttpClient<MockTransport>) -> Vec<String> {
+        client
+            .conn
+            .get_ref()
+            .requests()
+            .into_iter()
+            .map(|r| String::from_utf8(r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn cache_control_parse() {
+        let header: HttpHeader = [
+            ("Cache-Control", "public, Max-Age=\"60\""),
+            ("Cache-Control", "no-cache"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            CacheControl::parse(&header),
+            CacheControl {
+                no_store: false,
+                no_cache: true,
+                max_age: Some(60),
+            }
+        );
+    }
+
+    #[test]
+    fn cache_fresh_until() {
+        let now = SystemTime::now();
+        let header: HttpHeader = [("Cache-Control", "max-age=60"), ("Age", "10")]
+            .into_iter()
+            .collect();
+        let cc = CacheControl::parse(&header);
+        assert_eq!(
+            fresh_until(&header, &cc, now),
+            now + Duration::from_secs(50)
+        );
+
+        let header: HttpHeader = [
+            ("Date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("Expires", "Wed, 21 Oct 2015 08:28:00 GMT"),
+        ]
+        .into_iter()
+        .collect();
+        let cc = CacheControl::parse(&header);
+        assert_eq!(
+            fresh_until(&header, &cc, now),
+            now + Duration::from_secs(3600)
+        );
+
+        let header: HttpHeader = [("Expires", "0")].into_iter().collect();
+        assert_eq!(fresh_until(&header, &CacheControl::default(), now), now);
+
+        for max_age in [
+            "max-age=18446744073709551615",
+            "max-age=99999999999999999999",
+        ] {
+            let header: HttpHeader = [("Cache-Control", max_age)].into_iter().collect();
+            let cc = CacheControl::parse(&header);
+            assert_eq!(cc.max_age, Some(MAX_DELTA_SECONDS));
+            assert_eq!(
+                fresh_until(&header, &cc, now),
+                now + Duration::from_secs(MAX_DELTA_SECONDS)
+            );
+        }
+    }
+
+    #[test]
+    fn cache_fresh_and_revalidate() {
+        let mut conn = MockTransport::new();
+        conn.push(
+            ResponseBuilder::new(StatusCode::OK)
+                .header("Cache-Control", "max-age=3600")
+                .body("fresh")
+                .build(),
+        )
+        .push(
+            ResponseBuilder::new(StatusCode::OK)
+                .header("Cache-Control", "no-cache")
+                .header("ETag", "\"v1\"")
+                .body("stale")
+                .build(),
+        )
+        .push(
+            ResponseBuilder::new(StatusCode::NOT_MODIFIED)
+                .header("ETag", "\"v1\"")
+                .header("X-Served", "revalidated")
+                .build(),
+        )
+        .push(ResponseBuilder::new(StatusCode::CREATED).build())
+        .push(ResponseBuilder::new(StatusCode::OK).body("new").build());
+        let mut client = HttpClient::new(conn);
+        client.middleware(HttpCache::new());
+
+        for _ in 0..2 {
+            let resp = client.execute_request(&mut Request::get("/a")).unwrap();
+            assert_eq!(resp.text().unwrap(), "fresh");
+        }
+        assert_eq!(sent(&client).len(), 1);
+
+        let resp = client.execute_request(&mut Request::get("/b")).unwrap();
+        assert_eq!(resp.text().unwrap(), "stale");
+        let mut req = Request::get("/b");
+        let resp = client.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        assert_eq!(resp.text().unwrap(), "stale");
+        assert_eq!(resp.header.get("x-served").unwrap(), "revalidated");
+        assert!(!req.has_header(headers::IF_NONE_MATCH));
+
+        let mut req = Request::get("/b");
+        req.method(HttpMethod::Post);
+        client.execute_request(&mut req).unwrap();
+        let resp = client.execute_request(&mut Request::get("/b")).unwrap();
+        assert_eq!(resp.text().unwrap(), "new");
+
+        let sent = sent(&client);
+        assert_eq!(sent.len(), 5);
+        assert!(sent[2].contains("\r\nIf-None-Match: \"v1\"\r\n"));
+        assert!(!sent[4].contains("If-None-Match"));
+    }
+
+    #[test]
+    fn cache_vary_and_no_store() {
+        let mut conn = MockTransport::new();
+        let mut vary = ResponseBuilder::new(StatusCode::OK);
+        vary.header("Cache-Control", "max-age=60")
+            .header("Vary", "Accept");
+        conn.push(vary.body("json").build())
+            .push(vary.body("text").build())
+            .push(
+                ResponseBuilder::new(StatusCode::OK)
+                    .header("Cache-Control", "no-store")
+                    .build(),
+            )
+            .push(ResponseBuilder::new(StatusCode::OK).build());
+        let mut client = HttpClient::new(conn);
+        client.middleware(HttpCache::new());
+
+        let get = |accept: &str| {
+            let mut req = Request::get("/a");
+            req.header([("Accept", accept)].into_iter().collect());
+            req
+        };
+        let resp = client
+            .execute_request(&mut get("application/json"))
+            .unwrap();
+        assert_eq!(resp.text().unwrap(), "json");
+        let resp = client.execute_request(&mut get("text/plain")).unwrap();
+        assert_eq!(resp.text().unwrap(), "text");
+        let resp = client.execute_request(&mut get("text/plain")).unwrap();
+        assert_eq!(resp.text().unwrap(), "text");
+        assert_eq!(sent(&client).len(), 2);
+
+        client.execute_request(&mut Request::get("/c")).unwrap();
+        client.execute_request(&mut Request::get("/c")).unwrap();
+        assert_eq!(sent(&client).len(), 4);
+    }
+
+    #[test]
+    fn cache_key_params() {
+        let mut conn = MockTransport::new();
+        let mut fresh = ResponseBuilder::new(StatusCode::OK);
+        fresh.header("Cache-Control", "max-age=60");
+        conn.push(fresh.body("running").build())
+            .push(fresh.body("all").build());
+        let mut client = HttpClient::new(conn);
+        client
+            .base_url(Url::parse("http://localhost").unwrap())
+            .middleware(HttpCache::new());
+
+        let get = |all: bool| {
+            let mut req = Request::get("/containers/json");
+            if all {
+                let mut params = HttpParams::new();
+                params.add("all", "1");
+                req.params(params);
+            }
+            req
+        };
+        for _ in 0..2 {
+            let resp = client.execute_request(&mut get(false)).unwrap();
+            assert_eq!(resp.text().unwrap(), "running");
+            let resp = client.execute_request(&mut get(true)).unwrap();
+            assert_eq!(resp.text().unwrap(), "all");
+        }
+        assert_eq!(sent(&client).len(), 2);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::net::{self, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// a connection which can be shut down from another thread, unblocking its
+// reads and writes
+pub trait Shutdown: Send {
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Shutdown for TcpStream {
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Shutdown for UnixStream {
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+// aborts requests from another thread, e.g. to stop following `/events`:
+//
+//     let conn = UnixStream::connect(path)?;
+//     let token = CancelToken::new();
+//     token.shutdown_on_cancel(conn.try_clone()?);
+//     let mut client = HttpClient::new(conn);
+//     req.cancel_token(token.clone());
+//     thread::spawn(move || token.cancel());
+//
+// a request with a cancelled token fails with HttpError::Cancelled
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    conns: Mutex<Vec<Box<dyn Shutdown>>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // shut conn down when the token is cancelled. usually a clone of the
+    // connection of the client, e.g. by TcpStream::try_clone
+    pub fn shutdown_on_cancel(&self, conn: impl Shutdown + 'static) {
+        if self.is_cancelled() {
+            let _ = conn.shutdown();
+            return;
+        }
+        self.0.conns.lock().unwrap().push(Box::new(conn));
+    }
+
+    // NOTE: the connections are shut down, so their clients can't be used anymore
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for conn in self.0.conns.lock().unwrap().drain(..) {
+            let _ = conn.shutdown();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn cancel_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conn = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (_server, _) = listener.accept().unwrap();
+
+        let token = CancelToken::new();
+        token.shutdown_on_cancel(conn.try_clone().unwrap());
+        assert!(!token.is_cancelled());
+        let cancel = token.clone();
+        std::thread::spawn(move || cancel.cancel());
+        // the blocked read returns when the token is cancelled
+        assert_eq!(conn.read(&mut [0u8; 1]).unwrap(), 0);
+        assert!(token.is_cancelled());
+    }
+}
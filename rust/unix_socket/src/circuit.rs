@@ -0,0 +1,278 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::pool::pool_key;
+use crate::status::StatusCode;
+use crate::url::Url;
+use crate::{Request, Response};
+
+enum State {
+    Closed,
+    Open { until: Instant },
+    // trials sent, and the ones which succeeded
+    HalfOpen { trials: u32, successes: u32 },
+}
+
+struct Circuit {
+    state: State,
+    // the latest outcomes while closed, true for a failure
+    outcomes: VecDeque<bool>,
+}
+
+// fails requests fast while their destination is failing, instead of waiting
+// for a wedged daemon until the timeout:
+//
+//     let mut breaker = CircuitBreaker::new();
+//     breaker.failure_rate(0.5).open_duration(Duration::from_secs(10));
+//     client.middleware(breaker);
+//
+// the circuit of a destination opens when at least failure_rate of the latest
+// window requests failed, and requests fail with HttpError::CircuitOpen. after
+// open_duration, half_open_requests trial requests are sent. the circuit closes
+// if all of them succeed, and opens again if one fails. a failure is an error
+// or a response with one of failure_statuses, 502, 503 and 504 by default
+// NOTE: the circuits are kept by destination, so a breaker can be shared by
+// clients, e.g. `client.middleware(move |req, next| breaker.handle(req, next))`
+// with an Arc<CircuitBreaker>
+pub struct CircuitBreaker {
+    failure_rate: f64,
+    window: usize,
+    min_requests: usize,
+    open_duration: Duration,
+    half_open_requests: u32,
+    failure_statuses: Vec<StatusCode>,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_rate: 0.5,
+            window: 20,
+            min_requests: 10,
+            open_duration: Duration::from_secs(30),
+            half_open_requests: 1,
+            failure_statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // ratio of failures in the window which opens the circuit, 0.0 to 1.0
+    pub fn failure_rate(&mut self, p: f64) -> &mut Self {
+        self.failure_rate = p.clamp(0.0, 1.0);
+        self
+    }
+
+    // number of the latest outcomes the failure rate is taken from
+    pub fn window(&mut self, p: usize) -> &mut Self {
+        self.window = p.max(1);
+        self
+    }
+
+    // outcomes needed in the window before the circuit can open
+    pub fn min_requests(&mut self, p: usize) -> &mut Self {
+        self.min_requests = p.max(1);
+        self
+    }
+
+    pub fn open_duration(&mut self, p: Duration) -> &mut Self {
+        self.open_duration = p;
+        self
+    }
+
+    pub fn half_open_requests(&mut self, p: u32) -> &mut Self {
+        self.half_open_requests = p.max(1);
+        self
+    }
+
+    pub fn failure_statuses(&mut self, p: &[StatusCode]) -> &mut Self {
+        self.failure_statuses = p.to_vec();
+        self
+    }
+
+    // whether requests to the destination of url are rejected now
+    pub fn is_open(&self, url: &Url) -> bool {
+        let key = pool_key(url).unwrap_or_default();
+        let circuits = self.circuits.lock().unwrap();
+        match circuits.get(&key).map(|c| &c.state) {
+            Some(State::Open { until }) => Instant::now() < *until,
+            Some(State::HalfOpen { trials, .. }) => *trials >= self.half_open_requests,
+            _ => false,
+        }
+    }
+
+    // Ok is whether the request is a trial, Err is how long until a trial is
+    // allowed. zero while the trials are in flight
+    fn acquire(&self, key: &str, now: Instant) -> Result<bool, Duration> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(key.to_string()).or_insert(Circuit {
+            state: State::Closed,
+            outcomes: VecDeque::new(),
+        });
+        match &mut circuit.state {
+            State::Closed => Ok(false),
+            State::Open { until } if now < *until => Err(*until - now),
+            State::Open { .. } => {
+                circuit.state = State::HalfOpen {
+                    trials: 1,
+                    successes: 0,
+                };
+                Ok(true)
+            }
+            State::HalfOpen { trials, .. } if *trials < self.half_open_requests => {
+                *trials += 1;
+                Ok(true)
+            }
+            State::HalfOpen { .. } => Err(Duration::ZERO),
+        }
+    }
+
+    // failed is None when the outcome says nothing about the destination, e.g.
+    // the request was cancelled
+    fn record(&self, key: &str, trial: bool, failed: Option<bool>, now: Instant) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(key) else {
+            return;
+        };
+        match (&mut circuit.state, failed) {
+            (State::HalfOpen { trials, .. }, None) if trial => *trials -= 1,
+            (State::HalfOpen { .. }, Some(true)) if trial => {
+                circuit.state = State::Open {
+                    until: now + self.open_duration,
+                };
+            }
+            (State::HalfOpen { successes, .. }, Some(false)) if trial => {
+                *successes += 1;
+                if *successes >= self.half_open_requests {
+                    circuit.state = State::Closed;
+                    circuit.outcomes.clear();
+                }
+            }
+            (State::Closed, Some(failed)) if !trial => {
+                circuit.outcomes.push_back(failed);
+                if circuit.outcomes.len() > self.window {
+                    circuit.outcomes.pop_front();
+                }
+                let failures = circuit.outcomes.iter().filter(|f| **f).count();
+                let total = circuit.outcomes.len();
+                if total >= self.min_requests
+                    && failures as f64 >= self.failure_rate * total as f64
+                    && failures > 0
+                {
+                    circuit.state = State::Open {
+                        until: now + self.open_duration,
+                    };
+                    circuit.outcomes.clear();
+                }
+            }
+            // sent before the circuit changed
+            _ => {}
+        }
+    }
+}
+
+impl Middleware for CircuitBreaker {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        // NOTE: relative urls of a client without base url share one circuit
+        let key = pool_key(&req.url).unwrap_or_default();
+        let trial = self
+            .acquire(&key, Instant::now())
+            .map_err(HttpError::CircuitOpen)?;
+        let result = next.run(req);
+        let failed = match &result {
+            Ok(resp) => Some(self.failure_statuses.contains(&resp.status)),
+            Err(HttpError::Cancelled | HttpError::RateLimited(_) | HttpError::CircuitOpen(_)) => {
+                None
+            }
+            Err(_) => Some(true),
+        };
+        self.record(&key, trial, failed, Instant::now());
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockConn;
+    use crate::HttpClient;
+
+    #[test]
+    fn circuit_open_and_close() {
+        let mut breaker = CircuitBreaker::new();
+        breaker
+            .window(4)
+            .min_requests(4)
+            .failure_rate(0.5)
+            .open_duration(Duration::from_secs(10))
+            .half_open_requests(2);
+        let start = Instant::now();
+        let key = "unix:/var/run/docker.sock";
+
+        for failed in [false, true, false] {
+            assert_eq!(breaker.acquire(key, start), Ok(false));
+            breaker.record(key, false, Some(failed), start);
+        }
+        // opens by 2 failures of 4
+        assert_eq!(breaker.acquire(key, start), Ok(false));
+        breaker.record(key, false, Some(true), start);
+        let later = start + Duration::from_secs(4);
+        assert_eq!(breaker.acquire(key, later), Err(Duration::from_secs(6)));
+        // other destinations are not affected
+        assert_eq!(breaker.acquire("http:docker:2375", later), Ok(false));
+
+        // half open, with 2 trials
+        let reopen = start + Duration::from_secs(10);
+        assert_eq!(breaker.acquire(key, reopen), Ok(true));
+        assert_eq!(breaker.acquire(key, reopen), Ok(true));
+        assert_eq!(breaker.acquire(key, reopen), Err(Duration::ZERO));
+        breaker.record(key, true, Some(false), reopen);
+        // a cancelled trial frees its place
+        breaker.record(key, true, None, reopen);
+        assert_eq!(breaker.acquire(key, reopen), Ok(true));
+        breaker.record(key, true, Some(false), reopen);
+        assert_eq!(breaker.acquire(key, reopen), Ok(false));
+
+        // a failed trial opens it again
+        for _ in 0..4 {
+            breaker.record(key, false, Some(true), reopen);
+        }
+        let reopen = reopen + Duration::from_secs(10);
+        assert_eq!(breaker.acquire(key, reopen), Ok(true));
+        breaker.record(key, true, Some(true), reopen);
+        assert_eq!(breaker.acquire(key, reopen), Err(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn circuit_fail_fast() {
+        let unavailable = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n";
+        let mut client = HttpClient::new(MockConn::new(&unavailable.repeat(2)));
+        let mut breaker = CircuitBreaker::new();
+        breaker.min_requests(2);
+        client.middleware(breaker);
+
+        for _ in 0..2 {
+            let resp = client.execute_request(&mut Request::get("/_ping")).unwrap();
+            assert_eq!(resp.status, StatusCode::SERVICE_UNAVAILABLE);
+        }
+        let err = client
+            .execute_request(&mut Request::get("/_ping"))
+            .unwrap_err();
+        assert!(matches!(err, HttpError::CircuitOpen(_)), "{}", err);
+        let sent = String::from_utf8(client.conn.get_ref().output.clone()).unwrap();
+        assert_eq!(sent.matches("GET /_ping").count(), 2);
+    }
+}
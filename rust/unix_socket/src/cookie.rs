@@ -0,0 +1,513 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::url::Url;
+use crate::{headers, HttpHeader};
+
+// upper bound of Max-Age, 400 days like browsers (RFC 6265bis section 5.5)
+const MAX_AGE: u64 = 400 * 24 * 60 * 60;
+
+// a cookie set by Set-Cookie (RFC 6265)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    // without Domain attribute, the cookie is sent only to the same host
+    pub host_only: bool,
+    pub path: String,
+    // None is a session cookie
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+impl Cookie {
+    // None when the header is invalid or not allowed for url
+    pub fn parse(set_cookie: &str, url: &Url, now: SystemTime) -> Option<Self> {
+        let mut attrs = set_cookie.split(';');
+        let (name, value) = attrs.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let host = host(url);
+        let mut cookie = Cookie {
+            name: name.into(),
+            value: value.trim().trim_matches('"').into(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(&url.path),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+        let mut max_age = None;
+        for attr in attrs {
+            let (k, v) = attr.split_once('=').unwrap_or((attr, ""));
+            let v = v.trim();
+            match k.trim().to_ascii_lowercase().as_str() {
+                "domain" if !v.is_empty() => {
+                    let domain = v.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_match(&host, &domain) {
+                        return None;
+                    }
+                    cookie.host_only = false;
+                    cookie.domain = domain;
+                }
+                "path" if v.starts_with('/') => cookie.path = v.into(),
+                "max-age" => max_age = v.parse::<i64>().ok().or(max_age),
+                "expires" => {
+                    if let Some(t) = headers::parse_date(v) {
+                        cookie.expires.get_or_insert(t);
+                    }
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // Max-Age has precedence over Expires
+        if let Some(secs) = max_age {
+            cookie.expires = Some(match secs {
+                ..=0 => UNIX_EPOCH,
+                secs => now + Duration::from_secs((secs as u64).min(MAX_AGE)),
+            });
+        }
+        // NOTE: only a secure origin may set a secure cookie
+        if cookie.secure && !is_secure(url) {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|t| t <= now)
+    }
+
+    // whether the cookie is sent with a request to url
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = host(url);
+        let domain_ok = match self.host_only {
+            true => host == self.domain,
+            false => domain_match(&host, &self.domain),
+        };
+        domain_ok && path_match(&url.path, &self.path) && (!self.secure || is_secure(url))
+    }
+}
+
+// where a CookieJar keeps its cookies across process restarts
+pub trait CookieStore: Send + Sync {
+    fn load(&self) -> io::Result<Vec<Cookie>>;
+    fn save(&self, cookies: &[Cookie]) -> io::Result<()>;
+}
+
+// cookies shared by the requests of clients, see HttpClient::cookie_jar.
+// NOTE: the public suffix list is not consulted, so a Domain attribute like
+// `com` is accepted
+#[derive(Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+    store: Option<Box<dyn CookieStore>>,
+}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &*self.lock())
+            .field("persistent", &self.store.is_some())
+            .finish()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a jar with the unexpired cookies of store, which is saved whenever a
+    // response changes the cookies:
+    //
+    //     let jar = CookieJar::with_store(CookieFile::new("cookies.txt"))?;
+    pub fn with_store(store: impl CookieStore + 'static) -> io::Result<Self> {
+        let now = SystemTime::now();
+        let mut cookies = store.load()?;
+        cookies.retain(|c| !c.is_expired(now));
+        Ok(Self {
+            cookies: Mutex::new(cookies),
+            store: Some(Box::new(store)),
+        })
+    }
+
+    // store the Set-Cookie headers of a response to a request to url
+    // NOTE: errors of the persistent store are ignored here, call save to see them
+    pub fn store(&self, url: &Url, header: &HttpHeader) {
+        let now = SystemTime::now();
+        let mut changed = false;
+        for set_cookie in header.get_all(headers::SET_COOKIE) {
+            if let Some(cookie) = Cookie::parse(set_cookie, url, now) {
+                self.insert(cookie, now);
+                changed = true;
+            }
+        }
+        if changed {
+            let _ = self.save();
+        }
+    }
+
+    // add or replace a cookie. an expired cookie removes the stored one
+    pub fn insert(&self, cookie: Cookie, now: SystemTime) {
+        let mut cookies = self.lock();
+        cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired(now) {
+            cookies.push(cookie);
+        }
+    }
+
+    // write the cookies to the persistent store, if any
+    pub fn save(&self) -> io::Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        // the lock is held so that concurrent saves are not reordered
+        let cookies = self.lock();
+        store.save(&cookies)
+    }
+
+    // value of the Cookie header for a request to url
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let now = SystemTime::now();
+        let mut cookies = self.lock();
+        cookies.retain(|c| !c.is_expired(now));
+        let mut matched = cookies
+            .iter()
+            .filter(|c| c.matches(url))
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            return None;
+        }
+        // longer paths first
+        matched.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let pairs = matched
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>();
+        Some(pairs.join("; "))
+    }
+
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.lock().clone()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Cookie>> {
+        self.cookies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// a cookies.txt file in the Netscape format, which curl reads with -b and
+// writes with -c. session cookies are kept with expiry 0 like curl does
+pub struct CookieFile {
+    path: PathBuf,
+}
+
+impl CookieFile {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CookieStore for CookieFile {
+    // a missing file has no cookies
+    fn load(&self) -> io::Result<Vec<Cookie>> {
+        match fs::read_to_string(&self.path) {
+            Ok(s) => parse_cookies_txt(&s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, cookies: &[Cookie]) -> io::Result<()> {
+        // write a temporary file and rename it, so a crash never leaves a
+        // truncated file behind
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, format_cookies_txt(cookies))?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+fn format_cookies_txt(cookies: &[Cookie]) -> String {
+    let mut out = String::from("# Netscape HTTP Cookie File\n\n");
+    for c in cookies {
+        let expires = c
+            .expires
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let bool = |b: bool| if b { "TRUE" } else { "FALSE" };
+        out += &format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if c.http_only { HTTP_ONLY_PREFIX } else { "" },
+            if c.host_only { "" } else { "." },
+            c.domain,
+            bool(!c.host_only),
+            c.path,
+            bool(c.secure),
+            expires,
+            c.name,
+            c.value
+        );
+    }
+    out
+}
+
+fn parse_cookies_txt(s: &str) -> io::Result<Vec<Cookie>> {
+    let invalid = |n: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid cookie at line {}", n + 1),
+        )
+    };
+    let mut cookies = Vec::new();
+    for (n, line) in s.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+            return Err(invalid(n));
+        };
+        let expires = match expires.parse::<u64>().map_err(|_| invalid(n))? {
+            0 => None,
+            secs => Some(
+                UNIX_EPOCH
+                    .checked_add(Duration::from_secs(secs))
+                    .ok_or_else(|| invalid(n))?,
+            ),
+        };
+        cookies.push(Cookie {
+            name: name.into(),
+            value: value.into(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            host_only: subdomains != "TRUE",
+            path: path.into(),
+            expires,
+            secure: secure == "TRUE",
+            http_only,
+        });
+    }
+    Ok(cookies)
+}
+
+// requests over a unix socket are sent with `Host: localhost`
+fn host(url: &Url) -> String {
+    url.host
+        .as_deref()
+        .unwrap_or("localhost")
+        .to_ascii_lowercase()
+}
+
+fn is_secure(url: &Url) -> bool {
+    url.scheme.as_deref() == Some("https")
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[');
+    !is_ip && host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+}
+
+// RFC 6265 section 5.1.4
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(i) if i > 0 && path.starts_with('/') => path[..i].into(),
+        _ => "/".into(),
+    }
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    let path = if path.is_empty() { "/" } else { path };
+    path == cookie_path
+        || path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn cookie_parse() {
+        let now = SystemTime::now();
+        let u = url("http://api.example.com/v1/session/login");
+        let c = Cookie::parse("sid=abc; Path=/v1; HttpOnly; Max-Age=60", &u, now).unwrap();
+        assert_eq!((c.name.as_str(), c.value.as_str()), ("sid", "abc"));
+        assert_eq!(c.domain, "api.example.com");
+        assert!(c.host_only && c.http_only && !c.secure);
+        assert_eq!(c.path, "/v1");
+        assert_eq!(c.expires, Some(now + Duration::from_secs(60)));
+
+        let c = Cookie::parse("a=1; Domain=.Example.com", &u, now).unwrap();
+        assert_eq!(c.domain, "example.com");
+        assert!(!c.host_only);
+        assert_eq!(c.path, "/v1/session");
+
+        assert!(Cookie::parse("a=1; Domain=other.com", &u, now).is_none());
+        assert!(Cookie::parse("a=1; Secure", &u, now).is_none());
+        assert!(Cookie::parse("novalue", &u, now).is_none());
+
+        let c = Cookie::parse("a=b; Max-Age=9223372036854775807", &u, now).unwrap();
+        assert_eq!(c.expires, Some(now + Duration::from_secs(MAX_AGE)));
+    }
+
+    #[test]
+    fn cookie_matches() {
+        let now = SystemTime::now();
+        let u = url("https://example.com/");
+        let c = Cookie::parse("a=1; Domain=example.com; Path=/api; Secure", &u, now).unwrap();
+        assert!(c.matches(&url("https://www.example.com/api/x")));
+        assert!(c.matches(&url("https://example.com/api")));
+        assert!(!c.matches(&url("https://example.com/apis")));
+        assert!(!c.matches(&url("http://example.com/api")));
+        assert!(!c.matches(&url("https://badexample.com/api")));
+
+        let c = Cookie::parse("a=1", &url("unix:///var/run/admin.sock"), now).unwrap();
+        assert!(c.matches(&url("unix:///var/run/admin.sock:/users")));
+    }
+
+    #[test]
+    fn cookie_jar() {
+        let jar = CookieJar::new();
+        let u = url("http://localhost/api/login");
+        let header: HttpHeader = [
+            (headers::SET_COOKIE, "sid=1; Path=/"),
+            (headers::SET_COOKIE, "pref=dark; Path=/api"),
+            (
+                headers::SET_COOKIE,
+                "old=x; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            ),
+        ]
+        .into_iter()
+        .collect();
+        jar.store(&u, &header);
+        assert_eq!(
+            jar.cookie_header(&url("http://localhost/api/users")),
+            Some("pref=dark; sid=1".into())
+        );
+        assert_eq!(
+            jar.cookie_header(&url("http://localhost/")),
+            Some("sid=1".into())
+        );
+
+        // replaced, then removed
+        let header: HttpHeader = [(headers::SET_COOKIE, "sid=2; Path=/")]
+            .into_iter()
+            .collect();
+        jar.store(&u, &header);
+        assert_eq!(
+            jar.cookie_header(&url("http://localhost/")),
+            Some("sid=2".into())
+        );
+        let header: HttpHeader = [(headers::SET_COOKIE, "sid=; Path=/; Max-Age=0")]
+            .into_iter()
+            .collect();
+        jar.store(&u, &header);
+        assert_eq!(jar.cookie_header(&url("http://localhost/")), None);
+        assert_eq!(jar.cookies().len(), 1);
+    }
+
+    #[test]
+    fn cookie_txt() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let u = url("https://api.example.com/v1/login");
+        let cookies = vec![
+            Cookie::parse("sid=abc; Path=/; Secure; HttpOnly; Max-Age=60", &u, now).unwrap(),
+            Cookie::parse("theme=dark; Domain=example.com", &u, now).unwrap(),
+        ];
+        let txt = format_cookies_txt(&cookies);
+        assert_eq!(
+            txt,
+            "# Netscape HTTP Cookie File\n\n\
+             #HttpOnly_api.example.com\tFALSE\t/\tTRUE\t1700000060\tsid\tabc\n\
+             .example.com\tTRUE\t/v1\tFALSE\t0\ttheme\tdark\n"
+        );
+        assert_eq!(parse_cookies_txt(&txt).unwrap(), cookies);
+        assert!(parse_cookies_txt("example.com\tFALSE\t/\n").is_err());
+        assert!(
+            parse_cookies_txt("example.com\tFALSE\t/\tFALSE\t18446744073709551615\ta\tb\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cookie_file() {
+        let path = std::env::temp_dir().join(format!("unix_socket_cookies_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let u = url("http://localhost/login");
+        let header: HttpHeader = [(headers::SET_COOKIE, "sid=abc; Path=/")]
+            .into_iter()
+            .collect();
+
+        let jar = CookieJar::with_store(CookieFile::new(&path)).unwrap();
+        assert!(jar.cookies().is_empty());
+        jar.store(&u, &header);
+
+        // a new process
+        let jar = CookieJar::with_store(CookieFile::new(&path)).unwrap();
+        assert_eq!(
+            jar.cookie_header(&url("http://localhost/users")),
+            Some("sid=abc".into())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cookie_client() {
+        use std::sync::Arc;
+
+        use crate::test::MockConn;
+        use crate::{HttpClient, Request};
+
+        let resp = "HTTP/1.1 200 OK\r\nSet-Cookie: sid=abc; Path=/; HttpOnly\r\n\
+                    Content-Length: 0\r\n\r\n";
+        let mut client = HttpClient::new(MockConn::new(&resp.repeat(2)));
+        client.base_url(url("unix:///var/run/admin.sock"));
+        let jar = Arc::new(CookieJar::new());
+        client.cookie_jar(Some(jar.clone()));
+        client.execute_request(&mut Request::get("/login")).unwrap();
+        client.execute_request(&mut Request::get("/users")).unwrap();
+
+        let sent = String::from_utf8(client.conn.get_ref().output.clone()).unwrap();
+        let (first, second) = sent.split_at(sent.find("GET /users").unwrap());
+        assert!(!first.contains("Cookie:"));
+        assert!(second.contains("\r\nCookie: sid=abc\r\n"));
+        assert_eq!(jar.cookies()[0].domain, "localhost");
+    }
+}
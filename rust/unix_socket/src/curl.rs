@@ -0,0 +1,111 @@
+use crate::transport::TransportInfo;
+use crate::Request;
+
+// the curl command line which sends the same request, e.g.
+// `curl --unix-socket /var/run/docker.sock -X POST -H 'Content-Type: application/json' ...`
+// NOTE: a body reader can't be replayed, so the body is read from stdin, and a
+// body is sent uncompressed even when Request::compress is set
+pub fn command(req: &Request, transport: &TransportInfo) -> String {
+    let mut args = vec!["curl".to_string()];
+    if let Some(path) = &transport.unix_socket {
+        args.push("--unix-socket".into());
+        args.push(quote(path));
+    }
+    if let Some(name) = &transport.abstract_unix_socket {
+        args.push("--abstract-unix-socket".into());
+        args.push(quote(name));
+    }
+    let has_body = req.body.is_some() || req.has_body_reader();
+    let method = req.method.to_string();
+    if method != "GET" || has_body {
+        args.push("-X".into());
+        args.push(quote(&method));
+    }
+    if let Some(header) = &req.header {
+        for (k, v) in header.iter() {
+            args.push("-H".into());
+            args.push(quote(&format!("{}: {}", k, v)));
+        }
+    }
+    if let Some(body) = &req.body {
+        args.push("--data-binary".into());
+        args.push(quote(&String::from_utf8_lossy(body)));
+    } else if req.has_body_reader() {
+        args.push("--data-binary".into());
+        args.push("@-".into());
+    }
+
+    let target = req.target().unwrap_or_else(|_| req.url.request_target());
+    let url = match (&req.url.scheme, &req.url.host) {
+        // already resolved against an http url
+        (Some(scheme), Some(_)) if scheme == "http" || scheme == "https" => {
+            let authority = req.url.authority().unwrap_or_default();
+            format!("{}://{}{}", scheme, authority, target)
+        }
+        _ => format!("{}{}", transport.origin, target),
+    };
+    args.push(quote(&url));
+    args.join(" ")
+}
+
+// quote s for a POSIX shell unless it consists of safe characters only
+fn quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:@=,%+".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::url::Url;
+    use crate::{HttpHeader, HttpMethod, HttpParams};
+
+    #[test]
+    fn curl_quote() {
+        assert_eq!(quote("/var/run/docker.sock"), "/var/run/docker.sock");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn curl_command() {
+        let unix = TransportInfo::from_url(&Url::parse("unix:///var/run/docker.sock").unwrap());
+        let req = Request::get("/_ping");
+        assert_eq!(
+            req.to_curl(&unix),
+            "curl --unix-socket /var/run/docker.sock http://localhost/_ping"
+        );
+
+        let mut req = Request::new("/containers/create");
+        let params: HttpParams = [("name", "web app")].into_iter().collect();
+        let header: HttpHeader = [("Content-Type", "application/json")].into_iter().collect();
+        req.method(HttpMethod::Post)
+            .params(params)
+            .header(header)
+            .body(r#"{"Cmd":["echo","it's"]}"#);
+        assert_eq!(
+            req.to_curl(&unix),
+            "curl --unix-socket /var/run/docker.sock -X POST \
+             -H 'Content-Type: application/json' \
+             --data-binary '{\"Cmd\":[\"echo\",\"it'\\''s\"]}' \
+             'http://localhost/containers/create?name=web%20app'"
+        );
+
+        let tcp = TransportInfo::from_url(&Url::parse("tcp://127.0.0.1:2375").unwrap());
+        let mut req = Request::new("/build");
+        req.method(HttpMethod::Post)
+            .body_reader(std::io::Cursor::new(b"tar".to_vec()));
+        assert_eq!(
+            req.to_curl(&tcp),
+            "curl -X POST --data-binary @- http://127.0.0.1:2375/build"
+        );
+
+        // resolved by the client
+        let req = Request::get("https://docker:2376/info");
+        assert_eq!(req.to_curl(&tcp), "curl https://docker:2376/info");
+    }
+}
@@ -0,0 +1,110 @@
+use std::io::{self, BufRead, Read};
+use std::time::{Duration, Instant};
+
+use crate::transport::ReadTimeout;
+
+// fails reads once the deadline has passed. a single blocking read is only
+// interrupted with the socket, whose read timeout is shortened to the time
+// left while reading, and restored after
+pub struct DeadlineReader<'a, R> {
+    inner: R,
+    deadline: Option<Instant>,
+    socket: Option<&'a dyn ReadTimeout>,
+}
+
+impl<'a, R> DeadlineReader<'a, R> {
+    pub fn new(inner: R, deadline: Option<Instant>) -> Self {
+        Self {
+            inner,
+            deadline,
+            socket: None,
+        }
+    }
+
+    // a clone of the socket inner reads from, see HttpClient::socket
+    pub fn with_socket(mut self, socket: Option<&'a dyn ReadTimeout>) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    fn check(&self) -> io::Result<()> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "deadline exceeded"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // the read timeout to restore, when it was shortened to the time left
+    fn shorten(&self) -> io::Result<Option<Option<Duration>>> {
+        let (Some(socket), Some(deadline)) = (self.socket, self.deadline) else {
+            return Ok(None);
+        };
+        let timeout = socket.read_timeout()?;
+        let left = deadline.saturating_duration_since(Instant::now());
+        let left = left.max(Duration::from_millis(1));
+        if timeout.is_some_and(|timeout| timeout <= left) {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(left))?;
+        Ok(Some(timeout))
+    }
+
+    fn restore(&self, timeout: Option<Option<Duration>>) -> io::Result<()> {
+        match (self.socket, timeout) {
+            (Some(socket), Some(timeout)) => socket.set_read_timeout(timeout),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Read for DeadlineReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        let timeout = self.shorten()?;
+        let result = self.inner.read(buf);
+        self.restore(timeout)?;
+        result
+    }
+}
+
+impl<R: BufRead> BufRead for DeadlineReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        let timeout = self.shorten()?;
+        if timeout.is_some() {
+            // fill the buffer with the shortened timeout, and return it after
+            // the timeout is restored
+            let filled = self.inner.fill_buf().map(|_| ());
+            self.restore(timeout)?;
+            filled?;
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn deadline_reader() {
+        let mut r = DeadlineReader::new(Cursor::new(b"ok".to_vec()), None);
+        let mut buf = String::new();
+        r.read_line(&mut buf).unwrap();
+        assert_eq!(buf, "ok");
+
+        let deadline = Instant::now() - Duration::from_millis(1);
+        let mut r = DeadlineReader::new(Cursor::new(b"ok".to_vec()), Some(deadline));
+        let err = r.read_line(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::sha256::hex;
+use crate::status::StatusCode;
+use crate::url::Url;
+use crate::uuid::random_u64;
+use crate::{headers, md5, sha256, HttpHeader, Request, Response};
+
+// Digest authentication (RFC 7616). a 401 with `WWW-Authenticate: Digest` is
+// answered by sending the request again with credentials, and the challenge is
+// kept so that later requests to the same origin are authorized up front:
+//
+//     client.middleware(DigestAuth::new("admin", "secret"));
+//
+// NOTE: only qop=auth is supported, a challenge which offers auth-int only is
+// returned as is. a request with a body reader is not sent again either
+pub struct DigestAuth {
+    user: String,
+    pass: String,
+    // by the origin of the request url
+    challenges: Mutex<HashMap<String, Challenge>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Self::Md5),
+            "MD5-SESS" => Some(Self::Md5Sess),
+            "SHA-256" => Some(Self::Sha256),
+            "SHA-256-SESS" => Some(Self::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+            Self::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn hash(&self, data: &str) -> String {
+        match self {
+            Self::Md5 | Self::Md5Sess => hex(&md5::digest(data)),
+            Self::Sha256 | Self::Sha256Sess => hex(&sha256::digest(data)),
+        }
+    }
+
+    fn is_sess(&self) -> bool {
+        matches!(self, Self::Md5Sess | Self::Sha256Sess)
+    }
+}
+
+// the parameters of a Digest challenge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    pub algorithm: Algorithm,
+    // whether qop=auth is offered. without qop, the RFC 2069 response is sent
+    pub qop_auth: bool,
+    pub stale: bool,
+    // nonce count, the number of requests sent with the nonce
+    nc: u32,
+}
+
+impl Challenge {
+    // the strongest supported Digest challenge of the WWW-Authenticate headers
+    pub fn from_header(header: &HttpHeader) -> Option<Self> {
+        header
+            .get_all(headers::WWW_AUTHENTICATE)
+            .flat_map(parse_challenges)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Digest"))
+            .filter_map(|(_, params)| Self::from_params(&params))
+            .max_by_key(|c| matches!(c.algorithm, Algorithm::Sha256 | Algorithm::Sha256Sess))
+    }
+
+    fn from_params(params: &[(String, String)]) -> Option<Self> {
+        let get = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.as_str())
+        };
+        let qop = get("qop");
+        let qop_auth = qop.is_some_and(|q| q.split(',').any(|q| q.trim() == "auth"));
+        if qop.is_some() && !qop_auth {
+            return None;
+        }
+        Some(Self {
+            realm: get("realm")?.into(),
+            nonce: get("nonce")?.into(),
+            opaque: get("opaque").map(Into::into),
+            algorithm: Algorithm::parse(get("algorithm").unwrap_or("MD5"))?,
+            qop_auth,
+            stale: get("stale").is_some_and(|s| s.eq_ignore_ascii_case("true")),
+            nc: 0,
+        })
+    }
+
+    // value of the Authorization header for a request
+    fn authorization(
+        &mut self,
+        user: &str,
+        pass: &str,
+        method: &str,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        self.nc += 1;
+        let nc = format!("{:08x}", self.nc);
+        let alg = self.algorithm;
+        let mut ha1 = alg.hash(&format!("{}:{}:{}", user, self.realm, pass));
+        if alg.is_sess() {
+            ha1 = alg.hash(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = alg.hash(&format!("{}:{}", method, uri));
+        let response = match self.qop_auth {
+            true => alg.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            )),
+            false => alg.hash(&format!("{}:{}:{}", ha1, self.nonce, ha2)),
+        };
+
+        let mut out = format!(
+            "Digest username={}, realm={}, uri={}, algorithm={}, nonce={}",
+            quote(user),
+            quote(&self.realm),
+            quote(uri),
+            alg.as_str(),
+            quote(&self.nonce)
+        );
+        if self.qop_auth {
+            out += &format!(", nc={}, cnonce={}, qop=auth", nc, quote(cnonce));
+        }
+        out += &format!(", response={}", quote(&response));
+        if let Some(opaque) = &self.opaque {
+            out += &format!(", opaque={}", quote(opaque));
+        }
+        out
+    }
+}
+
+impl DigestAuth {
+    pub fn new(user: &str, pass: &str) -> Self {
+        Self {
+            user: user.into(),
+            pass: pass.into(),
+            challenges: Mutex::default(),
+        }
+    }
+
+    // set Authorization with the cached challenge of key. false when there is none
+    fn authorize(&self, key: &str, req: &mut Request) -> bool {
+        let mut challenges = self.challenges.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(challenge) = challenges.get_mut(key) else {
+            return false;
+        };
+        let uri = req.target().unwrap_or_else(|_| req.url.request_target());
+        let cnonce = format!("{:016x}", random_u64());
+        let authorization = challenge.authorization(
+            &self.user,
+            &self.pass,
+            &req.method.to_string(),
+            &uri,
+            &cnonce,
+        );
+        req.header
+            .get_or_insert_with(HttpHeader::new)
+            .add(headers::AUTHORIZATION, &authorization);
+        true
+    }
+}
+
+impl Middleware for DigestAuth {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let key = origin(&req.url);
+        let sent = self.authorize(&key, req);
+        let resp = next.run(req)?;
+        if resp.status != StatusCode::UNAUTHORIZED || !req.try_rewind() {
+            return Ok(resp);
+        }
+        let Some(challenge) = Challenge::from_header(&resp.header) else {
+            return Ok(resp);
+        };
+        // the credentials were rejected, unless only the nonce expired
+        if sent && !challenge.stale {
+            return Ok(resp);
+        }
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.clone(), challenge);
+        self.authorize(&key, req);
+        next.run(req)
+    }
+}
+
+fn origin(url: &Url) -> String {
+    format!(
+        "{}|{}|{}",
+        url.scheme.as_deref().unwrap_or_default(),
+        url.socket_path.as_deref().unwrap_or_default(),
+        url.authority().unwrap_or_default().to_ascii_lowercase()
+    )
+}
+
+// the challenges of a WWW-Authenticate value, e.g.
+// `Digest realm="a", qop="auth,auth-int", Basic realm="b"`
+fn parse_challenges(s: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut challenges: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c == ',' || c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = rest[end..].trim_start();
+        if let Some(value) = rest.strip_prefix('=') {
+            // an auth-param of the current challenge
+            let value = value.trim_start();
+            let (value, remaining) = match value.strip_prefix('"') {
+                Some(quoted) => unquote(quoted),
+                None => {
+                    let end = value.find(',').unwrap_or(value.len());
+                    (value[..end].trim_end().to_string(), &value[end..])
+                }
+            };
+            if let Some((_, params)) = challenges.last_mut() {
+                params.push((token.to_ascii_lowercase(), value));
+            }
+            rest = remaining;
+        } else if !token.is_empty() {
+            // NOTE: a token68 like Basic credentials is skipped as a scheme
+            challenges.push((token.to_string(), Vec::new()));
+        }
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+    }
+    challenges
+}
+
+// the quoted-string after the opening quote and the rest after the closing one
+fn unquote(s: &str) -> (String, &str) {
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (out, &s[i + 1..]),
+            '\\' => out.extend(chars.next().map(|(_, c)| c)),
+            c => out.push(c),
+        }
+    }
+    (out, "")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockTransport, ResponseBuilder};
+    use crate::HttpClient;
+
+    fn rfc7616_challenge(algorithm: &str) -> Challenge {
+        let value = format!(
+            "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+             algorithm={}, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+            algorithm
+        );
+        let header: HttpHeader = [(headers::WWW_AUTHENTICATE, value.as_str())]
+            .into_iter()
+            .collect();
+        Challenge::from_header(&header).unwrap()
+    }
+
+    #[test]
+    fn digest_parse_challenges() {
+        let got = parse_challenges(r#"Basic realm="b", Digest realm="a \"x\"", stale=TRUE"#);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].0, "Basic");
+        assert_eq!(got[1].0, "Digest");
+        assert_eq!(
+            got[1].1,
+            [
+                ("realm".to_string(), "a \"x\"".to_string()),
+                ("stale".to_string(), "TRUE".to_string())
+            ]
+        );
+
+        let header: HttpHeader = [
+            (headers::WWW_AUTHENTICATE, "Digest realm=\"r\", nonce=\"1\""),
+            (
+                headers::WWW_AUTHENTICATE,
+                "Digest realm=\"r\", nonce=\"2\", algorithm=SHA-256",
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let c = Challenge::from_header(&header).unwrap();
+        assert_eq!((c.nonce.as_str(), c.algorithm), ("2", Algorithm::Sha256));
+        assert!(!c.qop_auth);
+
+        let header: HttpHeader = [(
+            headers::WWW_AUTHENTICATE,
+            "Digest realm=\"r\", nonce=\"1\", qop=\"auth-int\"",
+        )]
+        .into_iter()
+        .collect();
+        assert!(Challenge::from_header(&header).is_none());
+    }
+
+    #[test]
+    fn digest_rfc7616_example() {
+        let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+        let tests = [
+            ("MD5", "8ca523f5e9506fed4657c9700eebdbec"),
+            (
+                "SHA-256",
+                "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1",
+            ),
+        ];
+        for (algorithm, response) in tests {
+            let mut c = rfc7616_challenge(algorithm);
+            let got = c.authorization("Mufasa", "Circle of Life", "GET", "/dir/index.html", cnonce);
+            let want = format!(
+                "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+                 uri=\"/dir/index.html\", algorithm={}, \
+                 nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+                 cnonce=\"{}\", qop=auth, response=\"{}\", \
+                 opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+                algorithm, cnonce, response
+            );
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn digest_client() {
+        let challenge = ResponseBuilder::new(StatusCode::UNAUTHORIZED)
+            .header(
+                "WWW-Authenticate",
+                "Digest realm=\"admin\", nonce=\"n1\", qop=\"auth\"",
+            )
+            .build();
+        let ok = ResponseBuilder::new(StatusCode::OK).body("ok").build();
+        let stale = ResponseBuilder::new(StatusCode::UNAUTHORIZED)
+            .header(
+                "WWW-Authenticate",
+                "Digest realm=\"admin\", nonce=\"n2\", qop=\"auth\", stale=true",
+            )
+            .build();
+        let mut conn = MockTransport::new();
+        conn.push(challenge.clone())
+            .push(ok.clone())
+            .push(ok.clone())
+            .push(stale)
+            .push(ok)
+            .push(challenge);
+        let mut client = HttpClient::new(conn);
+        client.middleware(DigestAuth::new("admin", "secret"));
+
+        let resp = client.execute_request(&mut Request::get("/a")).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        // authorized up front with the next nonce count
+        let resp = client.execute_request(&mut Request::get("/b")).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        // a stale nonce is replaced
+        let resp = client.execute_request(&mut Request::get("/c")).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        // rejected credentials are not sent again
+        let resp = client.execute_request(&mut Request::get("/d")).unwrap();
+        assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+
+        let requests = client
+            .conn
+            .get_ref()
+            .requests()
+            .into_iter()
+            .map(|r| String::from_utf8(r).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(requests.len(), 6);
+        assert!(!requests[0].contains("Authorization"));
+        assert!(requests[1].contains("nonce=\"n1\", nc=00000001"));
+        assert!(requests[2].starts_with("GET /b "));
+        assert!(requests[2].contains("uri=\"/b\""));
+        assert!(requests[2].contains("nonce=\"n1\", nc=00000002"));
+        assert!(requests[4].contains("nonce=\"n2\", nc=00000001"));
+        assert!(requests[5].contains("nonce=\"n2\", nc=00000002"));
+    }
+}
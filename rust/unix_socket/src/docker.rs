@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::body::Body;
+use crate::dockerignore::DockerIgnore;
+use crate::error::HttpError;
+use crate::stdcopy::std_copy;
+use crate::tar::Archive;
+use crate::transport::Transport;
+use crate::{
+    headers, percent, HttpClient, HttpHeader, HttpMethod, HttpParams, ReadWriter, Request, Response,
+};
+
+// the oldest version which has everything used here
+pub const API_VERSION: &str = "1.41";
+
+#[cfg(unix)]
+pub const DEFAULT_HOST: &str = "unix:///var/run/docker.sock";
+#[cfg(windows)]
+pub const DEFAULT_HOST: &str = "npipe:////./pipe/docker_engine";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerSummary {
+    pub id: String,
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub image: String,
+    #[serde(rename = "ImageID")]
+    pub image_id: String,
+    pub command: String,
+    pub created: i64,
+    pub state: String,
+    pub status: String,
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerState {
+    pub status: String,
+    pub running: bool,
+    pub paused: bool,
+    pub restarting: bool,
+    #[serde(rename = "OOMKilled")]
+    pub oom_killed: bool,
+    pub dead: bool,
+    pub pid: i64,
+    pub exit_code: i64,
+    pub error: String,
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerInspect {
+    pub id: String,
+    pub created: String,
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub state: ContainerState,
+    pub image: String,
+    pub name: String,
+    pub restart_count: i64,
+    pub config: Option<ContainerConfig>,
+}
+
+// body of `POST /containers/create`. unset fields are left to the daemon
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub tty: bool,
+    #[serde(default)]
+    pub open_stdin: bool,
+    #[serde(default)]
+    pub attach_stdin: bool,
+    #[serde(default)]
+    pub attach_stdout: bool,
+    #[serde(default)]
+    pub attach_stderr: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateContainerResponse {
+    pub id: String,
+    #[serde(default)]
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageSummary {
+    pub id: String,
+    pub parent_id: String,
+    #[serde(default)]
+    pub repo_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub repo_digests: Option<Vec<String>>,
+    pub created: i64,
+    pub size: i64,
+    #[serde(default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ProgressDetail {
+    pub current: Option<u64>,
+    pub total: Option<u64>,
+}
+
+// one record of the progress stream of `POST /images/create`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    pub status: String,
+    // layer id
+    pub id: Option<String>,
+    // progress bar rendered by the daemon, e.g. `[==>   ] 1.2MB/10MB`
+    pub progress: Option<String>,
+    pub progress_detail: Option<ProgressDetail>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    // name and optionally a tag, e.g. `app:latest`
+    pub tag: Option<String>,
+    // path in the context. `Dockerfile` when not set
+    pub dockerfile: Option<String>,
+    pub build_args: HashMap<String, String>,
+    pub no_cache: bool,
+    // always pull newer versions of base images
+    pub pull: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct BuildAux {
+    #[serde(rename = "ID")]
+    pub id: String,
+}
+
+// one record of the output stream of `POST /build`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildOutput {
+    // output of the build steps, usually one line
+    pub stream: Option<String>,
+    // progress of pulling base images
+    pub status: Option<String>,
+    pub id: Option<String>,
+    pub progress: Option<String>,
+    pub progress_detail: Option<ProgressDetail>,
+    // id of the built image
+    pub aux: Option<BuildAux>,
+}
+
+// result of Docker::exec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExecInspect {
+    pub running: bool,
+    // null while running
+    pub exit_code: Option<i64>,
+    pub pid: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IdResponse {
+    id: String,
+}
+
+// error body of the Engine API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+// error record in the middle of a JSON stream
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    error: Option<String>,
+}
+
+// newline delimited JSON of progress streams, e.g. `docker pull`. error
+// records are returned as Err
+pub struct JsonStream<'a, T> {
+    r: BufReader<Body<'a>>,
+    done: bool,
+    _t: PhantomData<T>,
+}
+
+impl<'a, T: DeserializeOwned> JsonStream<'a, T> {
+    pub fn new(body: Body<'a>) -> Self {
+        Self {
+            r: BufReader::new(body),
+            done: false,
+            _t: PhantomData,
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<T>, HttpError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.r.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        let decode = |e: serde_json::Error| {
+            HttpError::Decode(format!("invalid json record: {}: {}", e, line.trim()))
+        };
+        if let Some(err) = serde_json::from_str::<StreamError>(&line)
+            .map_err(decode)?
+            .error
+        {
+            return Err(HttpError::Other(err));
+        }
+        serde_json::from_str(&line).map(Some).map_err(decode)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for JsonStream<'_, T> {
+    type Item = Result<T, HttpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.next_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+fn api_error(resp: &Response) -> HttpError {
+    match resp.json::<ErrorResponse>() {
+        Ok(err) => HttpError::Other(format!("{}: {}", resp.status, err.message)),
+        Err(_) => HttpError::Status(resp.status),
+    }
+}
+
+// typed client of the Docker Engine API. paths are prefixed by the API version,
+// e.g. `/v1.41/containers/json`
+pub struct Docker<T: ReadWriter = Transport> {
+    client: HttpClient<T>,
+    version: String,
+    // opens another connection for requests which hijack it, e.g. exec
+    dialer: Option<Dialer<T>>,
+}
+
+type Dialer<T> = Box<dyn Fn() -> Result<HttpClient<T>, HttpError>>;
+
+impl Docker<Transport> {
+    pub fn connect(host: &str) -> Result<Self, HttpError> {
+        let mut docker = Self::new(HttpClient::connect(host)?);
+        let host = host.to_string();
+        docker.dialer(move || HttpClient::connect(&host));
+        Ok(docker)
+    }
+
+    // DOCKER_HOST, or the default socket of the platform
+    pub fn from_env() -> Result<Self, HttpError> {
+        let host = std::env::var("DOCKER_HOST").unwrap_or_else(|_| DEFAULT_HOST.into());
+        Self::connect(&host)
+    }
+}
+
+impl<T: ReadWriter> Docker<T> {
+    pub fn new(client: HttpClient<T>) -> Self {
+        Self {
+            client,
+            version: API_VERSION.into(),
+            dialer: None,
+        }
+    }
+
+    pub fn dialer(
+        &mut self,
+        f: impl Fn() -> Result<HttpClient<T>, HttpError> + 'static,
+    ) -> &mut Self {
+        self.dialer = Some(Box::new(f));
+        self
+    }
+
+    pub fn api_version(&mut self, p: &str) -> &mut Self {
+        self.version = p.into();
+        self
+    }
+
+    pub fn client(&mut self) -> &mut HttpClient<T> {
+        &mut self.client
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!("/v{}{}", self.version, path)
+    }
+
+    fn request(&self, method: HttpMethod, path: &str) -> Request {
+        let mut req = Request::new(&self.path(path));
+        req.method(method);
+        req
+    }
+
+    // non-2xx responses are converted into an error with the message of the daemon
+    fn execute(&mut self, req: &mut Request) -> Result<Response, HttpError> {
+        let resp = self.client.execute_request(req)?;
+        if resp.status.is_success() || resp.status.as_u16() == 304 {
+            return Ok(resp);
+        }
+        match resp.json::<ErrorResponse>() {
+            Ok(err) => Err(HttpError::Other(format!(
+                "{}: {}",
+                resp.status, err.message
+            ))),
+            Err(_) => Err(HttpError::Status(resp.status)),
+        }
+    }
+
+    pub fn list_containers(&mut self, all: bool) -> Result<Vec<ContainerSummary>, HttpError> {
+        let mut req = self.request(HttpMethod::Get, "/containers/json");
+        if all {
+            req.params(HttpParams::from_iter([("all", "true")]));
+        }
+        self.execute(&mut req)?.json()
+    }
+
+    pub fn inspect(&mut self, id: &str) -> Result<ContainerInspect, HttpError> {
+        let path = format!("/containers/{}/json", percent::encode_component(id));
+        let mut req = self.request(HttpMethod::Get, &path);
+        self.execute(&mut req)?.json()
+    }
+
+    pub fn create(
+        &mut self,
+        name: Option<&str>,
+        config: &ContainerConfig,
+    ) -> Result<CreateContainerResponse, HttpError> {
+        let mut req = self.request(HttpMethod::Post, "/containers/create");
+        if let Some(name) = name {
+            req.params(HttpParams::from_iter([("name", name)]));
+        }
+        req.json(config);
+        self.execute(&mut req)?.json()
+    }
+
+    // starting a running container is not an error
+    pub fn start(&mut self, id: &str) -> Result<(), HttpError> {
+        let path = format!("/containers/{}/start", percent::encode_component(id));
+        self.execute(&mut self.request(HttpMethod::Post, &path))?;
+        Ok(())
+    }
+
+    // the container is killed after timeout seconds. stopping a stopped
+    // container is not an error
+    pub fn stop(&mut self, id: &str, timeout: Option<u32>) -> Result<(), HttpError> {
+        let path = format!("/containers/{}/stop", percent::encode_component(id));
+        let mut req = self.request(HttpMethod::Post, &path);
+        if let Some(timeout) = timeout {
+            req.params(HttpParams::from_iter([("t", timeout.to_string().as_str())]));
+        }
+        self.execute(&mut req)?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &str, force: bool) -> Result<(), HttpError> {
+        let path = format!("/containers/{}", percent::encode_component(id));
+        let mut req = self.request(HttpMethod::Delete, &path);
+        if force {
+            req.params(HttpParams::from_iter([("force", "true")]));
+        }
+        self.execute(&mut req)?;
+        Ok(())
+    }
+
+    // pull image:tag from the registry. the pull is done when the stream ends
+    pub fn pull(
+        &mut self,
+        image: &str,
+        tag: &str,
+    ) -> Result<JsonStream<'_, PullProgress>, HttpError> {
+        let mut req = self.request(HttpMethod::Post, "/images/create");
+        req.params(HttpParams::from_iter([("fromImage", image), ("tag", tag)]));
+        let resp = self.client.execute_streaming(&mut req)?;
+        if !resp.status.is_success() {
+            return Err(api_error(&resp.buffered()?));
+        }
+        Ok(JsonStream::new(
+            resp.into_body().unwrap_or_else(Body::empty),
+        ))
+    }
+
+    // send the directory as the build context. files excluded by .dockerignore
+    // are not sent. the build is done when the stream ends
+    pub fn build(
+        &mut self,
+        dir: impl AsRef<Path>,
+        options: &BuildOptions,
+    ) -> Result<JsonStream<'_, BuildOutput>, HttpError> {
+        let dir = dir.as_ref();
+        let dockerfile = options.dockerfile.as_deref().unwrap_or("Dockerfile");
+        let mut ignore = match std::fs::read_to_string(dir.join(".dockerignore")) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        // NOTE: the daemon needs them even if they are excluded, like `docker build`
+        for path in [dockerfile, ".dockerignore"] {
+            if DockerIgnore::parse(&ignore).is_excluded(path) {
+                ignore.push_str(&format!("\n!{}", path));
+            }
+        }
+        let mut archive = Archive::new();
+        archive.append_dir_all(dir, &DockerIgnore::parse(&ignore))?;
+
+        let mut params = HttpParams::new();
+        params.add("dockerfile", dockerfile);
+        if let Some(tag) = &options.tag {
+            params.add("t", tag);
+        }
+        if !options.build_args.is_empty() {
+            let args = serde_json::to_string(&options.build_args)
+                .map_err(|e| HttpError::Other(format!("invalid build args: {}", e)))?;
+            params.add("buildargs", &args);
+        }
+        if options.no_cache {
+            params.add("nocache", "true");
+        }
+        if options.pull {
+            params.add("pull", "true");
+        }
+        let mut header = HttpHeader::new();
+        header.add(headers::CONTENT_TYPE, "application/x-tar");
+
+        let mut req = self.request(HttpMethod::Post, "/build");
+        req.params(params)
+            .header(header)
+            .body_reader(archive.into_reader());
+        let resp = self.client.execute_streaming(&mut req)?;
+        if !resp.status.is_success() {
+            return Err(api_error(&resp.buffered()?));
+        }
+        Ok(JsonStream::new(
+            resp.into_body().unwrap_or_else(Body::empty),
+        ))
+    }
+
+    // run cmd in the running container and wait until it exits
+    pub fn exec(&mut self, container: &str, cmd: &[&str]) -> Result<ExecOutput, HttpError> {
+        let path = format!("/containers/{}/exec", percent::encode_component(container));
+        let mut req = self.request(HttpMethod::Post, &path);
+        req.json(&serde_json::json!({
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "Cmd": cmd,
+        }));
+        let exec: IdResponse = self.execute(&mut req)?.json()?;
+        let id = percent::encode_component(&exec.id);
+
+        // NOTE: the connection is hijacked for the output, so another one is used
+        let dialer = self
+            .dialer
+            .as_ref()
+            .ok_or_else(|| HttpError::Other("exec requires a dialer".into()))?;
+        let mut req = self.request(HttpMethod::Post, &format!("/exec/{}/start", id));
+        req.json(&serde_json::json!({ "Detach": false, "Tty": false }));
+        let (_, conn) = dialer()?.execute_request_upgrade(&mut req)?;
+        let mut output = ExecOutput::default();
+        std_copy(&mut output.stdout, &mut output.stderr, conn)?;
+
+        let mut req = self.request(HttpMethod::Get, &format!("/exec/{}/json", id));
+        let inspect: ExecInspect = self.execute(&mut req)?.json()?;
+        output.exit_code = inspect
+            .exit_code
+            .ok_or_else(|| HttpError::Other("exec is still running".into()))?;
+        Ok(output)
+    }
+
+    pub fn list_images(&mut self) -> Result<Vec<ImageSummary>, HttpError> {
+        let mut req = self.request(HttpMethod::Get, "/images/json");
+        self.execute(&mut req)?.json()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+    use crate::body::ChunkedReader;
+    use crate::test::MockConn;
+
+    fn sent(docker: &mut Docker<MockConn>) -> String {
+        String::from_utf8(docker.client().conn.get_ref().output.clone()).unwrap()
+    }
+
+    fn json_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn docker_list_containers() {
+        let body = r#"[{"Id":"8dfafdbc3a40","Names":["/boring_feynman"],"Image":"ubuntu:latest","ImageID":"sha256:d74508fb","Command":"echo 1","Created":1367854155,"State":"exited","Status":"Exit 0","Labels":{"a":"b"}}]"#;
+        let conn = MockConn::new(&json_response("200 OK", body));
+        let mut docker = Docker::new(HttpClient::new(conn));
+        let containers = docker.list_containers(true).unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].id, "8dfafdbc3a40");
+        assert_eq!(containers[0].names, ["/boring_feynman"]);
+        assert_eq!(containers[0].image_id, "sha256:d74508fb");
+        assert!(sent(&mut docker).starts_with("GET /v1.41/containers/json?all=true HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn docker_pull() {
+        let conn = MockConn::new(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n",
+                "2d\r\n{\"status\":\"Pulling from library/alpine\",\"id\":\r\n",
+                "7b\r\n\"3.20\"}\n{\"status\":\"Downloading\",\"progressDetail\":{\"current\":1024,\"total\":4096},\"progress\":\"[==>    ]\",\"id\":\"c6a83fedfae6\"}\n\r\n",
+                "0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 74\r\n\r\n",
+                "{\"errorDetail\":{\"message\":\"manifest unknown\"},\"error\":\"manifest unknown\"}\n",
+            ]
+            .concat(),
+        );
+        let mut docker = Docker::new(HttpClient::new(conn));
+        let records = docker
+            .pull("alpine", "3.20")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [
+                PullProgress {
+                    status: "Pulling from library/alpine".into(),
+                    id: Some("3.20".into()),
+                    ..Default::default()
+                },
+                PullProgress {
+                    status: "Downloading".into(),
+                    id: Some("c6a83fedfae6".into()),
+                    progress: Some("[==>    ]".into()),
+                    progress_detail: Some(ProgressDetail {
+                        current: Some(1024),
+                        total: Some(4096)
+                    }),
+                },
+            ]
+        );
+        assert!(docker.client().is_keep_alive());
+
+        let mut records = docker.pull("alpine", "missing").unwrap();
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "manifest unknown");
+        assert!(records.next().is_none());
+        drop(records);
+        assert!(sent(&mut docker)
+            .starts_with("POST /v1.41/images/create?fromImage=alpine&tag=3.20 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn docker_build() {
+        let dir = std::env::temp_dir().join(format!("unix_socket_build_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine\nCOPY . /app\n").unwrap();
+        std::fs::write(dir.join(".dockerignore"), "*\n!app.sh\n").unwrap();
+        std::fs::write(dir.join("app.sh"), "echo hello\n").unwrap();
+        std::fs::write(dir.join("target/app"), "binary").unwrap();
+
+        let body = [
+            r#"{"stream":"Step 1/2 : FROM alpine\n"}"#,
+            r#"{"stream":" ---\u003e 91ef0af61f39\n"}"#,
+            r#"{"aux":{"ID":"sha256:4a2fb7d6"}}"#,
+            "",
+        ]
+        .join("\n");
+        let conn = MockConn::new(&json_response("200 OK", &body));
+        let mut docker = Docker::new(HttpClient::new(conn));
+        let options = BuildOptions {
+            tag: Some("app:latest".into()),
+            build_args: HashMap::from([("VERSION".into(), "1".into())]),
+            ..Default::default()
+        };
+        let output = docker
+            .build(&dir, &options)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let lines = output
+            .iter()
+            .filter_map(|o| o.stream.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["Step 1/2 : FROM alpine\n", " ---> 91ef0af61f39\n"]);
+        assert_eq!(output[2].aux.as_ref().unwrap().id, "sha256:4a2fb7d6");
+
+        let sent = docker.client().conn.get_ref().output.clone();
+        let pos = sent.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8(sent[..pos].to_vec()).unwrap();
+        assert!(
+            head.starts_with("POST /v1.41/build?dockerfile=Dockerfile&t=app%3Alatest&buildargs=%7B%22VERSION%22%3A%221%22%7D HTTP/1.1\r\n"),
+            "{}",
+            head
+        );
+        assert!(head.contains("\r\nContent-Type: application/x-tar\r\n"));
+        assert!(head.contains("\r\nTransfer-Encoding: chunked\r\n"));
+
+        let mut context = Vec::new();
+        ChunkedReader::new(&sent[pos..])
+            .read_to_end(&mut context)
+            .unwrap();
+        let names = crate::tar::test::entries(&context)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, [".dockerignore", "Dockerfile", "app.sh"]);
+    }
+
+    #[test]
+    fn docker_exec() {
+        let conn = MockConn::new(
+            &[
+                json_response("201 Created", r#"{"Id":"f90e34656806"}"#),
+                json_response("200 OK", r#"{"Running":false,"ExitCode":2,"Pid":42}"#),
+            ]
+            .concat(),
+        );
+        let mut docker = Docker::new(HttpClient::new(conn));
+        docker.dialer(|| {
+            let mut output = b"HTTP/1.1 101 UPGRADED\r\nContent-Type: application/vnd.docker.multiplexed-stream\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n\r\n".to_vec();
+            output.extend_from_slice(b"\x01\0\0\0\0\0\0\x06hello\n\x02\0\0\0\0\0\0\x04err\n");
+            let conn = MockConn::new(std::str::from_utf8(&output).unwrap());
+            Ok(HttpClient::new(conn))
+        });
+        let output = docker.exec("web", &["sh", "-c", "exit 2"]).unwrap();
+        assert_eq!(
+            output,
+            ExecOutput {
+                stdout: b"hello\n".to_vec(),
+                stderr: b"err\n".to_vec(),
+                exit_code: 2,
+            }
+        );
+        let sent = sent(&mut docker);
+        assert!(sent.starts_with("POST /v1.41/containers/web/exec HTTP/1.1\r\n"));
+        assert!(sent.contains(r#""Cmd":["sh","-c","exit 2"]"#), "{}", sent);
+        assert!(sent.contains("GET /v1.41/exec/f90e34656806/json HTTP/1.1\r\n"));
+
+        let conn = MockConn::new(&json_response("201 Created", r#"{"Id":"a"}"#));
+        let err = Docker::new(HttpClient::new(conn))
+            .exec("web", &["true"])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "exec requires a dialer");
+    }
+
+    #[test]
+    fn docker_create_and_start() {
+        let conn = MockConn::new(
+            &[
+                json_response("201 Created", r#"{"Id":"e90e34656806","Warnings":[]}"#),
+                "HTTP/1.1 204 No Content\r\n\r\n".into(),
+                json_response(
+                    "404 Not Found",
+                    r#"{"message":"No such container: missing"}"#,
+                ),
+            ]
+            .concat(),
+        );
+        let mut docker = Docker::new(HttpClient::new(conn));
+        let config = ContainerConfig {
+            image: "alpine".into(),
+            cmd: Some(vec!["echo".into(), "hello".into()]),
+            ..Default::default()
+        };
+        let created = docker.create(Some("web"), &config).unwrap();
+        assert_eq!(created.id, "e90e34656806");
+        docker.start(&created.id).unwrap();
+        let err = docker.stop("missing", Some(5)).unwrap_err();
+        assert_eq!(err.to_string(), "404 Not Found: No such container: missing");
+
+        let sent = sent(&mut docker);
+        assert!(sent.starts_with("POST /v1.41/containers/create?name=web HTTP/1.1\r\n"));
+        assert!(sent.contains(
+            r#"{"Image":"alpine","Cmd":["echo","hello"],"Tty":false,"OpenStdin":false,"AttachStdin":false,"AttachStdout":false,"AttachStderr":false}"#
+        ));
+        assert!(sent.contains("POST /v1.41/containers/e90e34656806/start HTTP/1.1\r\n"));
+        assert!(sent.contains("POST /v1.41/containers/missing/stop?t=5 HTTP/1.1\r\n"));
+    }
+}
@@ -0,0 +1,205 @@
+// patterns of .dockerignore. a path is excluded when the last pattern which
+// matches it or one of its parent directories is not an exception (`!`)
+#[derive(Debug, Clone, Default)]
+pub struct DockerIgnore {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: String,
+    exception: bool,
+}
+
+impl DockerIgnore {
+    pub fn parse(s: &str) -> Self {
+        let mut patterns = Vec::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (line, exception) = match line.strip_prefix('!') {
+                Some(line) => (line.trim(), true),
+                None => (line, false),
+            };
+            let glob = clean(line);
+            if glob.is_empty() {
+                continue;
+            }
+            patterns.push(Pattern { glob, exception });
+        }
+        Self { patterns }
+    }
+
+    // whether an exception may re-include something under an excluded directory
+    pub fn has_exceptions(&self) -> bool {
+        self.patterns.iter().any(|p| p.exception)
+    }
+
+    // path is relative to the context directory and separated by `/`
+    pub fn is_excluded(&self, path: &str) -> bool {
+        let path = clean(path);
+        let mut excluded = false;
+        for pattern in self.patterns.iter() {
+            // only a pattern which flips the result matters
+            if excluded != pattern.exception {
+                continue;
+            }
+            let mut matched = glob_match(pattern.glob.as_bytes(), path.as_bytes());
+            // a pattern excludes everything under a matching directory
+            let mut parent = path.as_str();
+            while !matched {
+                let Some((dir, _)) = parent.rsplit_once('/') else {
+                    break;
+                };
+                matched = glob_match(pattern.glob.as_bytes(), dir.as_bytes());
+                parent = dir;
+            }
+            if matched {
+                excluded = !pattern.exception;
+            }
+        }
+        excluded
+    }
+}
+
+// like filepath.Clean of Go for relative paths, e.g. `/a/./b//c/` to `a/b/c`
+fn clean(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+// `*` and `?` don't match `/`, `**` matches any number of directories
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // zero or more directories
+            glob_match(rest, path)
+                || (0..path.len()).any(|i| path[i] == b'/' && glob_match(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        [b'*', rest @ ..] => {
+            for i in 0..=path.len() {
+                if glob_match(rest, &path[i..]) {
+                    return true;
+                }
+                if i < path.len() && path[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => glob_match(rest, path),
+            _ => false,
+        },
+        [b'[', rest @ ..] => match (path, match_class(rest, path.first().copied())) {
+            ([_, path @ ..], Some((true, rest))) => glob_match(rest, path),
+            _ => false,
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => match path {
+            [p, path @ ..] if p == c => glob_match(rest, path),
+            _ => false,
+        },
+    }
+}
+
+// match c against the class after `[`. returns whether it matched and the
+// pattern after `]`, or None when the class is not terminated
+fn match_class(pattern: &[u8], c: Option<u8>) -> Option<(bool, &[u8])> {
+    let (negated, mut pattern) = match pattern {
+        [b'^' | b'!', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    let c = c?;
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match pattern {
+            [b']', rest @ ..] if !first => {
+                return Some((matched != negated && c != b'/', rest));
+            }
+            [b'\\', lo, b'-', b'\\', hi, rest @ ..]
+            | [b'\\', lo, b'-', hi, rest @ ..]
+            | [lo, b'-', b'\\', hi, rest @ ..]
+            | [lo, b'-', hi, rest @ ..]
+                if *hi != b']' =>
+            {
+                matched |= (*lo..=*hi).contains(&c);
+                pattern = rest;
+            }
+            [b'\\', x, rest @ ..] | [x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        let tests = [
+            ("*.log", "a.log", true),
+            ("*.log", "dir/a.log", false),
+            ("*/*.log", "dir/a.log", true),
+            ("**/*.log", "a.log", true),
+            ("**/*.log", "a/b/c.log", true),
+            ("a/**", "a/b/c", true),
+            ("a/**/c", "a/c", true),
+            ("a/**/c", "a/b/b/c", true),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("[a-c].txt", "b.txt", true),
+            ("[^a-c].txt", "b.txt", false),
+            ("[!a-c].txt", "d.txt", true),
+            ("\\*.txt", "*.txt", true),
+            ("\\*.txt", "a.txt", false),
+            ("[a-c.txt", "b.txt", false),
+        ];
+        for (pattern, path, want) in tests {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), path.as_bytes()),
+                want,
+                "{} {}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn docker_ignore() {
+        let ignore = DockerIgnore::parse(
+            "# comment\n\n/target\n*.log\n!keep.log\nnode_modules/\n**/.git\n!docs/**/*.md\ndocs\n",
+        );
+        assert!(ignore.is_excluded("target"));
+        assert!(ignore.is_excluded("target/debug/app"));
+        assert!(ignore.is_excluded("a.log"));
+        assert!(!ignore.is_excluded("keep.log"));
+        assert!(!ignore.is_excluded("src/a.log"));
+        assert!(ignore.is_excluded("node_modules/x/index.js"));
+        assert!(ignore.is_excluded("vendor/lib/.git/HEAD"));
+        assert!(!ignore.is_excluded("src/main.rs"));
+        // the last matching pattern wins
+        assert!(ignore.is_excluded("docs/api/index.md"));
+        assert!(ignore.has_exceptions());
+        assert!(!DockerIgnore::parse("*.log").has_exceptions());
+    }
+}
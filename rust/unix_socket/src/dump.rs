@@ -0,0 +1,218 @@
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Send,
+    Recv,
+}
+
+// receives the exact bytes written to and read from the connection, see
+// HttpClient::dump
+pub trait Dump: Send + Sync {
+    fn dump(&self, direction: Direction, data: &[u8]);
+}
+
+impl<F> Dump for F
+where
+    F: Fn(Direction, &[u8]) + Send + Sync,
+{
+    fn dump(&self, direction: Direction, data: &[u8]) {
+        self(direction, data)
+    }
+}
+
+// writes the bytes in the format of `curl --trace`:
+//
+//     => Send data, 16 bytes (0x10)
+//     0000: 47 45 54 20 2f 20 48 54 54 50 2f 31 2e 31 0d 0a GET / HTTP/1.1..
+pub struct HexDump<W>(Mutex<W>);
+
+impl<W: Write + Send> HexDump<W> {
+    pub fn new(w: W) -> Self {
+        Self(Mutex::new(w))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<W: Write + Send> Dump for HexDump<W> {
+    fn dump(&self, direction: Direction, data: &[u8]) {
+        let mut w = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        // NOTE: a failing writer must not fail the request
+        let _ = write_hex(&mut *w, direction, data);
+    }
+}
+
+fn write_hex(w: &mut impl Write, direction: Direction, data: &[u8]) -> io::Result<()> {
+    let arrow = match direction {
+        Direction::Send => "=> Send",
+        Direction::Recv => "<= Recv",
+    };
+    writeln!(
+        w,
+        "{} data, {} bytes (0x{:x})",
+        arrow,
+        data.len(),
+        data.len()
+    )?;
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect::<String>();
+        writeln!(w, "{:04x}: {:<48}{}", i * 16, hex, ascii)?;
+    }
+    w.flush()
+}
+
+// the connection of HttpClient. passes what is read and written to the dump
+// when it is set, and counts the bytes for metrics
+pub struct DumpStream<T> {
+    inner: T,
+    dump: Option<Arc<dyn Dump>>,
+    read: u64,
+    written: u64,
+}
+
+impl<T> DumpStream<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            dump: None,
+            read: 0,
+            written: 0,
+        }
+    }
+
+    // total bytes (written, read)
+    pub fn counts(&self) -> (u64, u64) {
+        (self.written, self.read)
+    }
+
+    pub fn set_dump(&mut self, p: Option<Arc<dyn Dump>>) {
+        self.dump = p;
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for DumpStream<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for DumpStream<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: Read> Read for DumpStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if let Some(dump) = self.dump.as_ref().filter(|_| n > 0) {
+            dump.dump(Direction::Recv, &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for DumpStream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        if let Some(dump) = self.dump.as_ref().filter(|_| n > 0) {
+            dump.dump(Direction::Send, &buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        self.written += n as u64;
+        if let Some(dump) = &self.dump {
+            let mut rest = n;
+            for buf in bufs {
+                if rest == 0 {
+                    break;
+                }
+                let len = buf.len().min(rest);
+                if len > 0 {
+                    dump.dump(Direction::Send, &buf[..len]);
+                }
+                rest -= len;
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockConn;
+    use crate::{HttpClient, Request};
+
+    #[test]
+    fn dump_hex() {
+        let mut out = Vec::new();
+        write_hex(&mut out, Direction::Send, b"GET / HTTP/1.1\r\n\x00").unwrap();
+        let want = [
+            "=> Send data, 17 bytes (0x11)",
+            "0000: 47 45 54 20 2f 20 48 54 54 50 2f 31 2e 31 0d 0a GET / HTTP/1.1..",
+            "0010: 00                                              .",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(String::from_utf8(out).unwrap(), want);
+    }
+
+    #[test]
+    fn dump_client() {
+        let resp = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let mut client = HttpClient::new(MockConn::new(&resp.repeat(2)));
+        let dumped = Arc::new(Mutex::new((Vec::new(), Vec::new())));
+        let d = dumped.clone();
+        client.dump(Some(Arc::new(move |direction, data: &[u8]| {
+            let mut d = d.lock().unwrap();
+            match direction {
+                Direction::Send => d.0.extend_from_slice(data),
+                Direction::Recv => d.1.extend_from_slice(data),
+            }
+        })));
+        client.execute_request(&mut Request::get("/_ping")).unwrap();
+        {
+            let d = dumped.lock().unwrap();
+            assert_eq!(d.0, client.conn.get_ref().output);
+            assert!(d.1.starts_with(resp.as_bytes()));
+        }
+
+        // turned off
+        client.dump(None);
+        client.execute_request(&mut Request::get("/_ping")).unwrap();
+        let sent = client.conn.get_ref().output.len();
+        assert!(dumped.lock().unwrap().0.len() < sent);
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression as Level;
+
+// content codings of Content-Encoding and Accept-Encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "brotli")]
+    Brotli,
+}
+
+impl Encoding {
+    // supported encodings in the order of preference
+    pub const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd,
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli,
+        Encoding::Gzip,
+        Encoding::Deflate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.decoder(Box::new(data))?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    // decompress r as it is read
+    pub fn decoder<'a>(&self, r: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+        match self {
+            Self::Gzip => Ok(Box::new(GzDecoder::new(r))),
+            // NOTE: "deflate" is zlib wrapped, but some servers send raw deflate
+            Self::Deflate => {
+                let mut r = BufReader::new(r);
+                if is_zlib(r.fill_buf()?) {
+                    Ok(Box::new(ZlibDecoder::new(r)))
+                } else {
+                    Ok(Box::new(DeflateDecoder::new(r)))
+                }
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                let decoder = ruzstd::decoding::StreamingDecoder::new(r)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                Ok(Box::new(decoder))
+            }
+            #[cfg(feature = "brotli")]
+            Self::Brotli => Ok(Box::new(brotli_decompressor::Decompressor::new(r, 4096))),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            #[cfg(feature = "zstd")]
+            "zstd" => Ok(Self::Zstd),
+            #[cfg(feature = "brotli")]
+            "br" => Ok(Self::Brotli),
+            _ => Err(format!("unsupported content encoding: {}", s)),
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// content codings which a request body can be compressed with, see
+// Request::compress. NOTE: no brotli, only its decoder is built in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    // the value of Content-Encoding
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            Self::Gzip => Encoding::Gzip,
+            Self::Deflate => Encoding::Deflate,
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Encoding::Zstd,
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut w = GzEncoder::new(Vec::new(), Level::default());
+                w.write_all(data)?;
+                w.finish()
+            }
+            Self::Deflate => {
+                let mut w = ZlibEncoder::new(Vec::new(), Level::default());
+                w.write_all(data)?;
+                w.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(ruzstd::encoding::compress_to_vec(
+                data,
+                ruzstd::encoding::CompressionLevel::Fastest,
+            )),
+        }
+    }
+
+    // compress r as it is read
+    pub fn encoder(&self, r: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+        match self {
+            Self::Gzip => Box::new(flate2::read::GzEncoder::new(r, Level::default())),
+            Self::Deflate => Box::new(flate2::read::ZlibEncoder::new(r, Level::default())),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(ZstdEncoder::new(r)),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encoding())
+    }
+}
+
+// the uncompressed size of each zstd frame of ZstdEncoder
+#[cfg(feature = "zstd")]
+const ZSTD_FRAME_SIZE: usize = 128 * 1024;
+
+// compresses r as a sequence of zstd frames, which is valid zstd data (RFC
+// 8878 section 3.1), so that r is not read whole into memory
+#[cfg(feature = "zstd")]
+struct ZstdEncoder<R> {
+    r: R,
+    // the current frame
+    frame: io::Cursor<Vec<u8>>,
+    started: bool,
+    done: bool,
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> ZstdEncoder<R> {
+    fn new(r: R) -> Self {
+        Self {
+            r,
+            frame: io::Cursor::default(),
+            started: false,
+            done: false,
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> Read for ZstdEncoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.frame.read(buf)?;
+            if n > 0 || self.done || buf.is_empty() {
+                return Ok(n);
+            }
+            let mut data = Vec::new();
+            let read = (&mut self.r)
+                .take(ZSTD_FRAME_SIZE as u64)
+                .read_to_end(&mut data)?;
+            self.done = read < ZSTD_FRAME_SIZE;
+            // an empty body is still one frame
+            if read == 0 && self.started {
+                return Ok(0);
+            }
+            self.started = true;
+            self.frame = io::Cursor::new(ruzstd::encoding::compress_to_vec(
+                &data[..],
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ));
+        }
+    }
+}
+
+// value of Accept-Encoding, e.g. `gzip, deflate`
+pub fn accept_encoding() -> String {
+    Encoding::ALL
+        .iter()
+        .map(Encoding::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// undo the codings of Content-Encoding, see parse_content_encoding
+pub fn decode(content_encoding: &str, data: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    let encodings = parse_content_encoding(content_encoding)?;
+    let mut out = Vec::new();
+    let result = decoder(&encodings, Box::new(data))
+        .and_then(|mut r| r.read_to_end(&mut out))
+        .map(|_| out);
+    Some(result)
+}
+
+// codings of Content-Encoding in the order applied. None when the body uses a
+// coding this client doesn't know
+pub fn parse_content_encoding(content_encoding: &str) -> Option<Vec<Encoding>> {
+    content_encoding
+        .split(',')
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+        .map(|coding| coding.parse::<Encoding>().ok())
+        .collect()
+}
+
+// reader which undoes the encodings
+pub fn decoder<'a>(
+    encodings: &[Encoding],
+    r: Box<dyn Read + 'a>,
+) -> io::Result<Box<dyn Read + 'a>> {
+    encodings
+        .iter()
+        .rev()
+        .try_fold(r, |r, encoding| encoding.decoder(r))
+}
+
+// zlib header: CM is 8 (deflate) and the first two bytes are a multiple of 31
+fn is_zlib(header: &[u8]) -> bool {
+    match header {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_content_encoding() {
+        let mut gz = GzEncoder::new(Vec::new(), Level::default());
+        gz.write_all(b"hello").unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(decode("gzip", &gz).unwrap().unwrap(), b"hello");
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), Level::default());
+        zlib.write_all(&gz).unwrap();
+        let both = zlib.finish().unwrap();
+        assert_eq!(decode("gzip, deflate", &both).unwrap().unwrap(), b"hello");
+
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), Level::default());
+        raw.write_all(b"hello").unwrap();
+        let raw = raw.finish().unwrap();
+        assert_eq!(decode("deflate", &raw).unwrap().unwrap(), b"hello");
+
+        assert!(decode("compress", &gz).is_none());
+        assert!(decode("gzip", b"hello").unwrap().is_err());
+        assert_eq!(decode("identity", b"hello").unwrap().unwrap(), b"hello");
+        assert!(accept_encoding().ends_with("gzip, deflate"));
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let all = [
+            Compression::Gzip,
+            Compression::Deflate,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+        ];
+        for compression in all {
+            let encoding = compression.encoding();
+            let data = compression.encode(b"hello").unwrap();
+            assert_eq!(encoding.decode(&data).unwrap(), b"hello", "{}", encoding);
+
+            let mut data = Vec::new();
+            compression
+                .encoder(Box::new(&b"hello"[..]))
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(encoding.decode(&data).unwrap(), b"hello", "{}", encoding);
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_encoder_frames() {
+        let body = (0..ZSTD_FRAME_SIZE * 2 + 1)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut data = Vec::new();
+        ZstdEncoder::new(&body[..]).read_to_end(&mut data).unwrap();
+        let mut decoded = Vec::with_capacity(body.len());
+        ruzstd::decoding::FrameDecoder::new()
+            .decode_all_to_vec(&data, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        let mut data = Vec::new();
+        ZstdEncoder::new(io::empty())
+            .read_to_end(&mut data)
+            .unwrap();
+        assert!(!data.is_empty());
+        assert_eq!(Encoding::Zstd.decode(&data).unwrap(), b"");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn decode_zstd() {
+        let data = Compression::Zstd.encode(b"hello").unwrap();
+        assert_eq!(decode("zstd", &data).unwrap().unwrap(), b"hello");
+        assert!(decode("zstd", b"hello").unwrap().is_err());
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn decode_brotli() {
+        // "hello" as an uncompressed meta-block
+        let data = [0x0b, 0x02, 0x80, b'h', b'e', b'l', b'l', b'o', 0x03];
+        assert_eq!(decode("br", &data).unwrap().unwrap(), b"hello");
+    }
+}
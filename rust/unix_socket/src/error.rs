@@ -0,0 +1,139 @@
+use std::fmt::Display;
+use std::io;
+use std::time::Duration;
+
+use crate::status::StatusCode;
+
+#[derive(Debug)]
+pub enum HttpError {
+    // failed to open a connection to addr
+    Connect(String, io::Error),
+    // read from or write to the connection failed
+    Io(io::Error),
+    // connect, read or write did not finish within the configured timeout
+    Timeout,
+    // the request did not finish within Request::timeout
+    DeadlineExceeded,
+    // the response is not valid HTTP
+    Parse(String),
+    // the body is not valid as text or json, see Response::text
+    Decode(String),
+    // the response has non-2xx status, see Response::error_for_status
+    Status(StatusCode),
+    // limits of the response, see Limits
+    TooManyHeaders(usize),
+    HeaderLineTooLong(usize),
+    BodyTooLarge(u64),
+    ChunkTooLarge(u64),
+    // redirects were followed more than the limit
+    TooManyRedirects(usize),
+    // the connection can't be used anymore, e.g. after `Connection: close`
+    ConnectionClosed,
+    // the request was aborted by CancelToken::cancel
+    Cancelled,
+    // rejected by RateLimiter, with the time until a request is allowed
+    RateLimited(Duration),
+    // rejected by CircuitBreaker while the destination is failing, with the
+    // time until a trial request is allowed
+    CircuitOpen(Duration),
+    Other(String),
+}
+
+impl HttpError {
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Timeout | Self::DeadlineExceeded => true,
+            Self::Connect(_, e) => is_timeout(e),
+            _ => false,
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    // read and write timeouts are reported as WouldBlock on unix
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connect(addr, e) => write!(f, "cannot connect to {}: {}", addr, e),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Timeout => write!(f, "timed out"),
+            Self::DeadlineExceeded => write!(f, "deadline exceeded"),
+            Self::Parse(msg) => write!(f, "{}", msg),
+            Self::Decode(msg) => write!(f, "{}", msg),
+            Self::Status(status) => write!(f, "unexpected status: {}", status),
+            Self::TooManyHeaders(max) => write!(f, "too many headers (max {})", max),
+            Self::HeaderLineTooLong(max) => write!(f, "header line too long (max {} bytes)", max),
+            Self::BodyTooLarge(max) => write!(f, "body too large (max {} bytes)", max),
+            Self::ChunkTooLarge(max) => write!(f, "chunk too large (max {} bytes)", max),
+            Self::TooManyRedirects(max) => write!(f, "too many redirects (max {})", max),
+            Self::ConnectionClosed => write!(f, "connection is closed"),
+            Self::Cancelled => write!(f, "request cancelled"),
+            Self::RateLimited(wait) => write!(f, "rate limited, retry after {:?}", wait),
+            Self::CircuitOpen(wait) => write!(f, "circuit open, retry after {:?}", wait),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connect(_, e) | Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        if is_timeout(&e) {
+            return Self::Timeout;
+        }
+        // HttpError passed through Read, e.g. by LimitReader
+        if e.get_ref().is_some_and(|inner| inner.is::<HttpError>()) {
+            let inner = e.into_inner().unwrap();
+            return *inner.downcast::<HttpError>().unwrap();
+        }
+        Self::Io(e)
+    }
+}
+
+impl From<String> for HttpError {
+    fn from(e: String) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<&str> for HttpError {
+    fn from(e: &str) -> Self {
+        Self::Other(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_error_kind() {
+        let err = HttpError::from(io::Error::from(io::ErrorKind::WouldBlock));
+        assert!(matches!(err, HttpError::Timeout));
+        assert!(err.is_timeout());
+
+        let err = HttpError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(matches!(err, HttpError::Io(_)));
+        assert!(!err.is_timeout());
+
+        let err = HttpError::Connect(
+            "127.0.0.1:2375".into(),
+            io::Error::from(io::ErrorKind::TimedOut),
+        );
+        assert!(err.is_timeout());
+    }
+}
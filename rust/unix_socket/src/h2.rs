@@ -0,0 +1,845 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{BufReader, Read, Write};
+
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::status::StatusCode;
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsConfig};
+use crate::transport::{self, Timeouts, Transport};
+use crate::url::Url;
+use crate::{headers, hpack, HttpHeader, HttpVersion, ReadWriter, Request, Response};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// settings
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+const DEFAULT_WINDOW_SIZE: i64 = 65535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+// SETTINGS_MAX_FRAME_SIZE of both sides unless the server changes its own
+const DEFAULT_MAX_FRAME_SIZE: usize = 16384;
+
+// header fields which are only meaningful for HTTP/1 (RFC 9113 8.2.2)
+const CONNECTION_HEADERS: [&str; 6] = [
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub type StreamId = u32;
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: StreamId,
+    payload: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Stream {
+    // None until the final response head is received
+    head: Option<Response>,
+    body: Vec<u8>,
+    // END_STREAM or RST_STREAM is received
+    done: bool,
+    // error code of RST_STREAM
+    reset: Option<u32>,
+    send_window: i64,
+}
+
+fn protocol_error(msg: impl Display) -> HttpError {
+    HttpError::Parse(format!("http2: {}", msg))
+}
+
+// name of the error code of RST_STREAM and GOAWAY
+fn error_name(code: u32) -> String {
+    let name = match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        code => return format!("error code {:#x}", code),
+    };
+    name.into()
+}
+
+// HTTP/2 connection (RFC 9113). several requests can be in flight at once:
+// send them with send_request and receive the responses with recv_response in
+// any order. frames of other streams are buffered meanwhile
+pub struct H2Connection<T: ReadWriter> {
+    conn: BufReader<T>,
+    decoder: hpack::Decoder,
+    streams: HashMap<StreamId, Stream>,
+    next_stream_id: StreamId,
+    // flow control window of the connection for sending DATA
+    send_window: i64,
+    // settings of the server
+    initial_window_size: i64,
+    max_frame_size: usize,
+    max_concurrent_streams: Option<usize>,
+    // the last stream processed by the server, which sent GOAWAY
+    goaway: Option<StreamId>,
+    // the connection can't be used after a connection error
+    broken: bool,
+    base_url: Option<Url>,
+    limits: Limits,
+}
+
+impl<T: ReadWriter> H2Connection<T> {
+    // start HTTP/2 with prior knowledge, e.g. h2c over a unix socket
+    pub fn handshake(conn: T) -> Result<Self, HttpError> {
+        let mut h2 = Self {
+            conn: BufReader::new(conn),
+            decoder: hpack::Decoder::new(),
+            streams: HashMap::new(),
+            next_stream_id: 1,
+            send_window: DEFAULT_WINDOW_SIZE,
+            initial_window_size: DEFAULT_WINDOW_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_concurrent_streams: None,
+            goaway: None,
+            broken: false,
+            base_url: None,
+            limits: Limits::default(),
+        };
+        h2.conn.get_mut().write_all(PREFACE)?;
+        // NOTE: server push is disabled, so PUSH_PROMISE is a connection error
+        let mut settings = Vec::new();
+        settings.extend_from_slice(&SETTINGS_ENABLE_PUSH.to_be_bytes());
+        settings.extend_from_slice(&0u32.to_be_bytes());
+        h2.write_frame(SETTINGS, 0, 0, &settings)?;
+        Ok(h2)
+    }
+
+    // relative request urls are resolved against this url
+    pub fn base_url(&mut self, p: Url) -> &mut Self {
+        self.base_url = Some(p);
+        self
+    }
+
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    pub fn execute_request(&mut self, req: &mut Request) -> Result<Response, HttpError> {
+        let id = self.send_request(req)?;
+        self.recv_response(id)
+    }
+
+    // send the request on a new stream. the response is received by recv_response
+    pub fn send_request(&mut self, req: &mut Request) -> Result<StreamId, HttpError> {
+        if self.broken || self.goaway.is_some() {
+            return Err(HttpError::ConnectionClosed);
+        }
+        if let Some(base_url) = &self.base_url {
+            if !req.url.is_absolute() {
+                req.url = base_url.resolve(&req.url);
+            }
+        }
+        req.validate()?;
+        while self
+            .max_concurrent_streams
+            .is_some_and(|max| self.streams.values().filter(|s| !s.done).count() >= max)
+        {
+            self.process_frame()?;
+        }
+
+        let id = self.next_stream_id;
+        self.next_stream_id += 2;
+        self.streams.insert(
+            id,
+            Stream {
+                send_window: self.initial_window_size,
+                ..Default::default()
+            },
+        );
+        let result = self.write_request(id, req);
+        if result.is_err() {
+            self.broken = true;
+            self.streams.remove(&id);
+        }
+        result.map(|_| id)
+    }
+
+    fn write_request(&mut self, id: StreamId, req: &mut Request) -> Result<(), HttpError> {
+        let data = req.encoded_body()?.map(|data| data.into_owned());
+        let fields = request_fields(req, data.as_ref().map(|d| d.len() as u64))?;
+        let block = hpack::encode(fields.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let has_body = data.is_some() || req.body_reader.is_some();
+        self.write_headers(id, &block, !has_body)?;
+
+        if let Some(data) = data {
+            self.write_data(id, &data, true)?;
+        } else if let Some(r) = req.take_body_reader() {
+            let mut r = match req.compress {
+                Some(encoding) => encoding.encoder(r),
+                None => r,
+            };
+            let mut buf = vec![0u8; DEFAULT_MAX_FRAME_SIZE];
+            let mut sent = 0u64;
+            loop {
+                let n = r.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                self.write_data(id, &buf[..n], false)?;
+                sent += n as u64;
+                if let Some(progress) = req.progress.as_mut() {
+                    progress(sent);
+                }
+            }
+            self.write_data(id, &[], true)?;
+        }
+        Ok(())
+    }
+
+    fn write_headers(
+        &mut self,
+        id: StreamId,
+        block: &[u8],
+        end_stream: bool,
+    ) -> Result<(), HttpError> {
+        let mut chunks = block.chunks(self.max_frame_size).peekable();
+        let mut kind = HEADERS;
+        let mut flags = if end_stream { END_STREAM } else { 0 };
+        if chunks.peek().is_none() {
+            return self.write_frame(kind, flags | END_HEADERS, id, &[]);
+        }
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_none() {
+                flags |= END_HEADERS;
+            }
+            self.write_frame(kind, flags, id, chunk)?;
+            kind = CONTINUATION;
+            flags = 0;
+        }
+        Ok(())
+    }
+
+    // send DATA frames within the flow control windows. frames are read from
+    // the server while the window is exhausted
+    fn write_data(
+        &mut self,
+        id: StreamId,
+        mut data: &[u8],
+        end_stream: bool,
+    ) -> Result<(), HttpError> {
+        loop {
+            let window = match self.streams.get(&id) {
+                Some(stream) if stream.done => {
+                    // the server responded early or reset the stream
+                    return Ok(());
+                }
+                Some(stream) => stream.send_window.min(self.send_window),
+                None => return Err(HttpError::ConnectionClosed),
+            };
+            if window <= 0 && !data.is_empty() {
+                self.process_frame()?;
+                continue;
+            }
+            let n = data
+                .len()
+                .min(window.max(0) as usize)
+                .min(self.max_frame_size);
+            let (chunk, rest) = data.split_at(n);
+            let flags = if end_stream && rest.is_empty() {
+                END_STREAM
+            } else {
+                0
+            };
+            self.write_frame(DATA, flags, id, chunk)?;
+            self.send_window -= n as i64;
+            if let Some(stream) = self.streams.get_mut(&id) {
+                stream.send_window -= n as i64;
+            }
+            data = rest;
+            if data.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        id: StreamId,
+        payload: &[u8],
+    ) -> Result<(), HttpError> {
+        let mut frame = Vec::with_capacity(9 + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        frame.push(kind);
+        frame.push(flags);
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        let conn = self.conn.get_mut();
+        conn.write_all(&frame)?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    // wait for the response of the stream
+    pub fn recv_response(&mut self, id: StreamId) -> Result<Response, HttpError> {
+        loop {
+            match self.streams.get(&id) {
+                Some(stream) if stream.done => break,
+                Some(_) if self.goaway.is_some_and(|last| id > last) => {
+                    self.streams.remove(&id);
+                    return Err(HttpError::ConnectionClosed);
+                }
+                Some(_) => {}
+                None => return Err(protocol_error(format!("unknown stream: {}", id))),
+            }
+            if let Err(e) = self.process_frame() {
+                self.streams.remove(&id);
+                return Err(e);
+            }
+        }
+        let stream = self.streams.remove(&id).unwrap_or_default();
+        if let Some(code) = stream.reset {
+            return Err(protocol_error(format!(
+                "stream reset: {}",
+                error_name(code)
+            )));
+        }
+        let resp = stream
+            .head
+            .ok_or_else(|| protocol_error("missing response headers"))?;
+        Ok(resp.with_body(Some(stream.body.into())))
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, HttpError> {
+        let mut head = [0u8; 9];
+        self.conn.read_exact(&mut head)?;
+        let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        if len > DEFAULT_MAX_FRAME_SIZE {
+            return Err(protocol_error(format!("frame too large: {} bytes", len)));
+        }
+        let stream_id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff;
+        let mut payload = vec![0u8; len];
+        self.conn.read_exact(&mut payload)?;
+        Ok(Frame {
+            kind: head[3],
+            flags: head[4],
+            stream_id,
+            payload,
+        })
+    }
+
+    // read a frame and update the state of the connection and streams
+    fn process_frame(&mut self) -> Result<(), HttpError> {
+        if self.broken {
+            return Err(HttpError::ConnectionClosed);
+        }
+        let result = self.read_frame().and_then(|frame| self.handle_frame(frame));
+        if result.is_err() {
+            self.broken = true;
+        }
+        result
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<(), HttpError> {
+        let Frame {
+            kind,
+            flags,
+            stream_id,
+            payload,
+        } = frame;
+        match kind {
+            DATA => {
+                let len = payload.len();
+                let data = strip_padding(flags, payload)?;
+                let max_body_size = self.limits.max_body_size;
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    if max_body_size
+                        .is_some_and(|max| (stream.body.len() + data.len()) as u64 > max)
+                    {
+                        return Err(HttpError::BodyTooLarge(max_body_size.unwrap_or_default()));
+                    }
+                    stream.body.extend_from_slice(&data);
+                    stream.done |= flags & END_STREAM != 0;
+                }
+                // NOTE: the body is buffered, so the window is given back at once
+                if len > 0 {
+                    self.write_frame(WINDOW_UPDATE, 0, 0, &(len as u32).to_be_bytes())?;
+                    if flags & END_STREAM == 0 {
+                        self.write_frame(WINDOW_UPDATE, 0, stream_id, &(len as u32).to_be_bytes())?;
+                    }
+                }
+            }
+            HEADERS => {
+                let mut block = strip_padding(flags, payload)?;
+                if flags & PRIORITY != 0 {
+                    if block.len() < 5 {
+                        return Err(protocol_error("invalid HEADERS frame"));
+                    }
+                    block.drain(..5);
+                }
+                // the block is bounded like an HTTP/1.1 head, since
+                // CONTINUATION frames can follow without end
+                let max_block = self
+                    .limits
+                    .max_headers
+                    .saturating_mul(self.limits.max_header_line);
+                let mut end_headers = flags & END_HEADERS != 0;
+                while !end_headers {
+                    let next = self.read_frame()?;
+                    if next.kind != CONTINUATION || next.stream_id != stream_id {
+                        return Err(protocol_error("expected CONTINUATION frame"));
+                    }
+                    if block.len() + next.payload.len() > max_block {
+                        return Err(protocol_error(format!(
+                            "header block too large (max {} bytes)",
+                            max_block
+                        )));
+                    }
+                    block.extend_from_slice(&next.payload);
+                    end_headers = next.flags & END_HEADERS != 0;
+                }
+                // NOTE: the block is decoded even for unknown streams to keep
+                // the dynamic table in sync
+                let fields = self.decoder.decode(&block).map_err(protocol_error)?;
+                if fields.len() > self.limits.max_headers {
+                    return Err(HttpError::TooManyHeaders(self.limits.max_headers));
+                }
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    match &mut stream.head {
+                        Some(head) => {
+                            for (k, v) in fields.iter().filter(|(k, _)| !k.starts_with(':')) {
+                                head.trailers.append(k, v);
+                            }
+                        }
+                        None => stream.head = response_head(fields)?,
+                    }
+                    stream.done |= flags & END_STREAM != 0;
+                }
+            }
+            RST_STREAM => {
+                let code = read_u32(&payload)?;
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.done = true;
+                    stream.reset = Some(code);
+                }
+            }
+            SETTINGS if flags & ACK != 0 => {}
+            SETTINGS => {
+                if payload.len() % 6 != 0 {
+                    return Err(protocol_error("invalid SETTINGS frame"));
+                }
+                for setting in payload.chunks(6) {
+                    let id = u16::from_be_bytes([setting[0], setting[1]]);
+                    let value = read_u32(&setting[2..])?;
+                    match id {
+                        SETTINGS_INITIAL_WINDOW_SIZE => {
+                            if value as i64 > MAX_WINDOW_SIZE {
+                                return Err(protocol_error("invalid initial window size"));
+                            }
+                            let delta = value as i64 - self.initial_window_size;
+                            for stream in self.streams.values_mut() {
+                                stream.send_window += delta;
+                            }
+                            self.initial_window_size = value as i64;
+                        }
+                        SETTINGS_MAX_FRAME_SIZE => {
+                            if !(DEFAULT_MAX_FRAME_SIZE as u32..1 << 24).contains(&value) {
+                                return Err(protocol_error("invalid max frame size"));
+                            }
+                            self.max_frame_size = value as usize;
+                        }
+                        SETTINGS_MAX_CONCURRENT_STREAMS => {
+                            self.max_concurrent_streams = Some(value as usize);
+                        }
+                        // the header table size doesn't matter since the
+                        // encoder doesn't use the dynamic table
+                        _ => {}
+                    }
+                }
+                self.write_frame(SETTINGS, ACK, 0, &[])?;
+            }
+            PING if flags & ACK != 0 => {}
+            PING => self.write_frame(PING, ACK, 0, &payload)?,
+            GOAWAY => {
+                let last = read_u32(&payload)? & 0x7fff_ffff;
+                self.goaway = Some(last);
+                let code = read_u32(payload.get(4..).unwrap_or_default())?;
+                if code != 0 {
+                    return Err(protocol_error(format!("GOAWAY: {}", error_name(code))));
+                }
+            }
+            WINDOW_UPDATE => {
+                let increment = (read_u32(&payload)? & 0x7fff_ffff) as i64;
+                let window = match stream_id {
+                    0 => &mut self.send_window,
+                    id => match self.streams.get_mut(&id) {
+                        Some(stream) => &mut stream.send_window,
+                        None => return Ok(()),
+                    },
+                };
+                *window += increment;
+                if *window > MAX_WINDOW_SIZE {
+                    return Err(protocol_error("flow control window overflow"));
+                }
+            }
+            PUSH_PROMISE => return Err(protocol_error("unexpected PUSH_PROMISE")),
+            // PRIORITY and unknown frames are ignored
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl H2Connection<Transport> {
+    // h2c with prior knowledge, e.g. H2Connection::connect("unix:///run/h2.sock")
+    pub fn connect(url: &str) -> Result<Self, HttpError> {
+        let url = Url::parse(url)?;
+        let conn = transport::connect(&url)?;
+        let mut h2 = Self::handshake(conn)?;
+        h2.base_url(transport::base_url(&url));
+        Ok(h2)
+    }
+
+    // h2 over TLS negotiated by ALPN
+    #[cfg(feature = "tls")]
+    pub fn connect_tls(url: &str, config: &TlsConfig) -> Result<Self, HttpError> {
+        let mut url = Url::parse(url)?;
+        url.port = url.port.or(Some(443));
+        let mut config = config.clone();
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let conn = transport::connect_tcp(&url, &Timeouts::default())?;
+        let host = url.host.as_deref().unwrap_or_default();
+        let mut conn = tls::connect(conn, host, &config)?;
+        if tls::handshake(&mut conn)?.as_deref() != Some(b"h2") {
+            return Err(format!("{} doesn't support h2", url).into());
+        }
+        let mut h2 = Self::handshake(Box::new(conn) as Transport)?;
+        url.scheme = Some("https".into());
+        h2.base_url(transport::base_url(&url));
+        Ok(h2)
+    }
+}
+
+fn read_u32(b: &[u8]) -> Result<u32, HttpError> {
+    match b {
+        [a, b, c, d, ..] => Ok(u32::from_be_bytes([*a, *b, *c, *d])),
+        _ => Err(protocol_error("frame too short")),
+    }
+}
+
+fn strip_padding(flags: u8, mut payload: Vec<u8>) -> Result<Vec<u8>, HttpError> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    let pad = *payload
+        .first()
+        .ok_or_else(|| protocol_error("invalid padding"))? as usize;
+    if pad + 1 > payload.len() {
+        return Err(protocol_error("invalid padding"));
+    }
+    payload.truncate(payload.len() - pad);
+    payload.remove(0);
+    Ok(payload)
+}
+
+// pseudo-header fields and header fields of the request
+fn request_fields(req: &Request, length: Option<u64>) -> Result<Vec<(String, String)>, HttpError> {
+    let scheme = match req.url.scheme.as_deref() {
+        Some("https") => "https",
+        _ => "http",
+    };
+    let authority = req.host_header();
+    let mut fields = vec![
+        (":method".to_string(), req.method.to_string()),
+        (":scheme".into(), scheme.into()),
+        (":authority".into(), authority),
+        (":path".into(), req.target()?),
+    ];
+    let is_compressed = req.is_compressed();
+    for (k, v) in req.header.iter().flat_map(HttpHeader::iter) {
+        let k = k.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&k.as_str())
+            || (k == "te" && !v.eq_ignore_ascii_case("trailers"))
+            || (is_compressed && k == "content-length")
+        {
+            continue;
+        }
+        fields.push((k, v.into()));
+    }
+    if let Some(encoding) = req.compress.filter(|_| is_compressed) {
+        if !req.has_header(headers::CONTENT_ENCODING) {
+            fields.push(("content-encoding".into(), encoding.to_string()));
+        }
+    }
+    if let Some(length) = length {
+        if is_compressed || !req.has_header(headers::CONTENT_LENGTH) {
+            fields.push(("content-length".into(), length.to_string()));
+        }
+    }
+    Ok(fields)
+}
+
+// None for 1xx informational responses
+fn response_head(fields: Vec<(String, String)>) -> Result<Option<Response>, HttpError> {
+    let mut status = None;
+    let mut header = HttpHeader::new();
+    for (k, v) in fields {
+        match k.as_str() {
+            ":status" => {
+                let code = v
+                    .parse::<u16>()
+                    .map_err(|_| protocol_error(format!("invalid status: {}", v)))?;
+                status = Some(StatusCode::from_u16(code).map_err(protocol_error)?);
+            }
+            k if k.starts_with(':') => {}
+            k => header.append(k, &v),
+        }
+    }
+    let status = status.ok_or_else(|| protocol_error("missing :status"))?;
+    if status.is_informational() {
+        return Ok(None);
+    }
+    Ok(Some(Response {
+        version: HttpVersion::Http2,
+        status,
+        reason: status.canonical_reason().unwrap_or_default().into(),
+        header,
+        trailers: HttpHeader::new(),
+        body: None,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockConn;
+    use crate::HttpMethod;
+
+    fn frame(kind: u8, flags: u8, id: StreamId, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[kind, flags]);
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn headers_frame(id: StreamId, flags: u8, fields: &[(&str, &str)]) -> Vec<u8> {
+        frame(
+            HEADERS,
+            flags | END_HEADERS,
+            id,
+            &hpack::encode(fields.iter().copied()),
+        )
+    }
+
+    // frames sent by the client after the preface
+    fn sent_frames(output: &[u8]) -> Vec<Frame> {
+        assert!(output.starts_with(PREFACE));
+        let mut data = &output[PREFACE.len()..];
+        let mut frames = Vec::new();
+        while !data.is_empty() {
+            let len = u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize;
+            frames.push(Frame {
+                kind: data[3],
+                flags: data[4],
+                stream_id: u32::from_be_bytes([data[5], data[6], data[7], data[8]]),
+                payload: data[9..9 + len].to_vec(),
+            });
+            data = &data[9 + len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn h2_multiplexing() {
+        let input = [
+            frame(SETTINGS, 0, 0, &[]),
+            frame(SETTINGS, ACK, 0, &[]),
+            // the responses are interleaved
+            headers_frame(3, 0, &[(":status", "200"), ("content-type", "text/plain")]),
+            headers_frame(1, 0, &[(":status", "103")]),
+            headers_frame(1, 0, &[(":status", "404")]),
+            frame(DATA, 0, 3, b"sec"),
+            frame(PING, 0, 0, b"12345678"),
+            frame(DATA, END_STREAM, 1, b"not found"),
+            frame(DATA, PADDED | END_STREAM, 3, b"\x02ond\0\0"),
+        ]
+        .concat();
+        let mut h2 = H2Connection::handshake(MockConn::from_bytes(input)).unwrap();
+        let first = h2
+            .send_request(&mut Request::get("/containers/a/json"))
+            .unwrap();
+        let second = h2.send_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!((first, second), (1, 3));
+
+        let resp = h2.recv_response(second).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        assert_eq!(resp.version, HttpVersion::Http2);
+        assert_eq!(resp.header.get("Content-Type").unwrap(), "text/plain");
+        assert_eq!(resp.body.unwrap(), b"second");
+        let resp = h2.recv_response(first).unwrap();
+        assert_eq!(resp.status, StatusCode::NOT_FOUND);
+        assert_eq!(resp.body.unwrap(), b"not found");
+
+        let frames = sent_frames(&h2.conn.get_ref().output);
+        let kinds = frames
+            .iter()
+            .map(|f| (f.kind, f.flags, f.stream_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (SETTINGS, 0, 0),
+                (HEADERS, END_STREAM | END_HEADERS, 1),
+                (HEADERS, END_STREAM | END_HEADERS, 3),
+                (SETTINGS, ACK, 0),
+                (WINDOW_UPDATE, 0, 0),
+                (WINDOW_UPDATE, 0, 3),
+                (PING, ACK, 0),
+                (WINDOW_UPDATE, 0, 0),
+                (WINDOW_UPDATE, 0, 0),
+            ]
+        );
+        let fields = hpack::Decoder::new().decode(&frames[1].payload).unwrap();
+        assert_eq!(
+            fields[..4],
+            [
+                (":method".to_string(), "GET".to_string()),
+                (":scheme".into(), "http".into()),
+                (":authority".into(), "localhost".into()),
+                (":path".into(), "/containers/a/json".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn h2_flow_control() {
+        let data_frames = |output: &[u8]| {
+            sent_frames(output)
+                .iter()
+                .filter(|f| f.kind == DATA)
+                .map(|f| f.payload.len())
+                .collect::<Vec<_>>()
+        };
+        let input = [
+            frame(WINDOW_UPDATE, 0, 0, &10u32.to_be_bytes()),
+            frame(WINDOW_UPDATE, 0, 1, &10u32.to_be_bytes()),
+            headers_frame(1, 0, &[(":status", "201")]),
+            frame(DATA, END_STREAM, 1, b"{}"),
+        ]
+        .concat();
+        let mut h2 = H2Connection::handshake(MockConn::from_bytes(input)).unwrap();
+        let mut req = Request::new("/containers/create");
+        let mut header = HttpHeader::new();
+        header.add("Connection", "keep-alive");
+        req.method(HttpMethod::Post)
+            .header(header)
+            .body(vec![b'x'; 65535 + 10]);
+        let resp = h2.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status, StatusCode::CREATED);
+        // the rest is sent after the windows are updated
+        let output = &h2.conn.get_ref().output;
+        assert_eq!(data_frames(output), [16384, 16384, 16384, 16383, 10]);
+        let fields = hpack::Decoder::new()
+            .decode(&sent_frames(output)[1].payload)
+            .unwrap();
+        assert!(fields.contains(&("content-length".into(), "65545".into())));
+        assert!(!fields.iter().any(|(k, _)| k == "connection"));
+
+        // the server responds before the body is sent completely
+        let input = [
+            frame(SETTINGS, 0, 0, &[0, 4, 0, 0, 0, 10]),
+            frame(WINDOW_UPDATE, 0, 1, &6u32.to_be_bytes()),
+            headers_frame(1, END_STREAM, &[(":status", "413")]),
+        ]
+        .concat();
+        let mut h2 = H2Connection::handshake(MockConn::from_bytes(input)).unwrap();
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post).body(vec![b'x'; 70000]);
+        let resp = h2.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status.as_u16(), 413);
+        assert_eq!(
+            data_frames(&h2.conn.get_ref().output),
+            [16384, 16384, 16384, 16383]
+        );
+    }
+
+    #[test]
+    fn h2_continuation_limit() {
+        let input = [
+            frame(HEADERS, 0, 1, &[0; 10]),
+            frame(CONTINUATION, 0, 1, &[0; 10]),
+            frame(CONTINUATION, 0, 1, &[0; 10]),
+        ]
+        .concat();
+        let mut h2 = H2Connection::handshake(MockConn::from_bytes(input)).unwrap();
+        h2.limits(Limits {
+            max_headers: 2,
+            max_header_line: 10,
+            ..Limits::default()
+        });
+        let id = h2.send_request(&mut Request::get("/")).unwrap();
+        let err = h2.recv_response(id).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "http2: header block too large (max 20 bytes)"
+        );
+    }
+
+    #[test]
+    fn h2_stream_errors() {
+        let input = [
+            frame(RST_STREAM, 0, 1, &7u32.to_be_bytes()),
+            frame(GOAWAY, 0, 0, &[0, 0, 0, 1, 0, 0, 0, 0]),
+        ]
+        .concat();
+        let mut h2 = H2Connection::handshake(MockConn::from_bytes(input)).unwrap();
+        let first = h2.send_request(&mut Request::get("/a")).unwrap();
+        let second = h2.send_request(&mut Request::get("/b")).unwrap();
+        let err = h2.recv_response(first).unwrap_err();
+        assert_eq!(err.to_string(), "http2: stream reset: REFUSED_STREAM");
+        assert!(matches!(
+            h2.recv_response(second),
+            Err(HttpError::ConnectionClosed)
+        ));
+        assert!(matches!(
+            h2.send_request(&mut Request::get("/c")),
+            Err(HttpError::ConnectionClosed)
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// wait before racing the next address, RFC 8305 section 5
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// connect to the first address which answers, e.g. the A and AAAA records of a
+// host. the attempts are started one by one ATTEMPT_DELAY apart, or as soon as
+// the previous one fails, so that a broken IPv6 route doesn't hang the
+// connection. timeout applies to each attempt
+// NOTE: the attempts which lose the race are closed when they finish
+pub fn connect(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut addrs = interleave(addrs).into_iter().peekable();
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    let mut last_err = None;
+    loop {
+        if let Some(addr) = addrs.next() {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let result = match timeout {
+                    Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                    None => TcpStream::connect(addr),
+                };
+                let _ = tx.send(result);
+            });
+            pending += 1;
+        }
+        if pending == 0 {
+            break;
+        }
+        let result = match addrs.peek().is_some() {
+            true => rx.recv_timeout(ATTEMPT_DELAY),
+            // every attempt ends by itself
+            false => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match result {
+            Ok(Ok(conn)) => return Ok(conn),
+            Ok(Err(e)) => {
+                pending -= 1;
+                last_err = Some(e);
+            }
+            Err(_) => {}
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "could not resolve address")
+    }))
+}
+
+// alternate the address families, starting with the family of the first
+// address as the resolver prefers it
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_v6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == first_v6);
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => return result,
+            (a, b) => result.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn happy_eyeballs_interleave() {
+        let addrs = [
+            "[::1]:80",
+            "[::2]:80",
+            "[::3]:80",
+            "127.0.0.1:80",
+            "127.0.0.2:80",
+        ]
+        .iter()
+        .map(|a| a.parse().unwrap())
+        .collect();
+        let got = interleave(addrs)
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            got,
+            [
+                "[::1]:80",
+                "127.0.0.1:80",
+                "[::2]:80",
+                "127.0.0.2:80",
+                "[::3]:80"
+            ]
+        );
+    }
+
+    #[test]
+    fn happy_eyeballs_fallback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // nothing listens on the first ones
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let addrs = vec![
+            closed,
+            format!("[::1]:{}", port).parse().unwrap(),
+            format!("127.0.0.1:{}", port).parse().unwrap(),
+        ];
+        let start = Instant::now();
+        let conn = connect(addrs, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(conn.peer_addr().unwrap().port(), port);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let err = connect(vec![closed], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
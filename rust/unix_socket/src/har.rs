@@ -0,0 +1,368 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::{base64, headers, HttpHeader, Request, Response};
+
+// records the requests of a client as HTTP Archive (HAR 1.2), e.g. to share a
+// reproduction of a daemon bug. bodies are cut at max_body_size:
+//
+//     let har = HarRecorder::new();
+//     client.middleware(har.clone());
+//     ...
+//     std::fs::write("session.har", har.to_json()?)?;
+#[derive(Clone)]
+pub struct HarRecorder {
+    entries: Arc<Mutex<Vec<Entry>>>,
+    max_body_size: usize,
+}
+
+impl Default for HarRecorder {
+    fn default() -> Self {
+        Self {
+            entries: Arc::default(),
+            max_body_size: 64 * 1024,
+        }
+    }
+}
+
+impl HarRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_body_size(&mut self, p: usize) -> &mut Self {
+        self.max_body_size = p;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    pub fn to_json(&self) -> Result<String, HttpError> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let har = Har {
+            log: Log {
+                version: "1.2",
+                creator: Creator {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                entries: &entries,
+            },
+        };
+        serde_json::to_string_pretty(&har).map_err(|e| HttpError::Other(e.to_string()))
+    }
+
+    fn entry(&self, req: &Request, resp: &Response, started: SystemTime, time: f64) -> Entry {
+        let target = req.target().unwrap_or_else(|_| req.url.request_target());
+        let scheme = match req.url.scheme.as_deref() {
+            Some("https") => "https",
+            _ => "http",
+        };
+        let authority = req.url.authority().unwrap_or_else(|| "localhost".into());
+        let query_string = target
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Pair {
+                    name: name.into(),
+                    value: value.into(),
+                }
+            })
+            .collect();
+        let req_headers = req.header.as_ref().map(pairs).unwrap_or_default();
+        let post_data = req.body.as_ref().map(|body| {
+            let (text, _) = self.text(body);
+            PostData {
+                mime_type: mime_type(req.header.as_ref()),
+                text,
+            }
+        });
+        let resp_body = resp.body.as_deref().unwrap_or_default();
+        let (text, encoding) = self.text(resp_body);
+        Entry {
+            started_date_time: format_time(started),
+            time,
+            request: HarRequest {
+                method: req.method.to_string(),
+                url: format!("{}://{}{}", scheme, authority, target),
+                http_version: "HTTP/1.1",
+                cookies: Vec::new(),
+                headers: req_headers,
+                query_string,
+                body_size: req.body.as_ref().map_or(0, |b| b.len() as i64),
+                post_data,
+                headers_size: -1,
+                socket: req.url.socket_path.clone(),
+            },
+            response: HarResponse {
+                status: resp.status.as_u16(),
+                status_text: resp.reason.clone(),
+                http_version: resp.version.to_string(),
+                cookies: Vec::new(),
+                headers: pairs(&resp.header),
+                content: Content {
+                    size: resp_body.len(),
+                    mime_type: mime_type(Some(&resp.header)),
+                    text,
+                    encoding,
+                },
+                redirect_url: resp
+                    .header
+                    .get(headers::LOCATION)
+                    .cloned()
+                    .unwrap_or_default(),
+                headers_size: -1,
+                body_size: resp_body.len() as i64,
+            },
+            cache: Cache {},
+            timings: Timings {
+                send: 0.0,
+                wait: time,
+                receive: 0.0,
+            },
+        }
+    }
+
+    // the body cut at max_body_size, as text or base64
+    fn text(&self, body: &[u8]) -> (String, Option<&'static str>) {
+        let body = &body[..body.len().min(self.max_body_size)];
+        match std::str::from_utf8(body) {
+            Ok(text) => (text.into(), None),
+            Err(_) => (base64::encode(body), Some("base64")),
+        }
+    }
+}
+
+impl Middleware for HarRecorder {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let resp = next.run(req)?;
+        let time = start.elapsed().as_secs_f64() * 1000.0;
+        let entry = self.entry(req, &resp, started, time);
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(entry);
+        Ok(resp)
+    }
+}
+
+fn pairs(header: &HttpHeader) -> Vec<Pair> {
+    header
+        .iter()
+        .map(|(name, value)| Pair {
+            name: name.into(),
+            value: value.into(),
+        })
+        .collect()
+}
+
+fn mime_type(header: Option<&HttpHeader>) -> String {
+    header
+        .and_then(|h| h.get(headers::CONTENT_TYPE))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// e.g. 2024-01-02T03:04:05.678Z
+fn format_time(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = headers::civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
+
+#[derive(Serialize)]
+struct Har<'a> {
+    log: Log<'a>,
+}
+
+#[derive(Serialize)]
+struct Log<'a> {
+    version: &'static str,
+    creator: Creator,
+    entries: &'a [Entry],
+}
+
+#[derive(Serialize)]
+struct Creator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    // milliseconds
+    time: f64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: Cache,
+    timings: Timings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: &'static str,
+    cookies: Vec<Pair>,
+    headers: Vec<Pair>,
+    query_string: Vec<Pair>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: i64,
+    // custom field for requests over a unix socket
+    #[serde(rename = "_socket", skip_serializing_if = "Option::is_none")]
+    socket: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<Pair>,
+    headers: Vec<Pair>,
+    content: Content,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+struct Pair {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: usize,
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct Cache {}
+
+#[derive(Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test::MockConn;
+    use crate::url::Url;
+    use crate::{HttpClient, HttpMethod};
+
+    #[test]
+    fn har_format_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_time(t), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn har_record() {
+        let mut input = b"HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\
+            Content-Length: 12\r\n\r\n{\"Id\":\"abc\"}"
+            .to_vec();
+        input.extend_from_slice(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n\xff\x00\x01");
+        let conn = MockConn::from_bytes(input);
+        let mut client = HttpClient::new(conn);
+        client.base_url(Url::parse("unix:///var/run/docker.sock").unwrap());
+        let mut har = HarRecorder::new();
+        har.max_body_size(8);
+        client.middleware(har.clone());
+
+        let mut req = Request::new("/containers/create?name=web");
+        req.method(HttpMethod::Post).body(r#"{"Image":"nginx"}"#);
+        client.execute_request(&mut req).unwrap();
+        client
+            .execute_request(&mut Request::get("/containers/abc/archive"))
+            .unwrap();
+        assert_eq!(har.len(), 2);
+
+        let json: serde_json::Value = serde_json::from_str(&har.to_json().unwrap()).unwrap();
+        let log = &json["log"];
+        assert_eq!(log["version"], "1.2");
+        let entry = &log["entries"][0];
+        assert_eq!(entry["request"]["method"], "POST");
+        assert_eq!(
+            entry["request"]["url"],
+            "http://localhost/containers/create?name=web"
+        );
+        assert_eq!(entry["request"]["_socket"], "/var/run/docker.sock");
+        assert_eq!(
+            entry["request"]["queryString"],
+            serde_json::json!([{"name": "name", "value": "web"}])
+        );
+        assert_eq!(entry["request"]["postData"]["text"], "{\"Image\"");
+        assert_eq!(entry["request"]["bodySize"], 17);
+        assert_eq!(entry["response"]["status"], 201);
+        assert_eq!(entry["response"]["statusText"], "Created");
+        assert_eq!(entry["response"]["content"]["mimeType"], "application/json");
+        assert_eq!(entry["response"]["content"]["size"], 12);
+
+        let content = &log["entries"][1]["response"]["content"];
+        assert_eq!(content["text"], "/wAB");
+        assert_eq!(content["encoding"], "base64");
+
+        har.clear();
+        assert!(har.is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const ACCEPT: &str = "Accept";
+pub const ACCEPT_ENCODING: &str = "Accept-Encoding";
+pub const ALLOW: &str = "Allow";
+pub const AUTHORIZATION: &str = "Authorization";
+pub const CONNECTION: &str = "Connection";
+pub const CONTENT_ENCODING: &str = "Content-Encoding";
+pub const CONTENT_LENGTH: &str = "Content-Length";
+pub const CONTENT_RANGE: &str = "Content-Range";
+pub const CONTENT_TYPE: &str = "Content-Type";
+pub const COOKIE: &str = "Cookie";
+pub const DATE: &str = "Date";
+pub const ETAG: &str = "ETag";
+pub const EXPECT: &str = "Expect";
+pub const HOST: &str = "Host";
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+pub const IF_MODIFIED_SINCE: &str = "If-Modified-Since";
+pub const IF_NONE_MATCH: &str = "If-None-Match";
+pub const LAST_EVENT_ID: &str = "Last-Event-ID";
+pub const LAST_MODIFIED: &str = "Last-Modified";
+pub const LOCATION: &str = "Location";
+pub const PROXY_AUTHORIZATION: &str = "Proxy-Authorization";
+pub const RANGE: &str = "Range";
+pub const SEC_WEBSOCKET_ACCEPT: &str = "Sec-WebSocket-Accept";
+pub const SEC_WEBSOCKET_KEY: &str = "Sec-WebSocket-Key";
+pub const SEC_WEBSOCKET_VERSION: &str = "Sec-WebSocket-Version";
+pub const SET_COOKIE: &str = "Set-Cookie";
+pub const TRANSFER_ENCODING: &str = "Transfer-Encoding";
+pub const UPGRADE: &str = "Upgrade";
+pub const USER_AGENT: &str = "User-Agent";
+pub const WWW_AUTHENTICATE: &str = "WWW-Authenticate";
+
+// media type of Content-Type header, e.g. `application/json; charset=utf-8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mime {
+    pub type_: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Mime {
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_, self.subtype)
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+}
+
+impl FromStr for Mime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cols = s.split(';');
+        let essence = cols.next().unwrap_or_default().trim();
+        let (type_, subtype) = essence
+            .split_once('/')
+            .ok_or_else(|| format!("invalid media type: {}", s))?;
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() {
+            return Err(format!("invalid media type: {}", s));
+        }
+
+        let mut params = Vec::new();
+        for param in cols {
+            let param = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let (k, v) = param
+                .split_once('=')
+                .ok_or_else(|| format!("invalid media type parameter: {}", param))?;
+            let v = v.trim().trim_matches('"');
+            params.push((k.trim().to_lowercase(), v.to_string()));
+        }
+
+        Ok(Self {
+            type_: type_.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+            params,
+        })
+    }
+}
+
+impl Display for Mime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (k, v) in self.params.iter() {
+            write!(f, "; {}={}", k, v)?;
+        }
+        Ok(())
+    }
+}
+
+// Content-Range header of a byte range, e.g. `bytes 0-499/1234`. the range is
+// None for `bytes */1234` of 416 Range Not Satisfiable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub range: Option<(u64, u64)>,
+    pub total: Option<u64>,
+}
+
+impl FromStr for ContentRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid content-range: {}", s);
+        let (unit, rest) = s.trim().split_once(' ').ok_or_else(err)?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return Err(err());
+        }
+        let (range, total) = rest.trim().split_once('/').ok_or_else(err)?;
+        let range = match range {
+            "*" => None,
+            range => {
+                let (start, end) = range.split_once('-').ok_or_else(err)?;
+                let start: u64 = start.parse().map_err(|_| err())?;
+                let end: u64 = end.parse().map_err(|_| err())?;
+                if end < start {
+                    return Err(err());
+                }
+                Some((start, end))
+            }
+        };
+        let total = match total {
+            "*" => None,
+            total => Some(total.parse::<u64>().map_err(|_| err())?),
+        };
+        match (range, total) {
+            (None, None) => return Err(err()),
+            (Some((_, end)), Some(total)) if end >= total => return Err(err()),
+            _ => {}
+        }
+        Ok(Self { range, total })
+    }
+}
+
+// an HTTP date, e.g. `Wed, 21 Oct 2015 07:28:00 GMT` or the old
+// `Wednesday, 21-Oct-15 07:28:00 GMT`. lenient like cookie Expires, RFC 6265
+// section 5.1.1
+pub fn parse_date(s: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let (mut day, mut month, mut year, mut time) = (None, None, None, None);
+    for token in s.split([' ', '-', ',']).filter(|t| !t.is_empty()) {
+        let lower = token.to_ascii_lowercase();
+        if time.is_none() && token.contains(':') {
+            let hms = token
+                .split(':')
+                .map(|n| n.parse::<u64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            // 60 for a leap second
+            let [h @ 0..=23, m @ 0..=59, s @ 0..=60] = hms[..] else {
+                return None;
+            };
+            time = Some(h * 3600 + m * 60 + s);
+        } else if let Some(m) = lower
+            .get(..3)
+            .and_then(|p| MONTHS.iter().position(|m| *m == p))
+            .filter(|_| month.is_none())
+        {
+            month = Some(m as u64 + 1);
+        } else if let Ok(n) = token.parse::<u64>() {
+            match token.len() {
+                1 | 2 if day.is_none() => day = Some(n),
+                // two digit years, RFC 6265 section 5.1.1
+                2 => year = Some(if n < 70 { 2000 + n } else { 1900 + n }),
+                4 => year = Some(n),
+                _ => return None,
+            }
+        }
+    }
+    let (day, month, year, time) = (day?, month?, year?, time?);
+    if !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    UNIX_EPOCH.checked_add(Duration::from_secs(days * 86400 + time))
+}
+
+// the year, month and day of days since 1970-01-01, the inverse of parse_date.
+// see http://howardhinnant.github.io/date_algorithms.html
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+// the IMF-fixdate of t, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+pub fn format_date(t: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn date_parse() {
+        let want = UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        assert_eq!(parse_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(want));
+        assert_eq!(parse_date("Wednesday, 21-Oct-15 07:28:00 GMT"), Some(want));
+        assert_eq!(
+            parse_date("Thu, 29 Feb 2024 00:00:01 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_709_164_801))
+        );
+        assert_eq!(parse_date("yesterday"), None);
+        assert_eq!(parse_date("Thu, 01 Jan 2025 24:00:00 GMT"), None);
+        assert_eq!(parse_date("Thu, 01 Jan 2025 00:60:00 GMT"), None);
+        assert_eq!(
+            parse_date("Thu, 01 Jan 2025 99999999999999999:00:00 GMT"),
+            None
+        );
+        assert_eq!(
+            parse_date("Thu, 01 Jan 2025 5124095576030431:00:00 GMT"),
+            None
+        );
+    }
+
+    #[test]
+    fn date_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn date_format() {
+        let t = UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        assert_eq!(format_date(t), "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(format_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        let t = UNIX_EPOCH + Duration::from_secs(1_709_164_801);
+        assert_eq!(parse_date(&format_date(t)), Some(t));
+    }
+
+    #[test]
+    fn mime_parse() {
+        let mime: Mime = "Application/JSON; charset=\"utf-8\"".parse().unwrap();
+        assert_eq!(mime.essence(), "application/json");
+        assert_eq!(mime.charset(), Some("utf-8"));
+        assert_eq!(mime.to_string(), "application/json; charset=utf-8");
+
+        assert!("json".parse::<Mime>().is_err());
+        assert!("text/; a=b".parse::<Mime>().is_err());
+    }
+
+    #[test]
+    fn content_range_parse() {
+        let range: ContentRange = "bytes 100-199/1000".parse().unwrap();
+        assert_eq!(range.range, Some((100, 199)));
+        assert_eq!(range.total, Some(1000));
+
+        let range: ContentRange = "bytes 0-9/*".parse().unwrap();
+        assert_eq!(range.total, None);
+        let range: ContentRange = "bytes */1000".parse().unwrap();
+        assert_eq!(range.range, None);
+
+        for s in [
+            "bytes */*",
+            "bytes 9-0/10",
+            "bytes 0-10/10",
+            "items 0-1/2",
+            "bytes 0-1",
+        ] {
+            assert!(s.parse::<ContentRange>().is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+
+use crate::huffman;
+
+// static table of HPACK, the header compression of HTTP/2 (RFC 7541 Appendix A).
+// index 1 is the first entry
+#[rustfmt::skip]
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// default SETTINGS_HEADER_TABLE_SIZE
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// header fields are decoded with the dynamic table, which is shared by all
+// header blocks of the connection
+#[derive(Debug)]
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    // the largest size which the encoder may choose
+    max_size_limit: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+            max_size_limit: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+fn entry_size(name: &str, value: &str) -> usize {
+    name.len() + value.len() + 32
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, index: usize) -> Result<(String, String), String> {
+        let entry = match index {
+            0 => None,
+            1..=61 => STATIC_TABLE
+                .get(index - 1)
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+            _ => self.table.get(index - 62).cloned(),
+        };
+        entry.ok_or_else(|| format!("hpack: invalid index: {}", index))
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        self.size += entry_size(&name, &value);
+        self.table.push_front((name, value));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= entry_size(&name, &value);
+        }
+    }
+
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<(String, String)>, String> {
+        let mut fields = Vec::new();
+        while let Some(&b) = block.first() {
+            if b & 0x80 != 0 {
+                // indexed header field
+                let index = decode_int(&mut block, 7)?;
+                fields.push(self.get(index)?);
+            } else if b & 0xe0 == 0x20 {
+                let size = decode_int(&mut block, 5)?;
+                if size > self.max_size_limit {
+                    return Err(format!("hpack: table size too large: {}", size));
+                }
+                self.max_size = size;
+                self.evict();
+            } else {
+                // literal header field. with incremental indexing, without
+                // indexing or never indexed
+                let (prefix, indexing) = if b & 0xc0 == 0x40 {
+                    (6, true)
+                } else {
+                    (4, false)
+                };
+                let name = match decode_int(&mut block, prefix)? {
+                    0 => decode_string(&mut block)?,
+                    index => self.get(index)?.0,
+                };
+                let value = decode_string(&mut block)?;
+                if indexing {
+                    self.insert(name.clone(), value.clone());
+                }
+                fields.push((name, value));
+            }
+        }
+        Ok(fields)
+    }
+}
+
+fn decode_int(block: &mut &[u8], prefix: u8) -> Result<usize, String> {
+    let truncated = || "hpack: truncated integer".to_string();
+    let mask = (1u16 << prefix) as usize - 1;
+    let (&first, rest) = block.split_first().ok_or_else(truncated)?;
+    *block = rest;
+    let mut value = first as usize & mask;
+    if value < mask {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&b, rest) = block.split_first().ok_or_else(truncated)?;
+        *block = rest;
+        if shift > 28 {
+            return Err("hpack: integer overflow".into());
+        }
+        value += ((b & 0x7f) as usize) << shift;
+        shift += 7;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn decode_string(block: &mut &[u8]) -> Result<String, String> {
+    let is_huffman = block.first().is_some_and(|b| b & 0x80 != 0);
+    let len = decode_int(block, 7)?;
+    if len > block.len() {
+        return Err("hpack: truncated string".into());
+    }
+    let (data, rest) = block.split_at(len);
+    *block = rest;
+    let data = if is_huffman {
+        huffman::decode(data)?
+    } else {
+        data.to_vec()
+    };
+    // NOTE: values which are not utf-8 are not expected from docker
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn encode_int(out: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let mask = (1u16 << prefix) as usize - 1;
+    if value < mask {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | mask as u8);
+    value -= mask;
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn encode_string(out: &mut Vec<u8>, s: &str) {
+    let encoded = huffman::encode(s.as_bytes());
+    if encoded.len() < s.len() {
+        encode_int(out, 0x80, 7, encoded.len());
+        out.extend_from_slice(&encoded);
+    } else {
+        encode_int(out, 0, 7, s.len());
+        out.extend_from_slice(s.as_bytes());
+    }
+}
+
+// encode header fields without the dynamic table, so the encoder has no state.
+// names must be lowercase
+pub fn encode<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in fields {
+        let exact = STATIC_TABLE.iter().position(|&e| e == (name, value));
+        if let Some(i) = exact {
+            encode_int(&mut out, 0x80, 7, i + 1);
+            continue;
+        }
+        // literal without indexing
+        match STATIC_TABLE.iter().position(|&(n, _)| n == name) {
+            Some(i) => encode_int(&mut out, 0, 4, i + 1),
+            None => {
+                out.push(0);
+                encode_string(&mut out, name);
+            }
+        }
+        encode_string(&mut out, value);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields(f: &[(&str, &str)]) -> Vec<(String, String)> {
+        f.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn decode_rfc7541() {
+        // C.3 requests without Huffman coding, C.4 with it
+        let tests: [[&[u8]; 3]; 2] = [
+            [
+                b"\x82\x86\x84\x41\x0fwww.example.com",
+                b"\x82\x86\x84\xbe\x58\x08no-cache",
+                b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+            ],
+            [
+                b"\x82\x86\x84\x41\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff",
+                b"\x82\x86\x84\xbe\x58\x86\xa8\xeb\x10\x64\x9c\xbf",
+                b"\x82\x87\x85\xbf\x40\x88\x25\xa8\x49\xe9\x5b\xa9\x7d\x7f\x89\x25\xa8\x49\xe9\x5b\xb8\xe8\xb4\xbf",
+            ],
+        ];
+        for blocks in tests {
+            let mut decoder = Decoder::new();
+            let base = [
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ];
+            assert_eq!(decoder.decode(blocks[0]).unwrap(), fields(&base));
+            let mut want = base.to_vec();
+            want.push(("cache-control", "no-cache"));
+            assert_eq!(decoder.decode(blocks[1]).unwrap(), fields(&want));
+            assert_eq!(
+                decoder.decode(blocks[2]).unwrap(),
+                fields(&[
+                    (":method", "GET"),
+                    (":scheme", "https"),
+                    (":path", "/index.html"),
+                    (":authority", "www.example.com"),
+                    ("custom-key", "custom-value"),
+                ])
+            );
+            assert_eq!(decoder.size, 164);
+        }
+
+        let mut decoder = Decoder::new();
+        assert!(decoder.decode(b"\xbe").is_err());
+        assert!(decoder.decode(b"\x3f\xe2\x1f").is_err());
+        assert!(decoder.decode(b"\x41\x0fwww").is_err());
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let want = [
+            (":method", "GET"),
+            (":path", "/v1.41/containers/json?all=true"),
+            (":authority", "localhost"),
+            ("accept-encoding", "gzip, deflate"),
+            ("x-registry-auth", "e30="),
+            ("long", &"a".repeat(300)),
+        ];
+        let block = encode(want);
+        assert_eq!(block[..2], [0x82, 0x04]);
+        assert_eq!(Decoder::new().decode(&block).unwrap(), fields(&want));
+    }
+}
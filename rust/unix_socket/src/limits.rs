@@ -0,0 +1,72 @@
+use std::io::{self, Read};
+
+use crate::error::HttpError;
+
+// upper bounds of what is read from the server, so a broken or malicious
+// server can't make the client allocate without limit. None means no limit
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_headers: usize,
+    // including the status line
+    pub max_header_line: usize,
+    pub max_body_size: Option<u64>,
+    pub max_chunk_size: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_headers: 100,
+            max_header_line: 16 * 1024,
+            max_body_size: None,
+            max_chunk_size: Some(16 * 1024 * 1024),
+        }
+    }
+}
+
+// fails once more than max bytes are read
+pub struct LimitReader<R> {
+    r: R,
+    remaining: u64,
+    max: u64,
+}
+
+impl<R: Read> LimitReader<R> {
+    pub fn new(r: R, max: u64) -> Self {
+        Self {
+            r,
+            remaining: max,
+            max,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.r.read(buf)?;
+        if n as u64 > self.remaining {
+            return Err(io::Error::other(HttpError::BodyTooLarge(self.max)));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn limit_reader() {
+        let mut body = Vec::new();
+        let mut r = LimitReader::new(Cursor::new(b"hello".to_vec()), 5);
+        r.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"hello");
+
+        let mut r = LimitReader::new(Cursor::new(b"hello".to_vec()), 4);
+        let err = HttpError::from(r.read_to_end(&mut body).unwrap_err());
+        assert!(matches!(err, HttpError::BodyTooLarge(4)), "{}", err);
+    }
+}
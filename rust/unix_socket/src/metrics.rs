@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use crate::status::StatusCode;
+use crate::HttpMethod;
+
+// outcome of one request of HttpClient::execute_request
+#[derive(Debug, Clone)]
+pub struct RequestMetrics {
+    pub method: HttpMethod,
+    // None when the request failed before a response head was read
+    pub status: Option<StatusCode>,
+    pub duration: Duration,
+    // bytes written to and read from the connection, including the heads
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    // whether an earlier request was sent on the connection
+    pub reused: bool,
+    pub error: Option<String>,
+}
+
+// receives RequestMetrics after each request, e.g. to export them to
+// Prometheus or statsd
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, metrics: &RequestMetrics);
+}
+
+impl<F> MetricsSink for F
+where
+    F: Fn(&RequestMetrics) + Send + Sync,
+{
+    fn record(&self, metrics: &RequestMetrics) {
+        self(metrics)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test::MockConn;
+    use crate::{HttpClient, Request};
+
+    #[test]
+    fn metrics_sink() {
+        let resp = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\n\r\nbusy";
+        let mut client = HttpClient::new(MockConn::new(resp));
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let r = recorded.clone();
+        client.metrics(Some(Arc::new(move |m: &RequestMetrics| {
+            r.lock().unwrap().push(m.clone());
+        })));
+
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post).body("{}");
+        client.execute_request(&mut req).unwrap();
+        assert!(client.execute_request(&mut Request::get("/_ping")).is_err());
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 2);
+        let m = &recorded[0];
+        assert_eq!(m.method, HttpMethod::Post);
+        assert_eq!(m.status, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(m.bytes_received, resp.len() as u64);
+        assert_eq!(m.bytes_sent, req.build().unwrap().len() as u64);
+        assert!(!m.reused);
+        assert!(m.error.is_none());
+
+        let m = &recorded[1];
+        assert_eq!(m.method, HttpMethod::Get);
+        assert_eq!(m.status, None);
+        assert_eq!(m.bytes_received, 0);
+        assert!(m.reused);
+        assert_eq!(m.error.as_deref(), Some("connection is closed"));
+    }
+}
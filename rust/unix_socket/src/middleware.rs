@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use crate::error::HttpError;
+use crate::{Request, Response};
+
+// wraps the requests of HttpClient::execute_request. a middleware can change
+// the request, call the rest of the chain any number of times, or answer
+// without calling it at all, e.g. auth injection, logging or caching:
+//
+//     client.middleware(|req: &mut Request, mut next: Next| {
+//         req.header.get_or_insert_with(HttpHeader::new).add("X-Registry-Auth", &token);
+//         next.run(req)
+//     });
+pub trait Middleware: Send + Sync {
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Result<Response, HttpError>;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut Request, Next<'_>) -> Result<Response, HttpError> + Send + Sync,
+{
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Result<Response, HttpError> {
+        self(req, next)
+    }
+}
+
+// the middlewares after the current one, ending with the client
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    send: &'a mut dyn FnMut(&mut Request) -> Result<Response, HttpError>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middlewares: &'a [Arc<dyn Middleware>],
+        send: &'a mut dyn FnMut(&mut Request) -> Result<Response, HttpError>,
+    ) -> Self {
+        Self { middlewares, send }
+    }
+
+    pub fn run(&mut self, req: &mut Request) -> Result<Response, HttpError> {
+        match self.middlewares.split_first() {
+            Some((first, rest)) => first.handle(req, Next::new(rest, &mut *self.send)),
+            None => (self.send)(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::status::StatusCode;
+    use crate::test::MockConn;
+    use crate::{headers, HttpClient, HttpHeader, HttpVersion};
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+
+    #[test]
+    fn middleware_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut client = HttpClient::new(MockConn::new(&OK.repeat(2)));
+        let l = log.clone();
+        client.middleware(move |req: &mut Request, mut next: Next| {
+            l.lock().unwrap().push(format!("outer {}", req.url));
+            let resp = next.run(req);
+            l.lock().unwrap().push("outer done".to_string());
+            resp
+        });
+        let l = log.clone();
+        client.middleware(move |req: &mut Request, mut next: Next| {
+            l.lock().unwrap().push("inner".to_string());
+            req.header
+                .get_or_insert_with(HttpHeader::new)
+                .add(headers::AUTHORIZATION, "Bearer token");
+            next.run(req)
+        });
+
+        let resp = client.execute_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!(resp.body, Some(b"ok".into()));
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["outer /_ping", "inner", "outer done"]
+        );
+        let sent = String::from_utf8(client.conn.get_ref().output.clone()).unwrap();
+        assert!(sent.contains("Authorization: Bearer token\r\n"));
+    }
+
+    #[test]
+    fn middleware_short_circuit_and_repeat() {
+        let mut client = HttpClient::new(MockConn::new(&OK.repeat(2)));
+        client.middleware(|req: &mut Request, mut next: Next| {
+            if req.url.to_string() == "/cached" {
+                return Ok(Response {
+                    version: HttpVersion::Http11,
+                    status: StatusCode::OK,
+                    reason: "OK".into(),
+                    header: HttpHeader::new(),
+                    trailers: HttpHeader::new(),
+                    body: Some(b"cached".into()),
+                });
+            }
+            // e.g. a retry
+            next.run(req)?;
+            next.run(req)
+        });
+
+        let resp = client
+            .execute_request(&mut Request::get("/cached"))
+            .unwrap();
+        assert_eq!(resp.body, Some(b"cached".into()));
+        assert!(client.conn.get_ref().output.is_empty());
+
+        client.execute_request(&mut Request::get("/_ping")).unwrap();
+        let sent = String::from_utf8(client.conn.get_ref().output.clone()).unwrap();
+        assert_eq!(sent.matches("GET /_ping").count(), 2);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read};
+
+use crate::uuid::random_u64;
+
+struct Part {
+    head: String,
+    body: Box<dyn Read + Send>,
+}
+
+// multipart/form-data body (RFC 7578). parts are read one by one when the
+// request is sent, so files are not loaded into memory
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self {
+            boundary: random_boundary(),
+            parts: Vec::new(),
+        }
+    }
+}
+
+fn random_boundary() -> String {
+    format!(
+        "------------------------{:016x}{:016x}",
+        random_u64(),
+        random_u64()
+    )
+}
+
+// quotes and line breaks would end the parameter of Content-Disposition
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    // value of Content-Type header
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn text(&mut self, name: &str, value: &str) -> &mut Self {
+        self.parts.push(Part {
+            head: format!(
+                "Content-Disposition: form-data; name=\"{}\"\r\n",
+                escape(name)
+            ),
+            body: Box::new(Cursor::new(value.as_bytes().to_vec())),
+        });
+        self
+    }
+
+    pub fn file(
+        &mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        r: impl Read + Send + 'static,
+    ) -> &mut Self {
+        self.parts.push(Part {
+            head: format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n",
+                escape(name),
+                escape(filename),
+                content_type
+            ),
+            body: Box::new(r),
+        });
+        self
+    }
+
+    pub fn into_reader(self) -> MultipartReader {
+        let mut readers: VecDeque<Box<dyn Read + Send>> = VecDeque::new();
+        for part in self.parts {
+            let head = format!("--{}\r\n{}\r\n", self.boundary, part.head);
+            readers.push_back(Box::new(Cursor::new(head.into_bytes())));
+            readers.push_back(part.body);
+            readers.push_back(Box::new(Cursor::new(b"\r\n".to_vec())));
+        }
+        let tail = format!("--{}--\r\n", self.boundary);
+        readers.push_back(Box::new(Cursor::new(tail.into_bytes())));
+        MultipartReader { readers }
+    }
+}
+
+pub struct MultipartReader {
+    readers: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl Read for MultipartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(r) = self.readers.front_mut() {
+            let n = r.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.readers.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multipart_body() {
+        let mut form = Multipart::new();
+        form.text("tag", "v1").file(
+            "context",
+            "a\"b.tar",
+            "application/x-tar",
+            Cursor::new(b"data"),
+        );
+        let boundary = form.boundary().to_string();
+        assert!(form.content_type().ends_with(&boundary));
+
+        let mut body = String::new();
+        form.into_reader().read_to_string(&mut body).unwrap();
+        let want = [
+            &format!("--{}", boundary),
+            "Content-Disposition: form-data; name=\"tag\"",
+            "",
+            "v1",
+            &format!("--{}", boundary),
+            "Content-Disposition: form-data; name=\"context\"; filename=\"a%22b.tar\"",
+            "Content-Type: application/x-tar",
+            "",
+            "data",
+            &format!("--{}--", boundary),
+            "",
+        ]
+        .join("\r\n");
+        assert_eq!(body, want);
+
+        assert_ne!(Multipart::new().boundary(), boundary);
+    }
+}
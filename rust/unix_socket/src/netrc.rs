@@ -0,0 +1,179 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+// credentials of a .netrc file, see HttpClient::netrc:
+//
+//     machine registry.local login ci password s3cret
+//     default login anonymous password guest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Netrc {
+    machines: Vec<Machine>,
+    default: Option<Machine>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Machine {
+    name: String,
+    login: String,
+    password: String,
+}
+
+impl Netrc {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut netrc = Netrc::default();
+        let mut tokens = Tokens { rest: s };
+        // the entry which login and password belong to
+        let mut current: Option<Machine> = None;
+        while let Some(token) = tokens.next() {
+            match token.as_str() {
+                "machine" | "default" => {
+                    netrc.push(current.take());
+                    let name = match token.as_str() {
+                        "machine" => tokens.next().ok_or("netrc: missing machine name")?,
+                        _ => String::new(),
+                    };
+                    current = Some(Machine {
+                        name,
+                        ..Default::default()
+                    });
+                }
+                "login" | "password" | "account" => {
+                    let value = tokens
+                        .next()
+                        .ok_or_else(|| format!("netrc: missing value of {}", token))?;
+                    let machine = current
+                        .as_mut()
+                        .ok_or_else(|| format!("netrc: {} outside of machine", token))?;
+                    match token.as_str() {
+                        "login" => machine.login = value,
+                        "password" => machine.password = value,
+                        _ => {}
+                    }
+                }
+                // a macro runs until an empty line
+                "macdef" => tokens.skip_macro(),
+                _ => return Err(format!("netrc: unknown token {}", token)),
+            }
+        }
+        netrc.push(current);
+        Ok(netrc)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::parse(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // ~/.netrc, or %USERPROFILE%\_netrc on windows like curl
+    pub fn default_path() -> Option<PathBuf> {
+        if cfg!(windows) {
+            let home = PathBuf::from(std::env::var_os("USERPROFILE")?);
+            return Some(home.join("_netrc"));
+        }
+        Some(PathBuf::from(std::env::var_os("HOME")?).join(".netrc"))
+    }
+
+    // login and password for host. the first matching machine wins, then default
+    pub fn credentials(&self, host: &str) -> Option<(&str, &str)> {
+        self.machines
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(host))
+            .or(self.default.as_ref())
+            .map(|m| (m.login.as_str(), m.password.as_str()))
+    }
+
+    fn push(&mut self, machine: Option<Machine>) {
+        match machine {
+            Some(m) if m.name.is_empty() => {
+                self.default.get_or_insert(m);
+            }
+            Some(m) => self.machines.push(m),
+            None => {}
+        }
+    }
+}
+
+// whitespace separated tokens. a token may be a quoted string with escapes
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl Tokens<'_> {
+    fn next(&mut self) -> Option<String> {
+        loop {
+            self.rest = self.rest.trim_start();
+            // comments run to the end of the line
+            if self.rest.starts_with('#') {
+                let end = self.rest.find('\n').unwrap_or(self.rest.len());
+                self.rest = &self.rest[end..];
+                continue;
+            }
+            break;
+        }
+        if self.rest.is_empty() {
+            return None;
+        }
+        if let Some(quoted) = self.rest.strip_prefix('"') {
+            let mut out = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        self.rest = &quoted[i + 1..];
+                        return Some(out);
+                    }
+                    '\\' => out.extend(chars.next().map(|(_, c)| c)),
+                    c => out.push(c),
+                }
+            }
+            self.rest = "";
+            return Some(out);
+        }
+        let end = self
+            .rest
+            .find(char::is_whitespace)
+            .unwrap_or(self.rest.len());
+        let token = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        Some(token.into())
+    }
+
+    fn skip_macro(&mut self) {
+        self.rest = match self.rest.find("\n\n") {
+            Some(i) => &self.rest[i + 2..],
+            None => "",
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn netrc_parse() {
+        let netrc = Netrc::parse(
+            "# local registries\n\
+             machine registry.local login ci password s3cret\n\
+             machine localhost\n  login \"a user\"\n  password \"p \\\"w\\\"\"\n\
+             macdef init\ncd /tmp\nput file\n\n\
+             default login anonymous password guest\n\
+             machine registry.local login other password x\n",
+        )
+        .unwrap();
+        assert_eq!(netrc.credentials("Registry.local"), Some(("ci", "s3cret")));
+        assert_eq!(netrc.credentials("localhost"), Some(("a user", "p \"w\"")));
+        assert_eq!(
+            netrc.credentials("example.com"),
+            Some(("anonymous", "guest"))
+        );
+
+        let netrc = Netrc::parse("machine a login b").unwrap();
+        assert_eq!(netrc.credentials("a"), Some(("b", "")));
+        assert_eq!(netrc.credentials("b"), None);
+
+        assert!(Netrc::parse("login b").is_err());
+        assert!(Netrc::parse("machine").is_err());
+        assert!(Netrc::parse("machine a port 80").is_err());
+    }
+}
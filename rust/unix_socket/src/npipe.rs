@@ -0,0 +1,44 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ERROR_PIPE_BUSY: all instances of the pipe are in use by other clients
+const ERROR_PIPE_BUSY: i32 = 231;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// `//./pipe/docker_engine` -> `\\.\pipe\docker_engine`
+pub fn pipe_name(path: &str) -> String {
+    path.replace('/', "\\")
+}
+
+// open a windows named pipe. the returned handle implements Read and Write,
+// so it can be used as a transport of HttpClient as is
+pub fn connect(path: &str) -> io::Result<File> {
+    let name = pipe_name(path);
+    let start = Instant::now();
+    loop {
+        match OpenOptions::new().read(true).write(true).open(&name) {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                if start.elapsed() > BUSY_TIMEOUT {
+                    return Err(e);
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn named_pipe_name() {
+        assert_eq!(
+            pipe_name("//./pipe/docker_engine"),
+            r"\\.\pipe\docker_engine"
+        );
+    }
+}
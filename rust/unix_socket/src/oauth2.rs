@@ -0,0 +1,346 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::pool::ClientPool;
+use crate::status::StatusCode;
+use crate::{basic_auth, headers, percent, HttpHeader, HttpMethod, Request, Response};
+
+// tokens are refreshed this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+// fetches an OAuth 2.0 access token (RFC 6749), caches it until it expires and
+// sends it as `Authorization: Bearer`. a 401 refreshes the token and sends the
+// request once more:
+//
+//     let mut oauth = OAuth2::client_credentials("/oauth/token", "cli", "s3cret");
+//     oauth.scope("containers:read");
+//     client.middleware(oauth);
+//
+// a relative token url is sent through the rest of the chain, i.e. to the same
+// server as the requests, and an absolute one over a new connection
+pub struct OAuth2 {
+    token_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scope: Option<String>,
+    grant: Grant,
+    state: Mutex<State>,
+    pool: ClientPool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grant {
+    ClientCredentials,
+    RefreshToken,
+}
+
+#[derive(Default)]
+struct State {
+    access_token: Option<String>,
+    // None when the server did not tell
+    expires_at: Option<Instant>,
+    refresh_token: Option<String>,
+}
+
+impl State {
+    fn valid_token(&self, now: Instant) -> Option<&str> {
+        let expired = self.expires_at.is_some_and(|t| t <= now + EXPIRY_MARGIN);
+        self.access_token.as_deref().filter(|_| !expired)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: Option<String>,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl OAuth2 {
+    // the client credentials grant, the client authenticates with its secret
+    pub fn client_credentials(token_url: &str, client_id: &str, client_secret: &str) -> Self {
+        let mut oauth = Self::new(token_url, client_id, Grant::ClientCredentials);
+        oauth.client_secret(client_secret);
+        oauth
+    }
+
+    // the refresh token grant with a token issued before, e.g. by a login
+    pub fn refresh_token(token_url: &str, client_id: &str, refresh_token: &str) -> Self {
+        let oauth = Self::new(token_url, client_id, Grant::RefreshToken);
+        oauth.lock().refresh_token = Some(refresh_token.into());
+        oauth
+    }
+
+    fn new(token_url: &str, client_id: &str, grant: Grant) -> Self {
+        Self {
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            scope: None,
+            grant,
+            state: Mutex::default(),
+            pool: ClientPool::new(),
+        }
+    }
+
+    // a confidential client authenticates with HTTP Basic, a public client
+    // only sends its id
+    pub fn client_secret(&mut self, p: &str) -> &mut Self {
+        self.client_secret = Some(p.into());
+        self
+    }
+
+    // space separated scopes to request
+    pub fn scope(&mut self, p: &str) -> &mut Self {
+        self.scope = Some(p.into());
+        self
+    }
+
+    // the latest refresh token, e.g. to store it for the next run, since the
+    // server may issue a new one with every refresh
+    pub fn current_refresh_token(&self) -> Option<String> {
+        self.lock().refresh_token.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // the cached token, or a new one when it is expired or rejected
+    fn token(&self, next: &mut Next<'_>, rejected: Option<&str>) -> Result<String, HttpError> {
+        // NOTE: the lock is held while fetching so that concurrent requests
+        // don't fetch a token each
+        let mut state = self.lock();
+        let now = Instant::now();
+        if let Some(token) = state.valid_token(now) {
+            if rejected != Some(token) {
+                return Ok(token.into());
+            }
+        }
+        let resp = match state.refresh_token.clone() {
+            Some(refresh_token) => {
+                let resp = self.request_token(next, Some(&refresh_token));
+                match resp {
+                    // the refresh token may be expired or revoked
+                    Err(_) if self.grant == Grant::ClientCredentials => {
+                        state.refresh_token = None;
+                        self.request_token(next, None)
+                    }
+                    resp => resp,
+                }
+            }
+            None if self.grant == Grant::ClientCredentials => self.request_token(next, None),
+            None => return Err("oauth2: no refresh token".to_string().into()),
+        }?;
+        state.access_token = Some(resp.access_token.clone());
+        // a lifetime beyond what Instant holds never expires
+        state.expires_at = resp
+            .expires_in
+            .and_then(|secs| now.checked_add(Duration::from_secs(secs)));
+        if resp.refresh_token.is_some() {
+            state.refresh_token = resp.refresh_token;
+        }
+        Ok(resp.access_token)
+    }
+
+    fn request_token(
+        &self,
+        next: &mut Next<'_>,
+        refresh_token: Option<&str>,
+    ) -> Result<TokenResponse, HttpError> {
+        let mut form = match refresh_token {
+            Some(token) => vec![("grant_type", "refresh_token"), ("refresh_token", token)],
+            None => vec![("grant_type", "client_credentials")],
+        };
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope.as_str()));
+        }
+        let mut req = Request::new(&self.token_url);
+        req.method(HttpMethod::Post);
+        let mut header = HttpHeader::new();
+        header.add(headers::ACCEPT, "application/json");
+        match &self.client_secret {
+            // the credentials are form-urlencoded first, RFC 6749 section 2.3.1
+            Some(secret) => header.add(
+                headers::AUTHORIZATION,
+                &basic_auth(
+                    &percent::encode_component(&self.client_id),
+                    Some(&percent::encode_component(secret)),
+                ),
+            ),
+            None => form.push(("client_id", self.client_id.as_str())),
+        }
+        req.header(header).form(&form);
+
+        let resp = match req.url.is_absolute() {
+            true => self.pool.execute_request(&mut req)?,
+            false => next.run(&mut req)?,
+        };
+        if !resp.status.is_success() {
+            let msg = match resp.json::<ErrorResponse>() {
+                Ok(e) => match e.error_description {
+                    Some(description) => format!("{}: {}", e.error, description),
+                    None => e.error,
+                },
+                Err(_) => resp.status.to_string(),
+            };
+            return Err(HttpError::Other(format!(
+                "oauth2: token request failed: {}",
+                msg
+            )));
+        }
+        let token = resp.json::<TokenResponse>()?;
+        if let Some(token_type) = &token.token_type {
+            if !token_type.eq_ignore_ascii_case("bearer") {
+                return Err(HttpError::Other(format!(
+                    "oauth2: unsupported token type: {}",
+                    token_type
+                )));
+            }
+        }
+        Ok(token)
+    }
+}
+
+impl Middleware for OAuth2 {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let token = self.token(&mut next, None)?;
+        let bearer = format!("Bearer {}", token);
+        req.header
+            .get_or_insert_with(HttpHeader::new)
+            .add(headers::AUTHORIZATION, &bearer);
+        let resp = next.run(req)?;
+        // NOTE: a consumed body reader can only be sent again if it's rewound
+        if resp.status != StatusCode::UNAUTHORIZED || !req.try_rewind() {
+            return Ok(resp);
+        }
+        let token = self.token(&mut next, Some(&token))?;
+        req.header
+            .get_or_insert_with(HttpHeader::new)
+            .add(headers::AUTHORIZATION, &format!("Bearer {}", token));
+        next.run(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockTransport, ResponseBuilder};
+    use crate::HttpClient;
+
+    fn json_response(status: StatusCode, body: &str) -> Vec<u8> {
+        ResponseBuilder::new(status)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .build()
+    }
+
+    #[test]
+    fn oauth2_client_credentials() {
+        let ok = ResponseBuilder::new(StatusCode::OK).build();
+        let mut conn = MockTransport::new();
+        conn.push(json_response(
+            StatusCode::OK,
+            r#"{"access_token":"t1","token_type":"Bearer","expires_in":3600,"refresh_token":"r1"}"#,
+        ))
+        .push(ok.clone())
+        .push(ok.clone())
+        .push(ResponseBuilder::new(StatusCode::UNAUTHORIZED).build())
+        .push(json_response(
+            StatusCode::OK,
+            r#"{"access_token":"t2","token_type":"bearer"}"#,
+        ))
+        .push(ok);
+        let mut client = HttpClient::new(conn);
+        let mut oauth = OAuth2::client_credentials("/oauth/token", "cli", "s3cret");
+        oauth.scope("containers:read");
+        client.middleware(oauth);
+
+        for path in ["/a", "/b", "/c"] {
+            let resp = client.execute_request(&mut Request::get(path)).unwrap();
+            assert_eq!(resp.status, StatusCode::OK);
+        }
+
+        let requests = client
+            .conn
+            .get_ref()
+            .requests()
+            .into_iter()
+            .map(|r| String::from_utf8(r).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(requests.len(), 6);
+        assert!(requests[0].starts_with("POST /oauth/token "));
+        assert!(requests[0].contains("\r\nAuthorization: Basic Y2xpOnMzY3JldA==\r\n"));
+        assert!(requests[0].ends_with("grant_type=client_credentials&scope=containers%3Aread"));
+        assert!(requests[1].starts_with("GET /a "));
+        assert!(requests[1].contains("\r\nAuthorization: Bearer t1\r\n"));
+        assert!(requests[2].contains("\r\nAuthorization: Bearer t1\r\n"));
+        assert!(requests[3].starts_with("GET /c "));
+        assert!(requests[4].contains("grant_type=refresh_token&refresh_token=r1"));
+        assert!(requests[5].starts_with("GET /c "));
+        assert!(requests[5].contains("\r\nAuthorization: Bearer t2\r\n"));
+    }
+
+    #[test]
+    fn oauth2_huge_expires_in() {
+        let ok = ResponseBuilder::new(StatusCode::OK).build();
+        let mut conn = MockTransport::new();
+        conn.push(json_response(
+            StatusCode::OK,
+            r#"{"access_token":"t1","expires_in":18446744073709551615}"#,
+        ))
+        .push(ok.clone())
+        .push(ok);
+        let mut client = HttpClient::new(conn);
+        client.middleware(OAuth2::client_credentials("/oauth/token", "cli", "s3cret"));
+
+        for path in ["/a", "/b"] {
+            let resp = client.execute_request(&mut Request::get(path)).unwrap();
+            assert_eq!(resp.status, StatusCode::OK);
+        }
+        assert_eq!(client.conn.get_ref().requests().len(), 3);
+    }
+
+    #[test]
+    fn oauth2_refresh_token() {
+        let mut conn = MockTransport::new();
+        conn.push(json_response(
+            StatusCode::OK,
+            r#"{"access_token":"t1","expires_in":10,"refresh_token":"r2"}"#,
+        ))
+        .push(ResponseBuilder::new(StatusCode::OK).build())
+        .push(json_response(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":"invalid_grant","error_description":"token revoked"}"#,
+        ));
+        let mut client = HttpClient::new(conn);
+        let oauth = std::sync::Arc::new(OAuth2::refresh_token("/token", "cli", "r1"));
+        let o = oauth.clone();
+        client.middleware(move |req: &mut Request, next: Next| o.handle(req, next));
+
+        client.execute_request(&mut Request::get("/a")).unwrap();
+        assert_eq!(oauth.current_refresh_token(), Some("r2".into()));
+        // expires within the margin, so refreshed again
+        let err = client.execute_request(&mut Request::get("/b")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "oauth2: token request failed: invalid_grant: token revoked"
+        );
+
+        let sent = String::from_utf8(client.conn.get_ref().written().to_vec()).unwrap();
+        assert!(sent.contains("grant_type=refresh_token&refresh_token=r1&client_id=cli"));
+        assert!(sent.contains("grant_type=refresh_token&refresh_token=r2&client_id=cli"));
+        assert!(!sent.contains("GET /b"));
+    }
+}
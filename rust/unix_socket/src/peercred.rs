@@ -0,0 +1,82 @@
+use std::fmt::Display;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+// credentials of the process on the other end of a unix socket, e.g. to make
+// sure that /var/run/docker.sock is served by a root-owned dockerd:
+//
+//     let client = HttpClient::connect_verified(DOCKER_HOST, |peer| peer.uid == 0)?;
+//
+// pid is None where the OS doesn't report it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+impl PeerCred {
+    // SO_PEERCRED on linux, getpeereid elsewhere
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn of(conn: &UnixStream) -> io::Result<Self> {
+        let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                conn.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            uid: cred.uid,
+            gid: cred.gid,
+            pid: Some(cred.pid),
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn of(conn: &UnixStream) -> io::Result<Self> {
+        let (mut uid, mut gid) = (0, 0);
+        let ret = unsafe { libc::getpeereid(conn.as_raw_fd(), &mut uid, &mut gid) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            uid,
+            gid,
+            pid: None,
+        })
+    }
+}
+
+impl Display for PeerCred {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "uid={} gid={}", self.uid, self.gid)?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={}", pid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn peer_cred() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let cred = PeerCred::of(&a).unwrap();
+        assert_eq!(cred.uid, unsafe { libc::getuid() });
+        assert_eq!(cred.gid, unsafe { libc::getgid() });
+        #[cfg(target_os = "linux")]
+        assert_eq!(cred.pid, Some(std::process::id() as i32));
+    }
+}
@@ -0,0 +1,101 @@
+// RFC 3986 percent-encoding
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+fn is_sub_delim(b: u8) -> bool {
+    matches!(
+        b,
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+    )
+}
+
+fn is_hex_pair(bytes: &[u8]) -> bool {
+    bytes.len() >= 2 && bytes[0].is_ascii_hexdigit() && bytes[1].is_ascii_hexdigit()
+}
+
+fn push_encoded(out: &mut String, b: u8) {
+    out.push_str(&format!("%{:02X}", b));
+}
+
+// encode everything except unreserved characters. used for query keys and values
+pub fn encode_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if is_unreserved(b) {
+            out.push(b as char);
+        } else {
+            push_encoded(&mut out, b);
+        }
+    }
+    out
+}
+
+// encode characters that are not allowed in a path. `/` and already encoded
+// sequences such as `%2F` are kept as is
+pub fn encode_path(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    for (i, &b) in bytes.iter().enumerate() {
+        let keep = is_unreserved(b)
+            || is_sub_delim(b)
+            || matches!(b, b'/' | b':' | b'@')
+            || (b == b'%' && is_hex_pair(&bytes[i + 1..]));
+        if keep {
+            out.push(b as char);
+        } else {
+            push_encoded(&mut out, b);
+        }
+    }
+    out
+}
+
+pub fn decode(s: &str) -> Result<String, String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if !is_hex_pair(&bytes[i + 1..]) {
+                return Err(format!("invalid percent-encoding: {}", s));
+            }
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+            out.push(u8::from_str_radix(hex, 16).unwrap());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| "cannot convert bytes to string".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        assert_eq!(
+            encode_component(r#"{"name":["foo"]}"#),
+            "%7B%22name%22%3A%5B%22foo%22%5D%7D"
+        );
+        assert_eq!(encode_component("a b&c=d"), "a%20b%26c%3Dd");
+        assert_eq!(encode_component("日本"), "%E6%97%A5%E6%9C%AC");
+        assert_eq!(
+            encode_path("/containers/my app/json"),
+            "/containers/my%20app/json"
+        );
+        assert_eq!(encode_path("/images/a%2Fb/json"), "/images/a%2Fb/json");
+        assert_eq!(encode_path("/100%"), "/100%25");
+    }
+
+    #[test]
+    fn decode_roundtrip() {
+        let s = r#"{"label":["a=b c"]} 日本"#;
+        assert_eq!(decode(&encode_component(s)).unwrap(), s);
+        assert!(decode("%zz").is_err());
+        assert!(decode("%4").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::transport::{self, Timeouts, Transport};
+use crate::url::Url;
+use crate::{headers, HttpClient, Request, Response};
+
+// sends requests with HTTP/1.1 pipelining, writing up to depth requests before
+// reading their responses in order, e.g. to inspect many containers of a local
+// daemon at once:
+//
+//     let mut client = PipelinedClient::new("unix:///var/run/docker.sock")?;
+//     let mut reqs = ids.iter().map(|id| Request::get(&format!("/containers/{}/json", id)));
+//     let results = client.execute_all(&mut reqs.collect::<Vec<_>>());
+//
+// when the server closes the connection before answering all of them, the
+// client falls back to sending one request at a time. unanswered requests are
+// sent again if they are retryable, see Request::retryable
+// NOTE: meant for trusted servers. a request with a streamed body or
+// `Expect: 100-continue` is not pipelined but sent alone
+pub struct PipelinedClient {
+    url: Url,
+    timeouts: Timeouts,
+    limits: Limits,
+    depth: usize,
+    client: Option<HttpClient<Transport>>,
+    serial: bool,
+}
+
+impl PipelinedClient {
+    pub fn new(url: &str) -> Result<Self, HttpError> {
+        Ok(Self {
+            url: Url::parse(url).map_err(HttpError::Parse)?,
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            depth: 16,
+            client: None,
+            serial: false,
+        })
+    }
+
+    pub fn timeouts(&mut self, p: Timeouts) -> &mut Self {
+        self.timeouts = p;
+        self
+    }
+
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // requests written before reading their responses
+    pub fn depth(&mut self, p: usize) -> &mut Self {
+        self.depth = p.max(1);
+        self
+    }
+
+    // whether the client fell back to sending one request at a time
+    pub fn is_serial(&self) -> bool {
+        self.serial
+    }
+
+    // the results of reqs, in the same order
+    pub fn execute_all(&mut self, reqs: &mut [Request]) -> Vec<Result<Response, HttpError>> {
+        let mut results = Vec::with_capacity(reqs.len());
+        // the requests from results.len() before unanswered were sent without
+        // a response
+        let mut unanswered = 0;
+        while results.len() < reqs.len() {
+            let i = results.len();
+            if i < unanswered && !(reqs[i].is_retryable() && reqs[i].try_rewind()) {
+                results.push(Err(HttpError::ConnectionClosed));
+                continue;
+            }
+            if self.serial {
+                results.push(self.connect().and_then(|c| c.execute_request(&mut reqs[i])));
+                continue;
+            }
+            let end = (i + self.depth).min(reqs.len());
+            match self.pipeline(&mut reqs[i..end]) {
+                Ok(batch) => {
+                    let sent = batch.sent;
+                    results.extend(batch.results);
+                    if results.len() < i + sent {
+                        // closed early
+                        unanswered = i + sent;
+                        self.serial = true;
+                    }
+                }
+                Err(e) => results.push(Err(e)),
+            }
+        }
+        results
+    }
+
+    fn connect(&mut self) -> Result<&mut HttpClient<Transport>, HttpError> {
+        if self.client.as_ref().is_some_and(|c| !c.is_keep_alive()) {
+            self.client = None;
+        }
+        if self.client.is_none() {
+            let conn = transport::connect_with(&self.url, &self.timeouts)?;
+            let mut client = HttpClient::new(conn);
+            client
+                .base_url(transport::base_url(&self.url))
+                .limits(self.limits);
+            self.client = Some(client);
+        }
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    // write reqs, stopping after one which can't be pipelined, and read the
+    // responses until the server closes the connection
+    fn pipeline(&mut self, reqs: &mut [Request]) -> Result<Batch, HttpError> {
+        let client = self.connect()?;
+        let mut sent = 0;
+        for req in reqs.iter_mut() {
+            let alone = !pipelinable(req);
+            if alone && sent > 0 {
+                break;
+            }
+            client.prepare_request(req);
+            // NOTE: a request written partially may have reached the server,
+            // so it is unanswered rather than unsent
+            sent += 1;
+            if client.write_request(req, None).is_err() || alone {
+                break;
+            }
+        }
+
+        let mut results = Vec::with_capacity(sent);
+        for req in &reqs[..sent] {
+            let close = req.has_token(headers::CONNECTION, "close");
+            let result = client
+                .read_response_stream(&req.method, None, close)
+                .and_then(|resp| resp.buffered());
+            match result {
+                // the server closed the connection instead of answering
+                Err(HttpError::ConnectionClosed | HttpError::Io(_)) => break,
+                Err(e) => {
+                    results.push(Err(e));
+                    break;
+                }
+                Ok(resp) => results.push(Ok(resp)),
+            }
+            if !client.is_keep_alive() {
+                break;
+            }
+        }
+        if results.len() < sent || results.last().is_some_and(|r| r.is_err()) {
+            self.client = None;
+        }
+        Ok(Batch { sent, results })
+    }
+}
+
+struct Batch {
+    sent: usize,
+    results: Vec<Result<Response, HttpError>>,
+}
+
+fn pipelinable(req: &Request) -> bool {
+    !req.has_body_reader()
+        && req.body_source.is_none()
+        && !req.has_token(headers::EXPECT, "100-continue")
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::HttpMethod;
+
+    // answers with their targets after batch requests arrived on the first
+    // connection, and closes it after answering close_after of them. later
+    // connections are answered one request at a time
+    fn serve(batch: usize, close_after: usize) -> (String, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (n, conn) in listener.incoming().enumerate() {
+                let mut conn = conn.unwrap();
+                let mut r = BufReader::new(conn.try_clone().unwrap());
+                let want = if n == 0 { batch } else { 1 };
+                'conn: loop {
+                    let mut targets = Vec::new();
+                    while targets.len() < want {
+                        let mut line = String::new();
+                        if r.read_line(&mut line).unwrap_or(0) == 0 {
+                            break 'conn;
+                        }
+                        targets.push(line.split(' ').nth(1).unwrap().to_string());
+                        while r.read_line(&mut line).unwrap() > 0 && !line.ends_with("\r\n\r\n") {}
+                    }
+                    let answer = if n == 0 { close_after.min(want) } else { want };
+                    for target in &targets[..answer] {
+                        let resp = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                            target.len(),
+                            target
+                        );
+                        conn.write_all(resp.as_bytes()).unwrap();
+                    }
+                    tx.send(targets).unwrap();
+                    if answer < want {
+                        break;
+                    }
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    fn body(result: &Result<Response, HttpError>) -> String {
+        result.as_ref().unwrap().text().unwrap()
+    }
+
+    #[test]
+    fn pipeline_in_order() {
+        let (url, rx) = serve(3, 3);
+        let mut client = PipelinedClient::new(&url).unwrap();
+        let mut reqs = (0..3)
+            .map(|i| Request::get(&format!("/containers/{}/json", i)))
+            .collect::<Vec<_>>();
+        let results = client.execute_all(&mut reqs);
+        let bodies = results.iter().map(body).collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [
+                "/containers/0/json",
+                "/containers/1/json",
+                "/containers/2/json"
+            ]
+        );
+        // all were written before the first response
+        assert_eq!(rx.recv().unwrap().len(), 3);
+        let mut reqs = vec![Request::get("/_ping"), Request::get("/_ping")];
+        reqs.push(Request::get("/version"));
+        let results = client.execute_all(&mut reqs);
+        assert_eq!(body(&results[2]), "/version");
+        assert!(!client.is_serial());
+    }
+
+    #[test]
+    fn pipeline_fallback() {
+        let (url, rx) = serve(3, 1);
+        let mut client = PipelinedClient::new(&url).unwrap();
+        let mut post = Request::new("/containers/create");
+        post.method(HttpMethod::Post);
+        let mut reqs = vec![Request::get("/a"), post, Request::get("/c")];
+        let results = client.execute_all(&mut reqs);
+
+        assert_eq!(body(&results[0]), "/a");
+        // POST may have been processed, so it's not sent again
+        assert!(matches!(results[1], Err(HttpError::ConnectionClosed)));
+        assert_eq!(body(&results[2]), "/c");
+        assert!(client.is_serial());
+        assert_eq!(rx.recv().unwrap(), ["/a", "/containers/create", "/c"]);
+        assert_eq!(rx.recv().unwrap(), ["/c"]);
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Token};
+
+use crate::body::CHUNK_SIZE;
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::proto::{self, Event, ResponseDecoder};
+use crate::{encoding, headers, HttpHeader, Request, Response};
+
+pub trait PollStream: Source + Read + Write {}
+
+impl<T> PollStream for T where T: Source + Read + Write {}
+
+enum State {
+    // no request in flight. the connection can be used by send
+    Idle,
+    Writing {
+        data: Vec<u8>,
+        written: usize,
+        decoder: ResponseDecoder,
+    },
+    Reading {
+        decoder: ResponseDecoder,
+        head: Option<Response>,
+        body: Vec<u8>,
+    },
+    Closed,
+}
+
+struct Connection<T> {
+    conn: T,
+    state: State,
+    // read from the connection but not decoded yet
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+// drives requests on many non-blocking connections from one thread. each
+// connection has one request in flight, which advances as mio reports the
+// connection ready:
+//
+//     let token = poller.add(mio::net::UnixStream::connect(path)?)?;
+//     poller.send(token, &mut Request::get("/_ping"))?;
+//     while poller.pending() > 0 {
+//         for (token, result) in poller.poll(None)? { ... }
+//     }
+pub struct Poller<T: PollStream> {
+    poll: Poll,
+    events: Events,
+    conns: HashMap<Token, Connection<T>>,
+    next_token: usize,
+    // finished by send before poll is called
+    done: Vec<(Token, Result<Response, HttpError>)>,
+    decompress: bool,
+    limits: Limits,
+}
+
+impl<T: PollStream> Poller<T> {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(1024),
+            conns: HashMap::new(),
+            next_token: 0,
+            done: Vec::new(),
+            decompress: true,
+            limits: Limits::default(),
+        })
+    }
+
+    pub fn decompress(&mut self, p: bool) -> &mut Self {
+        self.decompress = p;
+        self
+    }
+
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // register a connection in non-blocking mode, e.g. mio::net::UnixStream
+    pub fn add(&mut self, mut conn: T) -> io::Result<Token> {
+        let token = Token(self.next_token);
+        self.next_token += 1;
+        self.poll
+            .registry()
+            .register(&mut conn, token, Interest::READABLE | Interest::WRITABLE)?;
+        self.conns.insert(
+            token,
+            Connection {
+                conn,
+                state: State::Idle,
+                buf: Vec::new(),
+                eof: false,
+            },
+        );
+        Ok(token)
+    }
+
+    // deregister the connection and give it back
+    pub fn remove(&mut self, token: Token) -> Option<T> {
+        let mut conn = self.conns.remove(&token)?;
+        let _ = self.poll.registry().deregister(&mut conn.conn);
+        Some(conn.conn)
+    }
+
+    // the number of requests in flight
+    pub fn pending(&self) -> usize {
+        let in_flight = self
+            .conns
+            .values()
+            .filter(|c| matches!(c.state, State::Writing { .. } | State::Reading { .. }))
+            .count();
+        in_flight + self.done.len()
+    }
+
+    // start the request on the idle connection. the response is returned by poll
+    // NOTE: a body reader is read into memory at once
+    pub fn send(&mut self, token: Token, req: &mut Request) -> Result<(), HttpError> {
+        let conn = self
+            .conns
+            .get_mut(&token)
+            .ok_or_else(|| format!("unknown connection: {}", token.0))?;
+        match conn.state {
+            State::Idle => {}
+            State::Closed => return Err(HttpError::ConnectionClosed),
+            _ => return Err(format!("request in flight on connection: {}", token.0).into()),
+        }
+        if self.decompress && !req.has_header(headers::ACCEPT_ENCODING) {
+            req.header
+                .get_or_insert_with(HttpHeader::new)
+                .add(headers::ACCEPT_ENCODING, &encoding::accept_encoding());
+        }
+        let mut data = req.build()?;
+        req.write_body_reader(&mut data)?;
+        let close = req.has_token(headers::CONNECTION, "close");
+        let mut decoder = ResponseDecoder::new(self.limits, close);
+        decoder.method(req.method.clone());
+        conn.state = State::Writing {
+            data,
+            written: 0,
+            decoder,
+        };
+        // NOTE: readiness is edge-triggered, so the connection may already be
+        // writable without a new event
+        if let Some(result) = advance(conn, self.decompress, self.limits.max_body_size) {
+            self.done.push((token, result));
+        }
+        Ok(())
+    }
+
+    // wait until some connections are ready and return the finished responses.
+    // a connection is Idle again after its response unless it was closed
+    pub fn poll(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Vec<(Token, Result<Response, HttpError>)>> {
+        let mut done = std::mem::take(&mut self.done);
+        if !done.is_empty() {
+            return Ok(done);
+        }
+        loop {
+            match self.poll.poll(&mut self.events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+            break;
+        }
+        for event in self.events.iter() {
+            let Some(conn) = self.conns.get_mut(&event.token()) else {
+                continue;
+            };
+            if let Some(result) = advance(conn, self.decompress, self.limits.max_body_size) {
+                done.push((event.token(), result));
+            }
+        }
+        Ok(done)
+    }
+}
+
+// make progress until the connection would block. returns the result when the
+// request is finished
+fn advance<T: PollStream>(
+    conn: &mut Connection<T>,
+    decompress: bool,
+    max_body_size: Option<u64>,
+) -> Option<Result<Response, HttpError>> {
+    match step(conn, decompress, max_body_size) {
+        Ok(Some(resp)) => Some(Ok(resp)),
+        Ok(None) => None,
+        Err(e) => {
+            conn.state = State::Closed;
+            Some(Err(e))
+        }
+    }
+}
+
+fn step<T: PollStream>(
+    conn: &mut Connection<T>,
+    decompress: bool,
+    max_body_size: Option<u64>,
+) -> Result<Option<Response>, HttpError> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        match &mut conn.state {
+            State::Idle | State::Closed => return Ok(None),
+            State::Writing { data, written, .. } if *written < data.len() => {
+                match conn.conn.write(&data[*written..]) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                    Ok(n) => *written += n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            State::Writing { .. } => {
+                let State::Writing { decoder, .. } =
+                    std::mem::replace(&mut conn.state, State::Closed)
+                else {
+                    unreachable!();
+                };
+                conn.state = State::Reading {
+                    decoder,
+                    head: None,
+                    body: Vec::new(),
+                };
+            }
+            State::Reading {
+                decoder,
+                head,
+                body,
+            } => match decoder.decode(&mut conn.buf, conn.eof)? {
+                Some(Event::Informational(_)) => {}
+                Some(Event::Head(resp)) => *head = Some(resp),
+                Some(Event::Data(data)) => body.extend_from_slice(&data),
+                Some(Event::End) => {
+                    let keep_alive = decoder.is_keep_alive();
+                    let mut resp = head
+                        .take()
+                        .ok_or_else(|| HttpError::Parse("missing response head".into()))?;
+                    resp.trailers = decoder.take_trailers();
+                    let body = std::mem::take(body);
+                    conn.state = if keep_alive {
+                        State::Idle
+                    } else {
+                        State::Closed
+                    };
+                    let body = match decompress {
+                        true => proto::decode_body(&mut resp.header, body, max_body_size)?,
+                        false => body,
+                    };
+                    return Ok(Some(resp.with_body(Some(body.into()))));
+                }
+                None => match conn.conn.read(&mut buf) {
+                    Ok(n) => {
+                        conn.eof = n == 0;
+                        conn.buf.extend_from_slice(&buf[..n]);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+
+    #[test]
+    fn poll_many_connections() {
+        let mut poller = Poller::new().unwrap();
+        let mut servers = Vec::new();
+        let mut tokens = Vec::new();
+        for _ in 0..3 {
+            let (client, server) = UnixStream::pair().unwrap();
+            client.set_nonblocking(true).unwrap();
+            let token = poller.add(mio::net::UnixStream::from_std(client)).unwrap();
+            tokens.push(token);
+            servers.push(server);
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            let mut req = Request::get(&format!("/containers/{}/json", i));
+            poller.send(*token, &mut req).unwrap();
+        }
+        assert!(poller.send(tokens[0], &mut Request::get("/")).is_err());
+
+        // respond in reverse order from another thread
+        let server = std::thread::spawn(move || {
+            for (i, server) in servers.iter_mut().enumerate().rev() {
+                let mut r = BufReader::new(&*server);
+                let mut line = String::new();
+                r.read_line(&mut line).unwrap();
+                assert_eq!(line, format!("GET /containers/{}/json HTTP/1.1\r\n", i));
+                while line != "\r\n" {
+                    line.clear();
+                    r.read_line(&mut line).unwrap();
+                }
+                let body = format!("{{\"id\":{}}}", i);
+                write!(
+                    server,
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let mut responses = HashMap::new();
+        while poller.pending() > 0 {
+            for (token, result) in poller.poll(Some(Duration::from_secs(5))).unwrap() {
+                responses.insert(token, result.unwrap());
+            }
+        }
+        server.join().unwrap();
+        for (i, token) in tokens.iter().enumerate() {
+            let body = responses[token].body.as_deref().unwrap();
+            assert_eq!(body, format!("{{\"id\":{}}}", i).as_bytes());
+        }
+
+        // the connections are reusable
+        poller.send(tokens[1], &mut Request::get("/_ping")).unwrap();
+        assert_eq!(poller.pending(), 1);
+        assert!(poller.remove(tokens[1]).is_some());
+    }
+
+    #[test]
+    fn poll_decompress_limit() {
+        let mut poller = Poller::new().unwrap();
+        poller.decompress(true).limits(Limits {
+            max_body_size: Some(4096),
+            ..Limits::default()
+        });
+        let (client, mut server) = UnixStream::pair().unwrap();
+        client.set_nonblocking(true).unwrap();
+        let token = poller.add(mio::net::UnixStream::from_std(client)).unwrap();
+        poller.send(token, &mut Request::get("/")).unwrap();
+
+        // a small body which decodes to 1 MiB
+        let gzip = encoding::Compression::Gzip
+            .encode(&vec![0u8; 1024 * 1024])
+            .unwrap();
+        assert!(gzip.len() < 4096);
+        write!(
+            server,
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzip.len()
+        )
+        .unwrap();
+        server.write_all(&gzip).unwrap();
+
+        let mut results = Vec::new();
+        while poller.pending() > 0 {
+            results.extend(poller.poll(Some(Duration::from_secs(5))).unwrap());
+        }
+        let err = results.pop().unwrap().1.unwrap_err();
+        assert!(matches!(err, HttpError::BodyTooLarge(4096)), "{}", err);
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::redirect;
+use crate::resolver::{Resolver, SystemResolver};
+use crate::retry::RetryPolicy;
+use crate::sockopt::SocketOptions;
+use crate::transport::{self, Timeouts, Transport};
+use crate::tunnel::ProxyEnv;
+use crate::url::Url;
+use crate::uuid;
+use crate::{headers, HttpClient, HttpHeader, HttpMethod, Request, Response};
+
+struct IdleClient {
+    client: HttpClient<Transport>,
+    idle_since: Instant,
+}
+
+// keeps idle keep-alive connections per destination and reuses them
+pub struct ClientPool {
+    idle: Mutex<HashMap<String, Vec<IdleClient>>>,
+    base_url: Option<Url>,
+    max_idle: usize,
+    idle_timeout: Duration,
+    timeouts: Timeouts,
+    socket_options: SocketOptions,
+    resolver: Arc<dyn Resolver>,
+    retry: RetryPolicy,
+    max_redirects: usize,
+    decompress: bool,
+    limits: Limits,
+    // None reads the environment for each new connection
+    proxy_env: Option<ProxyEnv>,
+    idempotency_key: bool,
+}
+
+impl Default for ClientPool {
+    fn default() -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            base_url: None,
+            max_idle: 8,
+            idle_timeout: Duration::from_secs(90),
+            timeouts: Timeouts::default(),
+            socket_options: SocketOptions::default(),
+            resolver: Arc::new(SystemResolver),
+            retry: RetryPolicy::never(),
+            max_redirects: 0,
+            decompress: true,
+            limits: Limits::default(),
+            proxy_env: None,
+            idempotency_key: false,
+        }
+    }
+}
+
+// destination of url, e.g. `unix:/var/run/docker.sock` or `http:localhost:2375`
+pub fn pool_key(url: &Url) -> Result<String, String> {
+    let scheme = url
+        .scheme
+        .as_deref()
+        .ok_or_else(|| format!("relative url without base url: {}", url))?;
+    let dest = match (&url.socket_path, &url.host) {
+        (Some(path), _) => path.clone(),
+        (None, Some(host)) => {
+            let port = url.port.or(url.default_port()).unwrap_or_default();
+            format!("{}:{}", host, port)
+        }
+        (None, None) => return Err(format!("missing destination: {}", url)),
+    };
+    Ok(format!("{}:{}", scheme, dest))
+}
+
+impl ClientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // relative request urls are resolved against this url
+    pub fn base_url(&mut self, p: Url) -> &mut Self {
+        self.base_url = Some(p);
+        self
+    }
+
+    // max number of idle connections per destination
+    pub fn max_idle(&mut self, p: usize) -> &mut Self {
+        self.max_idle = p;
+        self
+    }
+
+    pub fn idle_timeout(&mut self, p: Duration) -> &mut Self {
+        self.idle_timeout = p;
+        self
+    }
+
+    // applied to new connections
+    pub fn timeouts(&mut self, p: Timeouts) -> &mut Self {
+        self.timeouts = p;
+        self
+    }
+
+    // resolves the hosts of new connections, e.g. a CachingResolver shared by
+    // pools
+    pub fn resolver(&mut self, p: Arc<dyn Resolver>) -> &mut Self {
+        self.resolver = p;
+        self
+    }
+
+    // applied to new connections, e.g. TCP_NODELAY
+    pub fn socket_options(&mut self, p: SocketOptions) -> &mut Self {
+        self.socket_options = p;
+        self
+    }
+
+    pub fn retry(&mut self, p: RetryPolicy) -> &mut Self {
+        self.retry = p;
+        self
+    }
+
+    // follow up to max redirects. 0 (default) returns redirect responses as is
+    pub fn max_redirects(&mut self, p: usize) -> &mut Self {
+        self.max_redirects = p;
+        self
+    }
+
+    // see HttpClient::decompress
+    pub fn decompress(&mut self, p: bool) -> &mut Self {
+        self.decompress = p;
+        self
+    }
+
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // proxies of new connections instead of http_proxy, https_proxy and
+    // no_proxy. ProxyEnv::default() always connects directly
+    pub fn proxy_env(&mut self, p: ProxyEnv) -> &mut Self {
+        self.proxy_env = Some(p);
+        self
+    }
+
+    // attach a random Idempotency-Key to POST and PATCH requests without one,
+    // which makes them retryable. the key is kept by the request, so retries
+    // and redirects of it send the same key
+    pub fn idempotency_key(&mut self, p: bool) -> &mut Self {
+        self.idempotency_key = p;
+        self
+    }
+
+    pub fn idle_count(&self, url: &Url) -> usize {
+        let Ok(key) = pool_key(url) else {
+            return 0;
+        };
+        let idle = self.idle.lock().unwrap();
+        idle.get(&key).map(|v| v.len()).unwrap_or(0)
+    }
+
+    fn checkout(&self, key: &str) -> Option<HttpClient<Transport>> {
+        let mut idle = self.idle.lock().unwrap();
+        let clients = idle.get_mut(key)?;
+        clients.retain(|c| c.idle_since.elapsed() < self.idle_timeout);
+        // the most recently used connection is the most likely to be alive
+        clients.pop().map(|c| c.client)
+    }
+
+    fn checkin(&self, key: String, client: HttpClient<Transport>) {
+        if !client.is_keep_alive() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let clients = idle.entry(key).or_default();
+        clients.retain(|c| c.idle_since.elapsed() < self.idle_timeout);
+        if clients.len() < self.max_idle {
+            clients.push(IdleClient {
+                client,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    fn dial(&self, url: &Url) -> Result<HttpClient<Transport>, HttpError> {
+        let env = self.proxy_env.clone().unwrap_or_else(ProxyEnv::from_env);
+        let conn = transport::connect_with_proxy_env(
+            url,
+            &self.timeouts,
+            &self.socket_options,
+            self.resolver.as_ref(),
+            &env,
+        )?;
+        let mut client = HttpClient::from_connection(conn, url);
+        client.decompress(self.decompress).limits(self.limits);
+        Ok(client)
+    }
+
+    pub fn execute_request(&self, req: &mut Request) -> Result<Response, HttpError> {
+        if let Some(base_url) = &self.base_url {
+            if !req.url.is_absolute() {
+                req.url = base_url.resolve(&req.url);
+            }
+        }
+        if self.idempotency_key && matches!(req.method, HttpMethod::Post | HttpMethod::Patch) {
+            let header = req.header.get_or_insert_with(HttpHeader::new);
+            if !header.contains(headers::IDEMPOTENCY_KEY) {
+                header.add(headers::IDEMPOTENCY_KEY, &uuid::v4());
+            }
+        }
+
+        let mut redirects = 0;
+        loop {
+            let resp = self.execute_with_retry(req)?;
+            if self.max_redirects == 0 || !redirect::follow(req, &resp)? {
+                return Ok(resp);
+            }
+            redirects += 1;
+            if redirects > self.max_redirects {
+                return Err(HttpError::TooManyRedirects(self.max_redirects));
+            }
+        }
+    }
+
+    fn execute_with_retry(&self, req: &mut Request) -> Result<Response, HttpError> {
+        let mut attempts = 1;
+        loop {
+            let result = self.execute_once(req);
+            let retry = match &result {
+                Ok(resp) => self.retry.is_retryable_status(resp.status),
+                Err(e) => self.retry.is_retryable_error(e),
+            };
+            if !retry
+                || !req.is_retryable()
+                || !self.retry.should_retry(attempts)
+                || !req.try_rewind()
+            {
+                return result;
+            }
+            thread::sleep(self.retry.backoff(attempts - 1));
+            attempts += 1;
+        }
+    }
+
+    fn execute_once(&self, req: &mut Request) -> Result<Response, HttpError> {
+        let key = pool_key(&req.url)?;
+
+        if let Some(mut client) = self.checkout(&key) {
+            match client.execute_request(req) {
+                Ok(resp) => {
+                    self.checkin(key, client);
+                    return Ok(resp);
+                }
+                // the server may have closed the idle connection. only requests
+                // which are safe to send twice are retried on a new connection,
+                // see Request::is_retryable
+                Err(e) if !req.is_retryable() || !req.try_rewind() => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        let mut client = self.dial(&req.url)?;
+        let resp = client.execute_request(req)?;
+        self.checkin(key, client);
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::resolver::StaticResolver;
+    use crate::status::StatusCode;
+    use crate::HttpMethod;
+
+    // keep-alive server which counts accepted connections
+    fn serve(accepted: Arc<AtomicUsize>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for conn in listener.incoming() {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let mut conn = conn.unwrap();
+                std::thread::spawn(move || {
+                    let mut r = BufReader::new(conn.try_clone().unwrap());
+                    loop {
+                        let mut line = String::new();
+                        loop {
+                            line.clear();
+                            if r.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            if line == "\r\n" {
+                                break;
+                            }
+                        }
+                        conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn pool_reuse() {
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let port = serve(accepted.clone());
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut pool = ClientPool::new();
+        pool.base_url(base.clone());
+        for _ in 0..3 {
+            let resp = pool.execute_request(&mut Request::get("/_ping")).unwrap();
+            assert_eq!(resp.body, Some(b"ok".into()));
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.idle_count(&base), 1);
+    }
+
+    #[test]
+    fn pool_resolver() {
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let port = serve(accepted.clone());
+
+        let mut resolver = StaticResolver::new();
+        resolver.host("docker.test", &["127.0.0.1".parse().unwrap()]);
+        let mut pool = ClientPool::new();
+        pool.resolver(Arc::new(resolver))
+            .proxy_env(ProxyEnv::default());
+        let url = format!("http://docker.test:{}/_ping", port);
+        let resp = pool.execute_request(&mut Request::get(&url)).unwrap();
+        assert_eq!(resp.body, Some(b"ok".into()));
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn pool_idle_timeout() {
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let port = serve(accepted.clone());
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut pool = ClientPool::new();
+        pool.base_url(base.clone()).idle_timeout(Duration::ZERO);
+        for _ in 0..3 {
+            pool.execute_request(&mut Request::get("/_ping")).unwrap();
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+
+        let mut pool = ClientPool::new();
+        pool.base_url(base.clone()).max_idle(0);
+        pool.execute_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!(pool.idle_count(&base), 0);
+    }
+
+    #[test]
+    fn pool_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // 503 except for the third and the sixth request
+        let server = std::thread::spawn(move || {
+            let mut methods = Vec::new();
+            for (i, conn) in listener.incoming().enumerate() {
+                let mut conn = conn.unwrap();
+                let mut r = BufReader::new(conn.try_clone().unwrap());
+                let mut line = String::new();
+                r.read_line(&mut line).unwrap();
+                methods.push(line.split(' ').next().unwrap().to_string());
+                while r.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let resp = if i != 2 && i != 5 {
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                };
+                conn.write_all(resp.as_bytes()).unwrap();
+                if methods.len() == 6 {
+                    return methods;
+                }
+            }
+            unreachable!()
+        });
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut policy = RetryPolicy::new();
+        policy.backoff_base(Duration::from_millis(1));
+        let mut pool = ClientPool::new();
+        pool.base_url(base).retry(policy);
+
+        let resp = pool.execute_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+
+        // POST is not retried unless marked
+        let mut req = Request::new("/build");
+        req.method(HttpMethod::Post);
+        let resp = pool.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status, StatusCode::SERVICE_UNAVAILABLE);
+        let mut req = Request::new("/build");
+        req.method(HttpMethod::Post).retryable(true);
+        let resp = pool.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+
+        assert_eq!(
+            server.join().unwrap(),
+            ["GET", "GET", "GET", "POST", "POST", "POST"]
+        );
+    }
+
+    #[test]
+    fn pool_redirect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut r = BufReader::new(conn.try_clone().unwrap());
+            loop {
+                let mut request_line = String::new();
+                if r.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let mut line = String::new();
+                while r.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let resp = match request_line.split(' ').nth(1).unwrap() {
+                    "/loop" => "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n",
+                    "/old" => "HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\nContent-Length: 0\r\n\r\n",
+                    _ => "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+                };
+                conn.write_all(resp.as_bytes()).unwrap();
+            }
+        });
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut pool = ClientPool::new();
+        pool.base_url(base);
+        let resp = pool.execute_request(&mut Request::get("/old")).unwrap();
+        assert_eq!(resp.status, StatusCode::MOVED_PERMANENTLY);
+
+        pool.max_redirects(3);
+        let mut req = Request::get("/old");
+        let resp = pool.execute_request(&mut req).unwrap();
+        assert_eq!(resp.body, Some(b"ok".into()));
+        assert_eq!(req.url.path, "/new");
+
+        let err = pool
+            .execute_request(&mut Request::get("/loop"))
+            .unwrap_err();
+        assert!(matches!(err, HttpError::TooManyRedirects(3)), "{}", err);
+    }
+
+    #[test]
+    fn pool_key_of_url() {
+        let url = Url::parse("unix:///var/run/docker.sock:/_ping").unwrap();
+        assert_eq!(pool_key(&url).unwrap(), "unix:/var/run/docker.sock");
+        let url = Url::parse("http://localhost/_ping").unwrap();
+        assert_eq!(pool_key(&url).unwrap(), "http:localhost:80");
+        assert!(pool_key(&Url::parse("/_ping").unwrap()).is_err());
+    }
+
+    #[test]
+    fn pool_idempotency_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // 503 to the first request, and the keys received
+        let server = std::thread::spawn(move || {
+            let mut keys = Vec::new();
+            for (i, conn) in listener.incoming().enumerate() {
+                let mut conn = conn.unwrap();
+                let mut r = BufReader::new(conn.try_clone().unwrap());
+                let mut line = String::new();
+                let mut key = None;
+                while r.read_line(&mut line).unwrap() > 2 {
+                    if let Some(v) = line.strip_prefix("Idempotency-Key: ") {
+                        key = Some(v.trim().to_string());
+                    }
+                    line.clear();
+                }
+                keys.push(key);
+                let resp = if i == 0 {
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                };
+                conn.write_all(resp.as_bytes()).unwrap();
+                if keys.len() == 4 {
+                    return keys;
+                }
+            }
+            unreachable!()
+        });
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut policy = RetryPolicy::new();
+        policy.backoff_base(Duration::from_millis(1));
+        let mut pool = ClientPool::new();
+        pool.base_url(base).retry(policy).idempotency_key(true);
+
+        // POST is retried with the same key
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post);
+        let resp = pool.execute_request(&mut req).unwrap();
+        assert_eq!(resp.status, StatusCode::OK);
+        // a key of the caller is kept, GET has none
+        let mut header = HttpHeader::new();
+        header.add("Idempotency-Key", "abc");
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post).header(header);
+        pool.execute_request(&mut req).unwrap();
+        pool.execute_request(&mut Request::get("/_ping")).unwrap();
+
+        let keys = server.join().unwrap();
+        let first = keys[0].clone().unwrap();
+        assert_eq!(first.len(), 36);
+        assert_eq!(
+            keys,
+            [Some(first.clone()), Some(first), Some("abc".into()), None]
+        );
+    }
+
+    #[test]
+    fn pool_stale_keyed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // closes each connection after one response, without telling
+        std::thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let mut r = BufReader::new(conn.try_clone().unwrap());
+                let mut line = String::new();
+                while r.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+            }
+        });
+        let base = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+
+        let mut pool = ClientPool::new();
+        pool.base_url(base.clone()).idempotency_key(true);
+        pool.execute_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!(pool.idle_count(&base), 1);
+        // a POST with Idempotency-Key is sent again on a new connection
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post);
+        let resp = pool.execute_request(&mut req).unwrap();
+        assert_eq!(resp.body, Some(b"ok".into()));
+    }
+}
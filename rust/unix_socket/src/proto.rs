@@ -0,0 +1,611 @@
+use std::io::{self, Read};
+
+use crate::error::HttpError;
+use crate::limits::{LimitReader, Limits};
+use crate::status::StatusCode;
+use crate::{encoding, headers, read_response_head, HttpHeader, HttpMethod, HttpVersion, Response};
+
+// how the end of the response body is known (RFC 9112 6.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    // HTTP/1.0 or `Connection: close` without Content-Length
+    Close,
+}
+
+// method is the one of the request, since a response to HEAD has no body even
+// with Content-Length
+pub fn framing(
+    resp: &Response,
+    method: &HttpMethod,
+    limits: &Limits,
+) -> Result<Framing, HttpError> {
+    if *method == HttpMethod::Head
+        || resp.status.is_informational()
+        || matches!(resp.status.as_u16(), 204 | 304)
+        || is_tunnel(resp, method)
+    {
+        return Ok(Framing::Empty);
+    }
+    let header = &resp.header;
+    // HTTP/1.0 doesn't know chunked transfer coding, so the body is delimited by
+    // content-length or by closing the connection
+    if resp.version == HttpVersion::Http11 && header.is_chunked() {
+        return Ok(Framing::Chunked);
+    }
+    if !header.contains(headers::CONTENT_LENGTH)
+        && (resp.version == HttpVersion::Http10 || header.has_token(headers::CONNECTION, "close"))
+    {
+        return Ok(Framing::Close);
+    }
+    let Some(length) = header.content_length() else {
+        return Err(HttpError::Parse(
+            "missing transfer-encoding or content-length".into(),
+        ));
+    };
+    if let Some(max) = limits.max_body_size.filter(|max| length > *max) {
+        return Err(HttpError::BodyTooLarge(max));
+    }
+    Ok(Framing::Length(length))
+}
+
+// the size line of a chunk, e.g. `1a;name=value\r\n`. chunk extensions are ignored
+pub fn parse_chunk_size(line: &[u8]) -> io::Result<u64> {
+    let invalid_data = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let line = std::str::from_utf8(line)
+        .map_err(|_| invalid_data("cannot convert bytes to string".into()))?;
+    let size = line.split(';').next().unwrap_or_default().trim();
+    u64::from_str_radix(size, 16).map_err(|err| {
+        invalid_data(format!(
+            "cannot read chunk length: {}: {}",
+            line.trim(),
+            err
+        ))
+    })
+}
+
+// a trailer field after the last chunk, e.g. `Grpc-Status: 0\r\n`
+pub fn parse_trailer(line: &[u8]) -> Result<(&str, &str), String> {
+    let line = std::str::from_utf8(line).map_err(|_| "cannot convert bytes to string")?;
+    let (key, value) = line
+        .split_once(':')
+        .ok_or_else(|| format!("invalid trailer: {}", line.trim()))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("invalid trailer key".into());
+    }
+    Ok((key, value.trim()))
+}
+
+// the trailers are limited like the header, see ChunkedReader and ResponseDecoder
+pub fn append_trailer(
+    trailers: &mut HttpHeader,
+    line: &[u8],
+    limits: &Limits,
+) -> Result<(), HttpError> {
+    if trailers.len() >= limits.max_headers {
+        return Err(HttpError::TooManyHeaders(limits.max_headers));
+    }
+    let (key, value) = parse_trailer(line).map_err(HttpError::Parse)?;
+    trailers.append(key, value);
+    Ok(())
+}
+
+// undo Content-Encoding of the whole body. the header is updated to describe
+// the decoded body
+pub fn decode_body(
+    header: &mut HttpHeader,
+    body: Vec<u8>,
+    max_body_size: Option<u64>,
+) -> Result<Vec<u8>, HttpError> {
+    // NOTE: an empty body is not valid input for the decoders
+    let Some(encoding) = header
+        .get(headers::CONTENT_ENCODING)
+        .filter(|_| !body.is_empty())
+    else {
+        return Ok(body);
+    };
+    let Some(encodings) = encoding::parse_content_encoding(encoding) else {
+        return Ok(body);
+    };
+    // decoded through LimitReader, so a small compressed body can't expand
+    // without limit before the size is checked
+    let mut decoded = Vec::new();
+    encoding::decoder(&encodings, Box::new(&body[..]))
+        .and_then(|mut r| match max_body_size {
+            Some(max) => LimitReader::new(r, max).read_to_end(&mut decoded),
+            None => r.read_to_end(&mut decoded),
+        })
+        .map_err(|e| match HttpError::from(e) {
+            HttpError::Io(e) => HttpError::Parse(format!("cannot decode response body: {}", e)),
+            e => e,
+        })?;
+    header.remove(headers::CONTENT_ENCODING);
+    header.remove(headers::CONTENT_LENGTH);
+    Ok(decoded)
+}
+
+// the connection is no longer HTTP after resp, e.g. a proxy tunnel
+pub fn is_tunnel(resp: &Response, method: &HttpMethod) -> bool {
+    resp.status == StatusCode::SWITCHING_PROTOCOLS
+        || *method == HttpMethod::Connect && resp.status.is_success()
+}
+
+// 1xx responses before the final one. 101 Switching Protocols is final, the
+// connection is handed over after it
+pub fn is_interim(resp: &Response) -> bool {
+    resp.status.is_informational() && resp.status != StatusCode::SWITCHING_PROTOCOLS
+}
+
+// count is the number of interim responses read so far. they are limited like
+// the header fields, so a server can't send them without end
+pub fn check_interim(count: usize, limits: &Limits) -> Result<(), HttpError> {
+    if count > limits.max_headers {
+        return Err(HttpError::Parse(format!(
+            "too many interim responses (max {})",
+            limits.max_headers
+        )));
+    }
+    Ok(())
+}
+
+fn is_empty_line(line: &[u8]) -> bool {
+    line == b"\r\n" || line == b"\n"
+}
+
+#[derive(Debug)]
+pub enum Event {
+    // an interim 1xx response like 103 Early Hints, the final Head follows
+    Informational(Response),
+    // status line and headers. the body follows as Data
+    Head(Response),
+    // a piece of the body. a piece doesn't span transfer chunks
+    Data(Vec<u8>),
+    // the end of the response
+    End,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Head,
+    // remaining bytes of the body
+    Length(u64),
+    ChunkSize,
+    // remaining bytes of the current chunk
+    Chunk(u64),
+    ChunkEnd,
+    Trailers,
+    Close,
+    Done,
+}
+
+// parser of a response which doesn't do I/O. the caller reads from the
+// connection into a buffer and passes it to decode until it returns an event:
+//
+//     loop {
+//         if let Some(event) = decoder.decode(&mut buf, eof)? {
+//             break event;
+//         }
+//         // read more into buf, eof is true when the read returned 0
+//     }
+pub struct ResponseDecoder {
+    limits: Limits,
+    // the request asked to close the connection
+    close: bool,
+    method: HttpMethod,
+    state: State,
+    keep_alive: bool,
+    // the body size read so far
+    read: u64,
+    trailers: HttpHeader,
+    interim: usize,
+}
+
+impl ResponseDecoder {
+    pub fn new(limits: Limits, close: bool) -> Self {
+        Self {
+            limits,
+            close,
+            method: HttpMethod::Get,
+            state: State::Head,
+            keep_alive: false,
+            read: 0,
+            trailers: HttpHeader::new(),
+            interim: 0,
+        }
+    }
+
+    // the method of the request, see framing
+    pub fn method(&mut self, p: HttpMethod) -> &mut Self {
+        self.method = p;
+        self
+    }
+
+    // whether the connection can be reused. only true after End
+    pub fn is_keep_alive(&self) -> bool {
+        matches!(self.state, State::Done) && self.keep_alive
+    }
+
+    // the trailer fields of a chunked body, complete after End
+    pub fn take_trailers(&mut self) -> HttpHeader {
+        std::mem::take(&mut self.trailers)
+    }
+
+    // consume bytes at the front of buf. None means more input is needed, eof
+    // tells the decoder that no more input comes
+    pub fn decode(&mut self, buf: &mut Vec<u8>, eof: bool) -> Result<Option<Event>, HttpError> {
+        let result = self.decode_event(buf, eof);
+        if result.is_err() {
+            self.keep_alive = false;
+            self.state = State::Done;
+        }
+        result
+    }
+
+    fn decode_event(&mut self, buf: &mut Vec<u8>, eof: bool) -> Result<Option<Event>, HttpError> {
+        loop {
+            match self.state {
+                State::Head => return self.decode_head(buf, eof),
+                State::Done => return Ok(Some(Event::End)),
+                State::Length(0) => {
+                    self.state = State::Done;
+                    return Ok(Some(Event::End));
+                }
+                State::Length(remaining) => {
+                    if buf.is_empty() && eof {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!("body is {} bytes shorter than content-length", remaining),
+                        )
+                        .into());
+                    }
+                    let data = self.take_data(buf, remaining)?;
+                    self.state = State::Length(remaining - data.len() as u64);
+                    return Ok((!data.is_empty()).then_some(Event::Data(data)));
+                }
+                State::ChunkSize => {
+                    let Some(line) = self.take_line(buf, eof)? else {
+                        return Ok(None);
+                    };
+                    let size = parse_chunk_size(&line)?;
+                    if let Some(max) = self.limits.max_chunk_size.filter(|max| size > *max) {
+                        return Err(HttpError::ChunkTooLarge(max));
+                    }
+                    self.state = match size {
+                        0 => State::Trailers,
+                        size => State::Chunk(size),
+                    };
+                }
+                State::Chunk(remaining) => {
+                    if buf.is_empty() && eof {
+                        return Err(HttpError::Parse("unexpected endof".into()));
+                    }
+                    let data = self.take_data(buf, remaining)?;
+                    let remaining = remaining - data.len() as u64;
+                    self.state = match remaining {
+                        0 => State::ChunkEnd,
+                        remaining => State::Chunk(remaining),
+                    };
+                    return Ok((!data.is_empty()).then_some(Event::Data(data)));
+                }
+                State::ChunkEnd => {
+                    let Some(line) = self.take_line(buf, eof)? else {
+                        return Ok(None);
+                    };
+                    if !is_empty_line(&line) {
+                        return Err(HttpError::Parse("invalid chunk terminator".into()));
+                    }
+                    self.state = State::ChunkSize;
+                }
+                // trailers are collected for take_trailers
+                State::Trailers => {
+                    let line = match self.take_line(buf, eof) {
+                        Ok(Some(line)) => line,
+                        Ok(None) => return Ok(None),
+                        // a missing end of trailers is tolerated like ChunkedReader
+                        Err(_) if buf.is_empty() => Vec::new(),
+                        Err(e) => return Err(e),
+                    };
+                    if line.is_empty() || is_empty_line(&line) {
+                        self.state = State::Done;
+                        return Ok(Some(Event::End));
+                    }
+                    append_trailer(&mut self.trailers, &line, &self.limits)?;
+                }
+                State::Close => {
+                    if buf.is_empty() {
+                        if !eof {
+                            return Ok(None);
+                        }
+                        self.keep_alive = false;
+                        self.state = State::Done;
+                        return Ok(Some(Event::End));
+                    }
+                    let data = self.take_data(buf, u64::MAX)?;
+                    return Ok(Some(Event::Data(data)));
+                }
+            }
+        }
+    }
+
+    fn decode_head(&mut self, buf: &mut Vec<u8>, eof: bool) -> Result<Option<Event>, HttpError> {
+        let max = self.limits.max_header_line;
+        let mut start = 0;
+        // status line, headers and the empty line
+        for i in 0..self.limits.max_headers + 2 {
+            let Some(end) = buf[start..].iter().position(|b| *b == b'\n') else {
+                if buf.len() - start > max {
+                    return Err(HttpError::HeaderLineTooLong(max));
+                }
+                return match eof {
+                    true if buf.is_empty() => Err(HttpError::ConnectionClosed),
+                    true => Err(HttpError::Parse("unexpected endof".into())),
+                    false => Ok(None),
+                };
+            };
+            let line = &buf[start..start + end + 1];
+            if line.len() > max {
+                return Err(HttpError::HeaderLineTooLong(max));
+            }
+            start += line.len();
+            if i > 0 && is_empty_line(line) {
+                let resp = read_response_head(&mut &buf[..start], &self.limits, &mut Vec::new())?;
+                buf.drain(..start);
+                if is_interim(&resp) {
+                    self.interim += 1;
+                    check_interim(self.interim, &self.limits)?;
+                    return Ok(Some(Event::Informational(resp)));
+                }
+                self.keep_alive =
+                    !self.close && resp.is_keep_alive() && !is_tunnel(&resp, &self.method);
+                self.state = match framing(&resp, &self.method, &self.limits)? {
+                    Framing::Empty => State::Length(0),
+                    Framing::Length(length) => State::Length(length),
+                    Framing::Chunked => State::ChunkSize,
+                    Framing::Close => {
+                        self.keep_alive = false;
+                        State::Close
+                    }
+                };
+                return Ok(Some(Event::Head(resp)));
+            }
+        }
+        Err(HttpError::TooManyHeaders(self.limits.max_headers))
+    }
+
+    fn take_line(&mut self, buf: &mut Vec<u8>, eof: bool) -> Result<Option<Vec<u8>>, HttpError> {
+        match buf.iter().position(|b| *b == b'\n') {
+            Some(end) => Ok(Some(buf.drain(..end + 1).collect())),
+            None if eof => Err(HttpError::Parse("unexpected endof".into())),
+            None => Ok(None),
+        }
+    }
+
+    fn take_data(&mut self, buf: &mut Vec<u8>, max: u64) -> Result<Vec<u8>, HttpError> {
+        let n = buf.len().min(max.min(usize::MAX as u64) as usize);
+        self.read += n as u64;
+        if let Some(max) = self.limits.max_body_size.filter(|max| self.read > *max) {
+            return Err(HttpError::BodyTooLarge(max));
+        }
+        Ok(buf.drain(..n).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // feed input in pieces of n bytes and collect the events
+    fn decode_all(input: &[u8], n: usize) -> Result<(Vec<Event>, bool), HttpError> {
+        let mut decoder = ResponseDecoder::new(Limits::default(), false);
+        let mut buf = Vec::new();
+        let mut pieces = input.chunks(n);
+        let mut events = Vec::new();
+        loop {
+            let eof = pieces.len() == 0;
+            match decoder.decode(&mut buf, eof)? {
+                Some(Event::End) => return Ok((events, decoder.is_keep_alive())),
+                Some(event) => events.push(event),
+                None => buf.extend_from_slice(pieces.next().unwrap_or_default()),
+            }
+        }
+    }
+
+    fn body(events: &[Event]) -> Vec<u8> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Data(data) => Some(data.as_slice()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    }
+
+    #[test]
+    fn decode_chunked() {
+        let input = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+        for n in [1, 7, input.len()] {
+            let (events, keep_alive) = decode_all(input, n).unwrap();
+            assert!(matches!(&events[0], Event::Head(resp) if resp.status.as_u16() == 200));
+            assert_eq!(body(&events), b"hello world");
+            assert!(keep_alive);
+        }
+
+        let err = decode_all(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhelloXX\r\n",
+            3,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "invalid chunk terminator");
+
+        let mut decoder = ResponseDecoder::new(Limits::default(), false);
+        let mut buf = input.to_vec();
+        while !matches!(decoder.decode(&mut buf, true).unwrap(), Some(Event::End)) {}
+        assert_eq!(decoder.take_trailers().get("trailer").unwrap(), "x");
+
+        let limits = Limits {
+            max_headers: 1,
+            ..Limits::default()
+        };
+        let mut decoder = ResponseDecoder::new(limits, false);
+        let mut buf =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nA: 1\r\nB: 2\r\n\r\n"
+                .to_vec();
+        let err = loop {
+            match decoder.decode(&mut buf, true) {
+                Ok(Some(Event::End)) => panic!("trailers are not limited"),
+                Ok(_) => {}
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err, HttpError::TooManyHeaders(1)));
+    }
+
+    #[test]
+    fn decode_informational() {
+        let input = b"HTTP/1.1 103 Early Hints\r\nLink: </app.css>; rel=preload\r\n\r\n\
+                      HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        for n in [1, input.len()] {
+            let (events, keep_alive) = decode_all(input, n).unwrap();
+            assert!(
+                matches!(&events[0], Event::Informational(resp) if resp.header.contains("link"))
+            );
+            assert!(matches!(&events[1], Event::Head(resp) if resp.status.as_u16() == 200));
+            assert_eq!(body(&events), b"ok");
+            assert!(keep_alive);
+        }
+    }
+
+    #[test]
+    fn decode_length_and_close() {
+        let input = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+        let (events, keep_alive) = decode_all(input, 4).unwrap();
+        assert_eq!(body(&events), b"hello");
+        assert!(!keep_alive);
+
+        let (events, keep_alive) = decode_all(b"HTTP/1.0 200 OK\r\n\r\nuntil eof", 4).unwrap();
+        assert_eq!(body(&events), b"until eof");
+        assert!(!keep_alive);
+
+        let (events, keep_alive) = decode_all(b"HTTP/1.1 204 No Content\r\n\r\n", 4).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(keep_alive);
+
+        let err = decode_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhel", 4).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "body is 2 bytes shorter than content-length"
+        );
+        assert!(matches!(
+            decode_all(b"", 1),
+            Err(HttpError::ConnectionClosed)
+        ));
+    }
+
+    #[test]
+    fn decode_body_limit() {
+        let gzip = encoding::Compression::Gzip
+            .encode(&vec![0u8; 1024 * 1024])
+            .unwrap();
+        let header = || -> HttpHeader { [("Content-Encoding", "gzip")].into_iter().collect() };
+
+        let mut h = header();
+        let err = decode_body(&mut h, gzip.clone(), Some(1024)).unwrap_err();
+        assert!(matches!(err, HttpError::BodyTooLarge(1024)), "{}", err);
+
+        let mut h = header();
+        let body = decode_body(&mut h, gzip, Some(1024 * 1024)).unwrap();
+        assert_eq!(body.len(), 1024 * 1024);
+        assert!(!h.contains("content-encoding"));
+
+        let mut h = header();
+        let err = decode_body(&mut h, b"not gzip".to_vec(), None).unwrap_err();
+        assert!(matches!(err, HttpError::Parse(_)), "{}", err);
+    }
+
+    #[test]
+    fn framing_bodiless() {
+        let limits = Limits::default();
+        let resp = |status: &str, header: &str| {
+            let head = format!("HTTP/1.1 {}\r\n{}\r\n", status, header);
+            read_response_head(&mut head.as_bytes(), &limits, &mut Vec::new()).unwrap()
+        };
+        let cases = [
+            (
+                "200 OK",
+                "Content-Length: 10\r\n",
+                HttpMethod::Head,
+                Framing::Empty,
+            ),
+            (
+                "200 OK",
+                "Content-Length: 10\r\n",
+                HttpMethod::Get,
+                Framing::Length(10),
+            ),
+            (
+                "204 No Content",
+                "Content-Length: 10\r\n",
+                HttpMethod::Get,
+                Framing::Empty,
+            ),
+            (
+                "304 Not Modified",
+                "Transfer-Encoding: chunked\r\n",
+                HttpMethod::Get,
+                Framing::Empty,
+            ),
+            (
+                "200 Connection Established",
+                "",
+                HttpMethod::Connect,
+                Framing::Empty,
+            ),
+            (
+                "407 Proxy Authentication Required",
+                "Content-Length: 0\r\n",
+                HttpMethod::Connect,
+                Framing::Length(0),
+            ),
+            (
+                "101 Switching Protocols",
+                "",
+                HttpMethod::Get,
+                Framing::Empty,
+            ),
+        ];
+        for (status, header, method, want) in cases {
+            assert_eq!(
+                framing(&resp(status, header), &method, &limits).unwrap(),
+                want,
+                "{}",
+                status
+            );
+        }
+        assert!(is_tunnel(&resp("200 OK", ""), &HttpMethod::Connect));
+        assert!(!is_tunnel(&resp("200 OK", ""), &HttpMethod::Get));
+
+        let mut decoder = ResponseDecoder::new(limits, false);
+        decoder.method(HttpMethod::Head);
+        let mut buf = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nHTTP/1.1".to_vec();
+        assert!(matches!(
+            decoder.decode(&mut buf, false).unwrap(),
+            Some(Event::Head(_))
+        ));
+        assert!(matches!(
+            decoder.decode(&mut buf, false).unwrap(),
+            Some(Event::End)
+        ));
+        assert!(decoder.is_keep_alive());
+        assert_eq!(buf, b"HTTP/1.1");
+    }
+
+    #[test]
+    fn chunk_size() {
+        assert_eq!(parse_chunk_size(b"1a;name=value\r\n").unwrap(), 26);
+        assert!(parse_chunk_size(b"xyz\r\n").is_err());
+    }
+}
@@ -0,0 +1,475 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
+use std::thread;
+
+use crate::balancer::{Balancer, Lease};
+use crate::body::{ChunkedReader, LengthReader};
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::proto::{self, Framing};
+use crate::server::{self, Listener, Payload, ServerRequest};
+use crate::status::StatusCode;
+use crate::transport::Transport;
+use crate::{headers, HttpHeader, HttpMethod, HttpVersion};
+
+// fields which only apply to a single connection, and are not forwarded
+// (RFC 9110 section 7.6.1)
+const HOP_BY_HOP: &[&str] = &[
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+const X_FORWARDED_HOST: &str = "X-Forwarded-Host";
+
+// forwards the requests of the connections of a listener to upstream, e.g. to
+// expose the docker socket on tcp:
+//
+//     let proxy = ReverseProxy::new("unix:///var/run/docker.sock")?;
+//     proxy.serve(TcpListener::bind("127.0.0.1:2375")?)?;
+//
+// bodies are streamed in both directions. every client connection has its own
+// upstream connection, which is opened again when upstream closes it. with a
+// Balancer, each upstream connection goes to the backend picked by it
+// NOTE: upgrades like the hijacked stream of `docker attach` are not forwarded
+pub struct ReverseProxy {
+    upstream: Arc<Balancer>,
+    limits: Limits,
+    host: Option<String>,
+}
+
+impl ReverseProxy {
+    pub fn new(upstream: &str) -> Result<Self, HttpError> {
+        Ok(Self::balanced(Arc::new(Balancer::new(&[upstream])?)))
+    }
+
+    // forward to the backends of balancer, e.g. with its health checks
+    // spawned by Balancer::spawn_health_checks
+    pub fn balanced(balancer: Arc<Balancer>) -> Self {
+        Self {
+            upstream: balancer,
+            limits: Limits::default(),
+            host: None,
+        }
+    }
+
+    // Host sent upstream. the authority of upstream by default, or localhost
+    // for a socket. the Host of the client is sent as X-Forwarded-Host
+    pub fn host(&mut self, p: &str) -> &mut Self {
+        self.host = Some(p.into());
+        self
+    }
+
+    // upper bounds of the heads of requests and responses
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // proxy the connections of listener, each by its own thread, until
+    // accepting fails
+    pub fn serve<L: Listener>(self, listener: L) -> io::Result<()> {
+        let proxy = Arc::new(self);
+        loop {
+            let conn = listener.accept()?;
+            let proxy = proxy.clone();
+            thread::spawn(move || proxy.proxy_connection(conn));
+        }
+    }
+
+    // forward the requests of conn until either side closes it
+    pub fn proxy_connection<T: Read + Write>(&self, conn: T) -> io::Result<()> {
+        let mut client = BufReader::new(conn);
+        let mut upstream = None;
+        let mut buf = Vec::new();
+        loop {
+            let (mut req, length) =
+                match server::read_request_head(&mut client, &self.limits, &mut buf) {
+                    Ok(Some(head)) => head,
+                    Ok(None) | Err(HttpError::Timeout) => return Ok(()),
+                    Err(HttpError::Io(e)) => return Err(e),
+                    Err(e) => return error(client.get_mut(), StatusCode::BAD_REQUEST, &e),
+                };
+            let keep_alive = req.is_keep_alive();
+            let head = req.method == HttpMethod::Head;
+            let version = req.version;
+
+            let mut reused = upstream.is_some();
+            let mut continued = false;
+            let (resp, conn) = loop {
+                let (lease, conn) = match &mut upstream {
+                    Some(upstream) => upstream,
+                    None => {
+                        let lease = self.upstream.pick();
+                        match lease.connect() {
+                            Ok(conn) => upstream.insert((lease, BufReader::new(conn))),
+                            Err(e) => return error(client.get_mut(), StatusCode::BAD_GATEWAY, &e),
+                        }
+                    }
+                };
+                if !continued {
+                    server::continue_if_expected(client.get_mut(), &req, length)?;
+                    continued = true;
+                }
+                match self.exchange(lease, conn, &mut client, &req, length) {
+                    Ok(resp) => {
+                        lease.success();
+                        break (resp, conn);
+                    }
+                    // upstream closed the kept-alive connection while it was
+                    // idle, which is not a failure of the backend
+                    Err(Failure::Closed(_)) if reused => {
+                        upstream = None;
+                        reused = false;
+                    }
+                    Err(Failure::Closed(e) | Failure::Other(e)) => {
+                        lease.failure();
+                        return error(client.get_mut(), StatusCode::BAD_GATEWAY, &e);
+                    }
+                }
+            };
+
+            let framing = match proto::framing(&resp, &req.method, &self.limits) {
+                Ok(framing) => framing,
+                Err(e) => return error(client.get_mut(), StatusCode::BAD_GATEWAY, &e),
+            };
+            let reusable = resp.is_keep_alive() && framing != Framing::Close;
+            let mut header = resp.header;
+            strip_hop_by_hop(&mut header);
+            let mut chunked;
+            let body: &mut dyn Read = match framing {
+                Framing::Empty => &mut io::empty(),
+                Framing::Length(length) => &mut LengthReader::new(&mut *conn, length),
+                Framing::Chunked => {
+                    chunked = ChunkedReader::new(&mut *conn);
+                    chunked.limits(self.limits)
+                }
+                Framing::Close => &mut *conn,
+            };
+            let keep_alive = server::write_message(
+                client.get_mut(),
+                version,
+                head,
+                keep_alive,
+                resp.status,
+                header,
+                Payload::Reader(body),
+            )?;
+            if !reusable {
+                upstream = None;
+            }
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    // send the request with its body to upstream, and read the head of the
+    // response
+    fn exchange<R: Read>(
+        &self,
+        lease: &Lease<'_>,
+        conn: &mut BufReader<Transport>,
+        client: &mut BufReader<R>,
+        req: &ServerRequest,
+        length: Option<u64>,
+    ) -> Result<crate::Response, Failure> {
+        let mut header = req.header.clone();
+        let host = header.get(headers::HOST).cloned();
+        strip_hop_by_hop(&mut header);
+        // answered by continue_if_expected
+        header.remove(headers::EXPECT);
+        let upstream_host = self
+            .host
+            .clone()
+            .or_else(|| lease.url().host_header())
+            .unwrap_or_else(|| "localhost".into());
+        header.add(headers::HOST, &upstream_host);
+        if let Some(host) = host {
+            header.add(X_FORWARDED_HOST, &host);
+        }
+        if length.is_none() {
+            header.add(headers::TRANSFER_ENCODING, "chunked");
+        }
+
+        let w = conn.get_mut();
+        let mut head = format!("{} {} HTTP/1.1\r\n", req.method, req.target);
+        for (k, v) in header.iter() {
+            head += &format!("{}: {}\r\n", k, v);
+        }
+        head += "\r\n";
+        // nothing was read from the client yet
+        w.write_all(head.as_bytes())
+            .map_err(|e| Failure::Closed(e.into()))?;
+        self.send_body(client, w, length).map_err(Failure::Other)?;
+
+        // the request can be sent again only without body
+        let replayable = length == Some(0);
+        let closed = match w.flush().and_then(|_| conn.fill_buf()) {
+            Ok(buf) => buf.is_empty().then_some(HttpError::ConnectionClosed),
+            Err(e) => Some(e.into()),
+        };
+        match closed {
+            Some(e) if replayable => return Err(Failure::Closed(e)),
+            Some(e) => return Err(Failure::Other(e)),
+            None => {}
+        }
+
+        let mut buf = Vec::new();
+        let mut resp = crate::read_response_head(conn, &self.limits, &mut buf);
+        // NOTE: 100 Continue was already sent to the client
+        let mut interim = 0;
+        while resp.as_ref().is_ok_and(proto::is_interim) {
+            interim += 1;
+            proto::check_interim(interim, &self.limits).map_err(Failure::Other)?;
+            resp = crate::read_response_head(conn, &self.limits, &mut buf);
+        }
+        resp.map_err(Failure::Other)
+    }
+
+    fn send_body<R: Read>(
+        &self,
+        client: &mut BufReader<R>,
+        w: &mut Transport,
+        length: Option<u64>,
+    ) -> Result<(), HttpError> {
+        match length {
+            Some(length) => {
+                let copied = io::copy(&mut (&mut *client).take(length), w)?;
+                if copied < length {
+                    return Err(HttpError::Parse("unexpected endof".into()));
+                }
+            }
+            // NOTE: trailers of the client are not forwarded
+            None => {
+                let mut chunks = ChunkedReader::new(&mut *client);
+                chunks.limits(self.limits);
+                let mut chunk = vec![0u8; 8 * 1024];
+                loop {
+                    let n = chunks.read(&mut chunk)?;
+                    if n == 0 {
+                        break;
+                    }
+                    w.write_all(format!("{:x}\r\n", n).as_bytes())?;
+                    w.write_all(&chunk[..n])?;
+                    w.write_all(b"\r\n")?;
+                    w.flush()?;
+                }
+                w.write_all(b"0\r\n\r\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// an error of ReverseProxy::exchange
+enum Failure {
+    // upstream closed the connection before anything of the response, and
+    // the request can be sent again
+    Closed(HttpError),
+    Other(HttpError),
+}
+
+// remove the hop-by-hop fields, and the ones listed in Connection
+fn strip_hop_by_hop(header: &mut HttpHeader) {
+    let listed = header
+        .get_all(headers::CONNECTION)
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    for key in listed
+        .iter()
+        .map(|k| k.as_str())
+        .chain(HOP_BY_HOP.iter().copied())
+    {
+        header.remove(key);
+    }
+}
+
+fn error<W: Write>(w: &mut W, status: StatusCode, e: &HttpError) -> io::Result<()> {
+    let mut header = HttpHeader::new();
+    header.add(headers::CONTENT_TYPE, "text/plain");
+    let body = e.to_string();
+    server::write_message(
+        w,
+        HttpVersion::Http11,
+        false,
+        false,
+        status,
+        header,
+        Payload::Bytes(body.as_bytes()),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    #[cfg(unix)]
+    use std::os::unix::net::UnixListener;
+
+    use super::*;
+    use crate::server::{HttpServer, ServerResponse};
+    use crate::{HttpClient, Request};
+
+    #[test]
+    fn proxy_strip_hop_by_hop() {
+        let mut header = HttpHeader::new();
+        header.append("Connection", "keep-alive, X-Secret");
+        header.append("X-Secret", "1");
+        header.append("Keep-Alive", "timeout=5");
+        header.append("Transfer-Encoding", "chunked");
+        header.append("Content-Type", "text/plain");
+        strip_hop_by_hop(&mut header);
+        let keys = header.iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, ["Content-Type"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn proxy_unix_to_tcp() {
+        // the daemon on a unix socket
+        let path = std::env::temp_dir().join(format!("proxy-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let daemon = HttpServer::new(UnixListener::bind(&path).unwrap());
+        thread::spawn(move || {
+            daemon.serve(|req| {
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                let host = req.header.get(headers::HOST).cloned().unwrap_or_default();
+                let forwarded = req.header.get(X_FORWARDED_HOST).cloned();
+                match req.path() {
+                    "/logs" => resp.body_reader(io::Cursor::new(b"line 1\nline 2\n".to_vec())),
+                    _ => resp.body(format!(
+                        "{} {} host={} forwarded={} secret={} body={}",
+                        req.method,
+                        req.target,
+                        host,
+                        forwarded.unwrap_or_default(),
+                        req.header.contains("X-Secret"),
+                        String::from_utf8_lossy(&req.body)
+                    )),
+                };
+                resp
+            })
+        });
+
+        // exposed on tcp
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = ReverseProxy::new(&format!("unix://{}", path.display())).unwrap();
+        thread::spawn(move || proxy.serve(listener));
+
+        let mut client = HttpClient::new(TcpStream::connect(addr).unwrap());
+        let mut req = Request::new("/containers/create?name=web");
+        let mut header = HttpHeader::new();
+        header.add("Host", "docker.test:2375");
+        req.method(HttpMethod::Post).header(header).body("{}");
+        let resp = client.execute_request(&mut req).unwrap();
+        let want = "POST /containers/create?name=web host=localhost forwarded=docker.test:2375 secret=false body={}";
+        assert_eq!(resp.text().unwrap(), want);
+
+        // a streamed request body, and a chunked response
+        let mut req = Request::new("/build");
+        req.method(HttpMethod::Post)
+            .body_reader(io::Cursor::new(b"context".to_vec()));
+        let mut header = HttpHeader::new();
+        header.add("Connection", "X-Secret");
+        header.add("X-Secret", "1");
+        req.header(header);
+        let resp = client.execute_request(&mut req).unwrap();
+        assert!(resp.text().unwrap().ends_with("secret=false body=context"));
+        let resp = client.execute_request(&mut Request::get("/logs")).unwrap();
+        assert_eq!(resp.header.get("Transfer-Encoding").unwrap(), "chunked");
+        assert_eq!(resp.body, Some(b"line 1\nline 2\n".into()));
+        // all on one connection
+        assert_eq!(client.requests, 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn proxy_bad_gateway() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let proxy = ReverseProxy::new(&format!("tcp://{}", closed)).unwrap();
+        let mut conn = crate::test::MockConn::new("GET /_ping HTTP/1.1\r\n\r\n");
+        proxy.proxy_connection(&mut conn).unwrap();
+        let resp = String::from_utf8(conn.output).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 502 Bad Gateway\r\n"), "{}", resp);
+    }
+
+    #[test]
+    fn proxy_balanced() {
+        let backend = |name: &'static str| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("tcp://{}", listener.local_addr().unwrap());
+            let server = HttpServer::new(listener);
+            thread::spawn(move || {
+                server.serve(move |_| {
+                    let mut resp = ServerResponse::new(StatusCode::OK);
+                    resp.body(name);
+                    resp
+                })
+            });
+            url
+        };
+        let (a, b) = (backend("a"), backend("b"));
+        let proxy = ReverseProxy::balanced(Arc::new(Balancer::new(&[&a, &b]).unwrap()));
+        let mut got = Vec::new();
+        for _ in 0..2 {
+            let mut conn = crate::test::MockConn::new(concat!(
+                "GET /_ping HTTP/1.1\r\n\r\n",
+                "GET /_ping HTTP/1.1\r\nConnection: close\r\n\r\n",
+            ));
+            proxy.proxy_connection(&mut conn).unwrap();
+            got.push(String::from_utf8(conn.output).unwrap());
+        }
+        // the requests of a connection go to the same backend
+        assert!(got[0].ends_with("\r\n\r\na"), "{}", got[0]);
+        assert_eq!(got[0].matches("\r\n\r\na").count(), 2);
+        assert!(got[1].ends_with("\r\n\r\nb"), "{}", got[1]);
+        assert_eq!(got[1].matches("\r\n\r\nb").count(), 2);
+    }
+
+    #[test]
+    fn proxy_upstream_closed() {
+        // closes each connection after one response, without telling
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for (i, conn) in listener.incoming().enumerate() {
+                let mut conn = conn.unwrap();
+                let mut r = BufReader::new(conn.try_clone().unwrap());
+                let mut line = String::new();
+                while r.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let resp = format!("HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n{}", i);
+                conn.write_all(resp.as_bytes()).unwrap();
+            }
+        });
+        let mut balancer = Balancer::new(&[&url]).unwrap();
+        balancer.unhealthy_threshold(1);
+        let balancer = Arc::new(balancer);
+        let proxy = ReverseProxy::balanced(balancer.clone());
+
+        let mut conn = crate::test::MockConn::new(concat!(
+            "GET /_ping HTTP/1.1\r\n\r\n",
+            "GET /_ping HTTP/1.1\r\nConnection: close\r\n\r\n",
+        ));
+        proxy.proxy_connection(&mut conn).unwrap();
+        let resp = String::from_utf8(conn.output).unwrap();
+        // the second request is sent on a new connection
+        assert!(resp.ends_with("\r\n\r\n1"), "{}", resp);
+        assert_eq!(resp.matches("200 OK").count(), 2);
+        assert!(balancer.is_healthy(&url));
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::{Request, Response};
+
+// limits the rate of requests with a token bucket, e.g. at most 10 requests
+// per second and 20 at once:
+//
+//     let mut limiter = RateLimiter::new(10, Duration::from_secs(1));
+//     limiter.burst(20);
+//     client.middleware(limiter);
+//
+// a request over the limit waits for its turn, or fails with
+// HttpError::RateLimited if the limiter rejects
+pub struct RateLimiter {
+    // time to earn a token
+    per_token: Duration,
+    burst: u32,
+    reject: bool,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    // negative while requests are waiting for tokens
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    // requests per interval. the burst is the same as requests
+    pub fn new(requests: u32, interval: Duration) -> Self {
+        let requests = requests.max(1);
+        Self {
+            per_token: interval / requests,
+            burst: requests,
+            reject: false,
+            bucket: Mutex::new(Bucket {
+                tokens: requests as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    // requests sent at once after the limiter was idle
+    pub fn burst(&mut self, p: u32) -> &mut Self {
+        self.burst = p.max(1);
+        let bucket = self.bucket.get_mut().unwrap();
+        bucket.tokens = bucket.tokens.min(self.burst as f64);
+        self
+    }
+
+    // fail requests over the limit instead of delaying them
+    pub fn reject(&mut self, p: bool) -> &mut Self {
+        self.reject = p;
+        self
+    }
+
+    // take a token at now. Ok is how long to wait before sending, Err is how
+    // long until a token is available when the request is rejected
+    fn acquire(&self, now: Instant) -> Result<Duration, Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.last);
+        let earned = elapsed.as_secs_f64() / self.per_token.as_secs_f64().max(f64::EPSILON);
+        bucket.tokens = (bucket.tokens + earned).min(self.burst as f64);
+        bucket.last = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(Duration::ZERO);
+        }
+        let wait = self.per_token.mul_f64(1.0 - bucket.tokens);
+        if self.reject {
+            return Err(wait);
+        }
+        // NOTE: the token is reserved, so waiting requests are sent in order
+        bucket.tokens -= 1.0;
+        Ok(wait)
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let wait = self
+            .acquire(Instant::now())
+            .map_err(HttpError::RateLimited)?;
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        next.run(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_limit_delay() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(1));
+        let start = limiter.bucket.lock().unwrap().last;
+        assert_eq!(limiter.acquire(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.acquire(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.acquire(start), Ok(Duration::from_millis(500)));
+        assert_eq!(limiter.acquire(start), Ok(Duration::from_millis(1000)));
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.acquire(later), Ok(Duration::ZERO));
+        assert_eq!(limiter.acquire(later), Ok(Duration::ZERO));
+        assert_eq!(limiter.acquire(later), Ok(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn rate_limit_reject() {
+        let mut limiter = RateLimiter::new(10, Duration::from_secs(1));
+        limiter.burst(1).reject(true);
+        let start = limiter.bucket.lock().unwrap().last;
+        assert_eq!(limiter.acquire(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.acquire(start), Err(Duration::from_millis(100)));
+        let next = start + Duration::from_millis(100);
+        assert_eq!(limiter.acquire(next), Ok(Duration::ZERO));
+    }
+}
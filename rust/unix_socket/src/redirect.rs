@@ -0,0 +1,146 @@
+use crate::error::HttpError;
+use crate::url::Url;
+use crate::{headers, HttpMethod, Request, Response};
+
+// headers which must not be sent to another origin
+const SENSITIVE_HEADERS: [&str; 3] = [
+    headers::AUTHORIZATION,
+    headers::COOKIE,
+    headers::PROXY_AUTHORIZATION,
+];
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme == b.scheme
+        && a.socket_path == b.socket_path
+        && a.host.as_deref().map(str::to_ascii_lowercase)
+            == b.host.as_deref().map(str::to_ascii_lowercase)
+        && a.port.or(a.default_port()) == b.port.or(b.default_port())
+}
+
+// turn req into the request for the redirect target of resp. returns false
+// when resp is not a redirect to follow
+pub fn follow(req: &mut Request, resp: &Response) -> Result<bool, HttpError> {
+    let status = resp.status.as_u16();
+    if !matches!(status, 301 | 302 | 303 | 307 | 308) {
+        return Ok(false);
+    }
+    let Some(location) = resp.header.get(headers::LOCATION) else {
+        return Ok(false);
+    };
+    let location = Url::parse(location)
+        .map_err(|e| HttpError::Parse(format!("invalid location {}: {}", location, e)))?;
+    let target = req.url.resolve(&location);
+
+    // 303 always switches to GET. 301 and 302 do so for POST as browsers do
+    let to_get = match status {
+        303 => req.method != HttpMethod::Head,
+        301 | 302 => req.method == HttpMethod::Post,
+        _ => false,
+    };
+    // NOTE: a streamed body is already consumed and is only sent again if it
+    // can be rewound
+    if !to_get && !req.try_rewind() {
+        return Ok(false);
+    }
+    if to_get {
+        req.method = HttpMethod::Get;
+        req.body = None;
+        req.body_reader = None;
+        req.body_source = None;
+        req.body_streamed = false;
+        if let Some(header) = req.header.as_mut() {
+            header.remove(headers::CONTENT_LENGTH);
+            header.remove(headers::CONTENT_TYPE);
+            header.remove(headers::TRANSFER_ENCODING);
+        }
+    }
+
+    if !same_origin(&req.url, &target) {
+        if let Some(header) = req.header.as_mut() {
+            for key in SENSITIVE_HEADERS {
+                header.remove(key);
+            }
+        }
+    }
+
+    // the query of the location replaces the params
+    req.params = None;
+    req.url = target;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::status::StatusCode;
+    use crate::{HttpHeader, HttpVersion};
+
+    fn redirect(status: u16, location: &str) -> Response {
+        Response {
+            version: HttpVersion::Http11,
+            status: StatusCode::from_u16(status).unwrap(),
+            reason: String::new(),
+            header: HttpHeader::from_iter([(headers::LOCATION, location)]),
+            trailers: HttpHeader::new(),
+            body: None,
+        }
+    }
+
+    #[test]
+    fn follow_see_other() {
+        let mut req = Request::new("http://docker/containers/create?name=a");
+        req.method(HttpMethod::Post)
+            .header(HttpHeader::from_iter([
+                (headers::CONTENT_TYPE, "application/json"),
+                (headers::AUTHORIZATION, "Bearer token"),
+            ]))
+            .body(b"{}".to_vec());
+
+        assert!(follow(&mut req, &redirect(303, "../containers/a/json")).unwrap());
+        assert_eq!(req.method, HttpMethod::Get);
+        assert_eq!(req.body, None);
+        assert_eq!(req.url.to_string(), "http://docker/containers/a/json");
+        let header = req.header.as_ref().unwrap();
+        assert!(!header.contains(headers::CONTENT_TYPE));
+        assert!(header.contains(headers::AUTHORIZATION));
+    }
+
+    #[test]
+    fn follow_cross_origin() {
+        let mut req = Request::put("http://docker/v1/images");
+        req.header(HttpHeader::from_iter([
+            (headers::AUTHORIZATION, "Bearer token"),
+            (headers::ACCEPT, "*/*"),
+        ]))
+        .body(b"data".to_vec());
+
+        assert!(follow(&mut req, &redirect(307, "https://registry/v2/images")).unwrap());
+        assert_eq!(req.method, HttpMethod::Put);
+        assert_eq!(req.body, Some(b"data".into()));
+        let header = req.header.as_ref().unwrap();
+        assert!(!header.contains(headers::AUTHORIZATION));
+        assert!(header.contains(headers::ACCEPT));
+
+        assert!(!follow(&mut req, &redirect(304, "/")).unwrap());
+        assert!(!follow(&mut req, &redirect(200, "/")).unwrap());
+    }
+
+    #[test]
+    fn follow_streamed_body() {
+        let mut req = Request::put("http://docker/upload");
+        req.body_reader(std::io::Cursor::new(b"data".to_vec()));
+        req.take_body_reader();
+        assert!(!follow(&mut req, &redirect(307, "/v2/upload")).unwrap());
+
+        let mut req = Request::put("http://docker/upload");
+        req.body_factory(|| Ok(std::io::Cursor::new(b"data".to_vec())));
+        req.take_body_reader();
+        assert!(follow(&mut req, &redirect(307, "/v2/upload")).unwrap());
+        let mut body = String::new();
+        req.take_body_reader()
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "data");
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// addresses of a host. ttl is None when the resolver doesn't know it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub addrs: Vec<IpAddr>,
+    pub ttl: Option<Duration>,
+}
+
+// resolves the host of tcp and https urls, see transport::connect_with_resolver
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, host: &str) -> io::Result<Lookup>;
+}
+
+// getaddrinfo of the OS
+// NOTE: it doesn't report TTLs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str) -> io::Result<Lookup> {
+        let addrs = (host, 0).to_socket_addrs()?.map(|a| a.ip()).collect();
+        Ok(Lookup { addrs, ttl: None })
+    }
+}
+
+// caches the lookups of inner until their TTL expires, or default_ttl if the
+// TTL is unknown. failed lookups are not cached
+pub struct CachingResolver {
+    inner: Arc<dyn Resolver>,
+    default_ttl: Duration,
+    cache: Mutex<HashMap<String, (Vec<IpAddr>, Instant)>>,
+}
+
+impl CachingResolver {
+    pub fn new(inner: Arc<dyn Resolver>) -> Self {
+        Self {
+            inner,
+            default_ttl: Duration::from_secs(60),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn default_ttl(&mut self, p: Duration) -> &mut Self {
+        self.default_ttl = p;
+        self
+    }
+
+    fn resolve_at(&self, host: &str, now: Instant) -> io::Result<Lookup> {
+        let key = host.to_ascii_lowercase();
+        if let Some((addrs, expires)) = self.cache.lock().unwrap().get(&key) {
+            if now < *expires {
+                let ttl = Some(*expires - now);
+                return Ok(Lookup {
+                    addrs: addrs.clone(),
+                    ttl,
+                });
+            }
+        }
+        let lookup = self.inner.resolve(host)?;
+        let ttl = lookup.ttl.unwrap_or(self.default_ttl);
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (_, expires)| now < *expires);
+        if !ttl.is_zero() {
+            cache.insert(key, (lookup.addrs.clone(), now + ttl));
+        }
+        Ok(lookup)
+    }
+}
+
+impl Resolver for CachingResolver {
+    fn resolve(&self, host: &str) -> io::Result<Lookup> {
+        self.resolve_at(host, Instant::now())
+    }
+}
+
+// fixed addresses of hosts, e.g. for tests or split-horizon DNS. other hosts
+// are resolved by the fallback, or fail without one
+#[derive(Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Arc<dyn Resolver>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(&mut self, host: &str, addrs: &[IpAddr]) -> &mut Self {
+        self.hosts.insert(host.to_ascii_lowercase(), addrs.to_vec());
+        self
+    }
+
+    pub fn fallback(&mut self, p: Arc<dyn Resolver>) -> &mut Self {
+        self.fallback = Some(p);
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, host: &str) -> io::Result<Lookup> {
+        if let Some(addrs) = self.hosts.get(&host.to_ascii_lowercase()) {
+            return Ok(Lookup {
+                addrs: addrs.clone(),
+                ttl: None,
+            });
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.resolve(host),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown host: {}", host),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct Counting(AtomicUsize, Option<Duration>);
+
+    impl Resolver for Counting {
+        fn resolve(&self, _: &str) -> io::Result<Lookup> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Lookup {
+                addrs: vec![IpAddr::from([10, 0, 0, n as u8])],
+                ttl: self.1,
+            })
+        }
+    }
+
+    #[test]
+    fn resolver_cache() {
+        let inner = Arc::new(Counting(AtomicUsize::new(0), Some(Duration::from_secs(5))));
+        let resolver = CachingResolver::new(inner.clone());
+        let now = Instant::now();
+        let first = resolver.resolve_at("Docker", now).unwrap();
+        let cached = resolver
+            .resolve_at("docker", now + Duration::from_secs(4))
+            .unwrap();
+        assert_eq!(cached.addrs, first.addrs);
+        assert_eq!(cached.ttl, Some(Duration::from_secs(1)));
+        let expired = resolver
+            .resolve_at("docker", now + Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(expired.addrs, [IpAddr::from([10, 0, 0, 1])]);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 2);
+
+        let mut resolver = CachingResolver::new(Arc::new(Counting(AtomicUsize::new(0), None)));
+        resolver.default_ttl(Duration::ZERO);
+        resolver.resolve_at("docker", now).unwrap();
+        let again = resolver.resolve_at("docker", now).unwrap();
+        assert_eq!(again.addrs, [IpAddr::from([10, 0, 0, 1])]);
+    }
+
+    #[test]
+    fn resolver_static() {
+        let mut resolver = StaticResolver::new();
+        resolver.host("registry.local", &[IpAddr::from([192, 168, 0, 10])]);
+        let lookup = resolver.resolve("Registry.Local").unwrap();
+        assert_eq!(lookup.addrs, [IpAddr::from([192, 168, 0, 10])]);
+        assert!(resolver.resolve("example.com").is_err());
+
+        resolver.fallback(Arc::new(SystemResolver));
+        let lookup = resolver.resolve("127.0.0.1").unwrap();
+        assert_eq!(lookup.addrs, [IpAddr::from([127, 0, 0, 1])]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::io;
+use std::time::Duration;
+
+use crate::error::HttpError;
+use crate::status::StatusCode;
+use crate::uuid::random_u64;
+
+// when and how often a failed request is sent again. only idempotent requests
+// and requests marked with Request::retryable are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff_base: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    statuses: Vec<StatusCode>,
+    error_kinds: Vec<io::ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_base: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            error_kinds: vec![
+                io::ErrorKind::ConnectionRefused,
+                io::ErrorKind::ConnectionReset,
+                io::ErrorKind::ConnectionAborted,
+                io::ErrorKind::BrokenPipe,
+                io::ErrorKind::UnexpectedEof,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // no retry at all
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    // including the first attempt
+    pub fn max_attempts(&mut self, p: u32) -> &mut Self {
+        self.max_attempts = p.max(1);
+        self
+    }
+
+    // the n-th retry waits backoff_base * 2^n, up to max_backoff
+    pub fn backoff_base(&mut self, p: Duration) -> &mut Self {
+        self.backoff_base = p;
+        self
+    }
+
+    pub fn max_backoff(&mut self, p: Duration) -> &mut Self {
+        self.max_backoff = p;
+        self
+    }
+
+    // wait a random duration between 0 and the backoff ("full jitter")
+    pub fn jitter(&mut self, p: bool) -> &mut Self {
+        self.jitter = p;
+        self
+    }
+
+    pub fn statuses(&mut self, p: Vec<StatusCode>) -> &mut Self {
+        self.statuses = p;
+        self
+    }
+
+    pub fn error_kinds(&mut self, p: Vec<io::ErrorKind>) -> &mut Self {
+        self.error_kinds = p;
+        self
+    }
+
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    pub fn is_retryable_error(&self, err: &HttpError) -> bool {
+        match err {
+            HttpError::Io(e) | HttpError::Connect(_, e) => self.error_kinds.contains(&e.kind()),
+            // the server closed the connection before responding
+            HttpError::ConnectionClosed => true,
+            HttpError::Timeout => self.error_kinds.contains(&io::ErrorKind::TimedOut),
+            _ => false,
+        }
+    }
+
+    // how long to wait before the retry-th retry (starting from 0)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        if !self.jitter {
+            return backoff;
+        }
+        backoff.mul_f64(random())
+    }
+}
+
+// random number in [0, 1)
+fn random() -> f64 {
+    let n = random_u64();
+    (n >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_backoff() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .backoff_base(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .jitter(false);
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(1));
+        assert_eq!(policy.backoff(100), Duration::from_secs(1));
+
+        policy.jitter(true);
+        for retry in 0..5 {
+            assert!(policy.backoff(retry) <= Duration::from_millis(100 << retry));
+        }
+    }
+
+    #[test]
+    fn retryable() {
+        let policy = RetryPolicy::new();
+        assert!(policy.is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!policy.is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert!(policy.is_retryable_error(&HttpError::Io(reset)));
+        assert!(policy.is_retryable_error(&HttpError::ConnectionClosed));
+        assert!(!policy.is_retryable_error(&HttpError::Timeout));
+        assert!(!policy.is_retryable_error(&HttpError::Parse("invalid".into())));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::server::{ServerRequest, ServerResponse};
+use crate::status::StatusCode;
+use crate::{headers, percent, HttpMethod, HttpParams};
+
+type Handler = Box<dyn Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync>;
+
+// the path captures and the query of a routed request
+#[derive(Debug, Default)]
+pub struct Params {
+    path: Vec<(String, String)>,
+    query: HttpParams,
+}
+
+impl Params {
+    // the decoded segment captured by `{name}`
+    pub fn path(&self, name: &str) -> Option<&str> {
+        self.path
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.get(name)
+    }
+
+    pub fn query_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.query.get_all(name)
+    }
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+struct Route {
+    method: HttpMethod,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    fn captures(&self, path: &[&str]) -> Option<Vec<(String, String)>> {
+        if path.len() != self.segments.len() {
+            return None;
+        }
+        let mut captures = Vec::new();
+        for (segment, s) in self.segments.iter().zip(path) {
+            match segment {
+                Segment::Literal(literal) if literal == s => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(name) => captures.push((name.clone(), percent::decode(s).ok()?)),
+            }
+        }
+        Some(captures)
+    }
+}
+
+// dispatches requests of HttpServer by method and path, e.g. a mock of the
+// docker API:
+//
+//     let mut router = Router::new();
+//     router.get("/containers/{id}/json", |_, params| {
+//         let mut resp = ServerResponse::new(StatusCode::OK);
+//         resp.body(format!(r#"{{"Id":"{}"}}"#, params.path("id").unwrap()));
+//         resp
+//     });
+//     server.serve(move |req| router.handle(req))?;
+//
+// a `{name}` segment of a pattern captures one segment of the path. the first
+// route registered which matches wins. a path without a route is answered with
+// 404 Not Found, a method without one with 405 Method Not Allowed. HEAD falls
+// back to the GET route
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Handler>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route<H>(&mut self, method: HttpMethod, pattern: &str, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        let segments = split(pattern)
+            .into_iter()
+            .map(
+                |s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(name) => Segment::Param(name.to_string()),
+                    None => Segment::Literal(s.to_string()),
+                },
+            )
+            .collect();
+        self.routes.push(Route {
+            method,
+            segments,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<H>(&mut self, pattern: &str, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        self.route(HttpMethod::Get, pattern, handler)
+    }
+
+    pub fn post<H>(&mut self, pattern: &str, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        self.route(HttpMethod::Post, pattern, handler)
+    }
+
+    pub fn put<H>(&mut self, pattern: &str, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        self.route(HttpMethod::Put, pattern, handler)
+    }
+
+    pub fn delete<H>(&mut self, pattern: &str, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        self.route(HttpMethod::Delete, pattern, handler)
+    }
+
+    // answers requests without a route instead of 404 Not Found
+    pub fn not_found<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: Fn(&mut ServerRequest, &Params) -> ServerResponse + Send + Sync + 'static,
+    {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    pub fn handle(&self, req: &mut ServerRequest) -> ServerResponse {
+        let query = match req.params() {
+            Ok(query) => query,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        let path = split(req.path());
+        let matched = self
+            .routes
+            .iter()
+            .filter_map(|route| Some((route, route.captures(&path)?)))
+            .collect::<Vec<_>>();
+        let route = matched
+            .iter()
+            .find(|(route, _)| route.method == req.method)
+            .or_else(|| match req.method {
+                HttpMethod::Head => matched.iter().find(|(r, _)| r.method == HttpMethod::Get),
+                _ => None,
+            });
+        if let Some((route, captures)) = route {
+            let params = Params {
+                path: captures.clone(),
+                query,
+            };
+            return (route.handler)(req, &params);
+        }
+
+        if matched.is_empty() {
+            let params = Params {
+                path: Vec::new(),
+                query,
+            };
+            return match &self.not_found {
+                Some(handler) => handler(req, &params),
+                None => error(StatusCode::NOT_FOUND, "not found"),
+            };
+        }
+        let mut allow = Vec::new();
+        for (route, _) in &matched {
+            let method = route.method.to_string();
+            if !allow.contains(&method) {
+                allow.push(method);
+            }
+        }
+        let mut resp = error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+        resp.header(headers::ALLOW, &allow.join(", "));
+        resp
+    }
+}
+
+fn split(path: &str) -> Vec<&str> {
+    path.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+fn error(status: StatusCode, message: &str) -> ServerResponse {
+    let mut resp = ServerResponse::new(status);
+    resp.header(headers::CONTENT_TYPE, "text/plain")
+        .body(message.to_string());
+    resp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::server::serve_connection;
+    use crate::test::MockConn;
+
+    fn serve(router: &Router, method: &str, target: &str) -> String {
+        let mut conn = MockConn::new(&format!("{} {} HTTP/1.1\r\n\r\n", method, target));
+        serve_connection(&mut conn, &Limits::default(), &|req: &mut ServerRequest| {
+            router.handle(req)
+        })
+        .unwrap();
+        String::from_utf8(conn.output).unwrap()
+    }
+
+    #[test]
+    fn router() {
+        let mut router = Router::new();
+        router
+            .get("/containers/json", |_, params| {
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.body(format!("list all={}", params.query("all").unwrap_or("0")));
+                resp
+            })
+            .get("/containers/{id}/json", |_, params| {
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.body(format!("inspect {}", params.path("id").unwrap()));
+                resp
+            })
+            .delete("/containers/{id}", |_, params| {
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.body(format!("remove {}", params.path("id").unwrap()));
+                resp
+            });
+
+        let tests = [
+            ("GET", "/containers/json?all=1", "200 OK", "list all=1"),
+            (
+                "GET",
+                "/containers/my%20app/json",
+                "200 OK",
+                "inspect my app",
+            ),
+            ("DELETE", "/containers/abc/", "200 OK", "remove abc"),
+            ("GET", "/images/json", "404 Not Found", "not found"),
+            (
+                "GET",
+                "/containers/json?all=%zz",
+                "400 Bad Request",
+                "invalid",
+            ),
+        ];
+        for (method, target, status, body) in tests {
+            let got = serve(&router, method, target);
+            assert!(
+                got.starts_with(&format!("HTTP/1.1 {}\r\n", status)),
+                "{}",
+                got
+            );
+            let (_, got_body) = got.split_once("\r\n\r\n").unwrap();
+            assert!(got_body.starts_with(body), "{}: {}", target, got);
+        }
+
+        let got = serve(&router, "HEAD", "/containers/json");
+        assert_eq!(got, "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+        let got = serve(&router, "POST", "/containers/abc");
+        assert!(
+            got.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{}",
+            got
+        );
+        assert!(got.contains("\r\nAllow: DELETE\r\n"), "{}", got);
+
+        router.not_found(|req, _| {
+            let mut resp = ServerResponse::new(StatusCode::NOT_FOUND);
+            resp.body(format!("no route for {}", req.path()));
+            resp
+        });
+        let got = serve(&router, "GET", "/images/json");
+        assert!(
+            got.ends_with("\r\n\r\nno route for /images/json"),
+            "{}",
+            got
+        );
+    }
+}
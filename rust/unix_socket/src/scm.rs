@@ -0,0 +1,186 @@
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+// file descriptors received by a single recvmsg at most, the rest is closed
+// by the kernel
+const MAX_FDS: usize = 32;
+
+// send data with fds as SCM_RIGHTS ancillary data. the fds are received
+// together with the first byte of data
+pub fn send_with_fds(conn: &UnixStream, data: &[u8], fds: &[BorrowedFd<'_>]) -> io::Result<usize> {
+    if data.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fds must be sent with data",
+        ));
+    }
+    let fds_len = mem::size_of_val(fds) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            let raw = fds.iter().map(|fd| fd.as_raw_fd()).collect::<Vec<RawFd>>();
+            std::ptr::copy_nonoverlapping(
+                raw.as_ptr() as *const u8,
+                libc::CMSG_DATA(cmsg),
+                fds_len as usize,
+            );
+        }
+    }
+    let n = unsafe { libc::sendmsg(conn.as_raw_fd(), &msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+// read into buf like Read::read, appending the fds received with the bytes
+pub fn recv_with_fds(
+    conn: &UnixStream,
+    buf: &mut [u8],
+    fds: &mut Vec<OwnedFd>,
+) -> io::Result<usize> {
+    let fds_len = (MAX_FDS * mem::size_of::<RawFd>()) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let flags = 0;
+    let n = unsafe { libc::recvmsg(conn.as_raw_fd(), &mut msg, flags) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let len = (*cmsg).cmsg_len as usize - (data as usize - cmsg as usize);
+                for i in 0..len / mem::size_of::<RawFd>() {
+                    let fd = std::ptr::read_unaligned((data as *const RawFd).add(i));
+                    fds.push(OwnedFd::from_raw_fd(fd));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok(n as usize)
+}
+
+// unix socket which keeps the fds received with the bytes read, e.g. of a
+// daemon which hands back fds after a hijacked response:
+//
+//     let client = HttpClient::new(FdStream::new(UnixStream::connect(path)?));
+//     let (resp, mut upgraded) = client.execute_request_upgrade(&mut req)?;
+//     let fds = upgraded.get_mut().take_fds();
+pub struct FdStream {
+    conn: UnixStream,
+    fds: Vec<OwnedFd>,
+}
+
+impl FdStream {
+    pub fn new(conn: UnixStream) -> Self {
+        Self {
+            conn,
+            fds: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &UnixStream {
+        &self.conn
+    }
+
+    // the fds received so far, in order
+    pub fn take_fds(&mut self) -> Vec<OwnedFd> {
+        mem::take(&mut self.fds)
+    }
+
+    pub fn send_fds(&mut self, data: &[u8], fds: &[BorrowedFd<'_>]) -> io::Result<usize> {
+        send_with_fds(&self.conn, data, fds)
+    }
+}
+
+impl Read for FdStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        recv_with_fds(&self.conn, buf, &mut self.fds)
+    }
+}
+
+impl Write for FdStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.conn.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+    use std::os::fd::AsFd;
+
+    use super::*;
+    use crate::{HttpClient, Request};
+
+    #[test]
+    fn scm_rights() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let path = std::env::temp_dir().join(format!("scm-{}", std::process::id()));
+        std::fs::write(&path, b"shared").unwrap();
+        let file = File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(send_with_fds(&a, b"x", &[file.as_fd()]).unwrap(), 1);
+        let mut fds = Vec::new();
+        let mut buf = [0u8; 4];
+        assert_eq!(recv_with_fds(&b, &mut buf, &mut fds).unwrap(), 1);
+        assert_eq!(fds.len(), 1);
+        let mut received = File::from(fds.remove(0));
+        received.seek(SeekFrom::Start(0)).unwrap();
+        let mut s = String::new();
+        received.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "shared");
+
+        assert!(send_with_fds(&a, b"", &[file.as_fd()]).is_err());
+    }
+
+    #[test]
+    fn fd_stream_response() {
+        let (server, conn) = UnixStream::pair().unwrap();
+        let file = File::open("/dev/null").unwrap();
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        send_with_fds(&server, resp, &[file.as_fd()]).unwrap();
+
+        let mut client = HttpClient::new(FdStream::new(conn));
+        let resp = client.execute_request(&mut Request::get("/fd")).unwrap();
+        assert_eq!(resp.body, Some(b"ok".into()));
+        let fds = client.get_mut().take_fds();
+        assert_eq!(fds.len(), 1);
+    }
+}
@@ -0,0 +1,893 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::body::ChunkedReader;
+use crate::bytes::Bytes;
+use crate::cancel::Shutdown;
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::status::StatusCode;
+use crate::{headers, percent, HttpHeader, HttpMethod, HttpParams, HttpVersion};
+
+// connects to a listener, so that its blocked accept returns
+pub type Waker = Box<dyn Fn() -> io::Result<()> + Send + Sync>;
+
+// a listening socket of HttpServer
+pub trait Listener {
+    type Conn: Read + Write + Send + 'static;
+
+    fn accept(&self) -> io::Result<Self::Conn>;
+
+    // how long a read of conn may block, see HttpServer::idle_timeout
+    fn set_read_timeout(conn: &Self::Conn, timeout: Option<Duration>) -> io::Result<()>;
+
+    // a handle shutting down conn from another thread while draining
+    fn shutdown_handle(conn: &Self::Conn) -> io::Result<Box<dyn Shutdown>>;
+
+    // see ShutdownSignal
+    fn waker(&self) -> io::Result<Waker>;
+
+    // called when HttpServer::serve returns, e.g. to remove the socket file
+    fn cleanup(&self) {}
+}
+
+impl Listener for TcpListener {
+    type Conn = TcpStream;
+
+    fn accept(&self) -> io::Result<TcpStream> {
+        TcpListener::accept(self).map(|(conn, _)| conn)
+    }
+
+    fn set_read_timeout(conn: &TcpStream, timeout: Option<Duration>) -> io::Result<()> {
+        conn.set_read_timeout(timeout)
+    }
+
+    fn shutdown_handle(conn: &TcpStream) -> io::Result<Box<dyn Shutdown>> {
+        Ok(Box::new(conn.try_clone()?))
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let mut addr = self.local_addr()?;
+        // a listener on all interfaces is reached through loopback
+        match addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+            IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+            _ => {}
+        }
+        Ok(Box::new(move || TcpStream::connect(addr).map(drop)))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn accept(&self) -> io::Result<UnixStream> {
+        UnixListener::accept(self).map(|(conn, _)| conn)
+    }
+
+    fn set_read_timeout(conn: &UnixStream, timeout: Option<Duration>) -> io::Result<()> {
+        conn.set_read_timeout(timeout)
+    }
+
+    fn shutdown_handle(conn: &UnixStream) -> io::Result<Box<dyn Shutdown>> {
+        Ok(Box::new(conn.try_clone()?))
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let addr = self.local_addr()?;
+        Ok(Box::new(move || UnixStream::connect_addr(&addr).map(drop)))
+    }
+
+    // NOTE: the socket file is left behind by the listener otherwise, and a
+    // later bind to the path fails
+    fn cleanup(&self) {
+        let Ok(addr) = self.local_addr() else {
+            return;
+        };
+        if let Some(path) = addr.as_pathname() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// stops HttpServer::serve from another thread, e.g. on SIGTERM. the listener
+// stops accepting, idle connections are closed, and the requests in flight
+// are answered with `Connection: close`. connections still open after the
+// grace period are shut down
+#[derive(Clone, Default)]
+pub struct ShutdownSignal(Arc<Drain>);
+
+#[derive(Default)]
+struct Drain {
+    shutdown: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+    conns: Mutex<Conns>,
+    // notified when a connection is closed
+    closed: Condvar,
+}
+
+#[derive(Default)]
+struct Conns {
+    next_id: u64,
+    // the connections being served and whether a request is in flight
+    conns: HashMap<u64, (Box<dyn Shutdown>, bool)>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shutdown(&self) {
+        self.0.shutdown.store(true, Ordering::SeqCst);
+        if let Some(waker) = &*self.0.waker.lock().unwrap() {
+            let _ = waker();
+        }
+        for (conn, busy) in self.0.conns.lock().unwrap().conns.values() {
+            if !busy {
+                let _ = conn.shutdown();
+            }
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.0.shutdown.load(Ordering::SeqCst)
+    }
+
+    fn register(&self, conn: Box<dyn Shutdown>) -> u64 {
+        let mut conns = self.0.conns.lock().unwrap();
+        let id = conns.next_id;
+        conns.next_id += 1;
+        conns.conns.insert(id, (conn, false));
+        id
+    }
+
+    // false when the connection should be closed instead
+    fn set_busy(&self, id: u64, busy: bool) -> bool {
+        let mut conns = self.0.conns.lock().unwrap();
+        if let Some(conn) = conns.conns.get_mut(&id) {
+            conn.1 = busy;
+        }
+        busy || !self.is_shutdown()
+    }
+
+    fn unregister(&self, id: u64) {
+        self.0.conns.lock().unwrap().conns.remove(&id);
+        self.0.closed.notify_all();
+    }
+
+    // wait for the connections to close until deadline, then shut them down
+    fn drain(&self, deadline: Instant) {
+        let mut conns = self.0.conns.lock().unwrap();
+        while !conns.conns.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            conns = self.0.closed.wait_timeout(conns, deadline - now).unwrap().0;
+        }
+        for (conn, _) in conns.conns.values() {
+            let _ = conn.shutdown();
+        }
+    }
+}
+
+// a request received by HttpServer. the body is read before the handler is
+// called, and is empty without one
+#[derive(Debug)]
+pub struct ServerRequest {
+    pub method: HttpMethod,
+    // as sent in the request line, e.g. `/containers/json?all=1`
+    pub target: String,
+    pub version: HttpVersion,
+    pub header: HttpHeader,
+    pub body: Bytes,
+    // fields after a chunked body
+    pub trailers: HttpHeader,
+}
+
+impl ServerRequest {
+    // the target without the query, still percent-encoded
+    pub fn path(&self) -> &str {
+        self.target.split_once('?').map_or(&self.target, |(p, _)| p)
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, q)| q)
+    }
+
+    // the decoded pairs of the query in order
+    pub fn params(&self) -> Result<HttpParams, HttpError> {
+        let mut params = HttpParams::new();
+        for pair in self.query().unwrap_or_default().split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            let k = percent::decode(&k.replace('+', " ")).map_err(HttpError::Parse)?;
+            let v = percent::decode(&v.replace('+', " ")).map_err(HttpError::Parse)?;
+            params.append(&k, &v);
+        }
+        Ok(params)
+    }
+
+    // HTTP/1.0 closes the connection after the response unless keep-alive is requested
+    pub fn is_keep_alive(&self) -> bool {
+        match self.version {
+            HttpVersion::Http10 => self.header.has_token(headers::CONNECTION, "keep-alive"),
+            _ => !self.header.has_token(headers::CONNECTION, "close"),
+        }
+    }
+}
+
+pub enum ServerBody {
+    Bytes(Bytes),
+    // sent with chunked transfer coding unless Content-Length is set
+    Reader(Box<dyn Read + Send>),
+}
+
+// the response of a handler of HttpServer. Content-Length is added for a
+// buffered body
+pub struct ServerResponse {
+    status: StatusCode,
+    header: HttpHeader,
+    body: ServerBody,
+}
+
+impl ServerResponse {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            header: HttpHeader::new(),
+            body: ServerBody::Bytes(Bytes::new()),
+        }
+    }
+
+    pub fn status(&mut self, p: StatusCode) -> &mut Self {
+        self.status = p;
+        self
+    }
+
+    pub fn header(&mut self, key: &str, value: &str) -> &mut Self {
+        self.header.append(key, value);
+        self
+    }
+
+    pub fn body(&mut self, p: impl Into<Bytes>) -> &mut Self {
+        self.body = ServerBody::Bytes(p.into());
+        self
+    }
+
+    // stream the body as it is read, e.g. the events of a long-running job
+    pub fn body_reader(&mut self, p: impl Read + Send + 'static) -> &mut Self {
+        self.body = ServerBody::Reader(Box::new(p));
+        self
+    }
+}
+
+// HTTP/1.1 server, e.g. a local control-plane daemon on a unix socket:
+//
+//     let server = HttpServer::new(UnixListener::bind("/run/app.sock")?);
+//     server.serve(|req| {
+//         let mut resp = ServerResponse::new(StatusCode::OK);
+//         resp.body(format!("{} {}", req.method, req.path()));
+//         resp
+//     })?;
+//
+// every connection is served by its own thread, and kept alive between
+// requests unless the client asks to close it
+pub struct HttpServer<L> {
+    listener: L,
+    limits: Limits,
+    idle_timeout: Option<Duration>,
+    signal: ShutdownSignal,
+    grace_period: Duration,
+    cleanup: bool,
+}
+
+impl<L: Listener> HttpServer<L> {
+    pub fn new(listener: L) -> Self {
+        Self {
+            listener,
+            limits: Limits::default(),
+            idle_timeout: Some(Duration::from_secs(60)),
+            signal: ShutdownSignal::new(),
+            grace_period: Duration::from_secs(30),
+            cleanup: true,
+        }
+    }
+
+    pub fn get_ref(&self) -> &L {
+        &self.listener
+    }
+
+    // upper bounds of the requests. a request over them is answered with an
+    // error status and the connection is closed
+    pub fn limits(&mut self, p: Limits) -> &mut Self {
+        self.limits = p;
+        self
+    }
+
+    // close a connection when a read blocks longer than this, 60s by default
+    pub fn idle_timeout(&mut self, p: Option<Duration>) -> &mut Self {
+        self.idle_timeout = p;
+        self
+    }
+
+    // stops serve when triggered, see ShutdownSignal
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    // how long the requests in flight may take after shutdown, 30s by default
+    pub fn grace_period(&mut self, p: Duration) -> &mut Self {
+        self.grace_period = p;
+        self
+    }
+
+    // remove the socket file when serve returns, see Listener::cleanup. e.g.
+    // false for a socket of systemd, see systemd::listen_fds
+    pub fn cleanup(&mut self, p: bool) -> &mut Self {
+        self.cleanup = p;
+        self
+    }
+
+    // accept connections until accepting fails or the server is shut down
+    pub fn serve<H>(&self, handler: H) -> io::Result<()>
+    where
+        H: Fn(&mut ServerRequest) -> ServerResponse + Send + Sync + 'static,
+    {
+        *self.signal.0.waker.lock().unwrap() = Some(self.listener.waker()?);
+        let result = self.accept_loop(Arc::new(handler));
+        *self.signal.0.waker.lock().unwrap() = None;
+        if self.cleanup {
+            self.listener.cleanup();
+        }
+        result?;
+        self.signal.drain(Instant::now() + self.grace_period);
+        Ok(())
+    }
+
+    fn accept_loop<H>(&self, handler: Arc<H>) -> io::Result<()>
+    where
+        H: Fn(&mut ServerRequest) -> ServerResponse + Send + Sync + 'static,
+    {
+        while !self.signal.is_shutdown() {
+            let conn = self.listener.accept()?;
+            if self.signal.is_shutdown() {
+                break;
+            }
+            L::set_read_timeout(&conn, self.idle_timeout)?;
+            let id = self.signal.register(L::shutdown_handle(&conn)?);
+            let handler = handler.clone();
+            let limits = self.limits;
+            let signal = self.signal.clone();
+            thread::spawn(move || {
+                let result = serve_conn(conn, &limits, &*handler, Some((&signal, id)));
+                signal.unregister(id);
+                result
+            });
+        }
+        Ok(())
+    }
+}
+
+// answer the requests of conn until either side closes it
+pub fn serve_connection<T, H>(conn: T, limits: &Limits, handler: &H) -> io::Result<()>
+where
+    T: Read + Write,
+    H: Fn(&mut ServerRequest) -> ServerResponse,
+{
+    serve_conn(conn, limits, handler, None)
+}
+
+fn serve_conn<T, H>(
+    conn: T,
+    limits: &Limits,
+    handler: &H,
+    drain: Option<(&ShutdownSignal, u64)>,
+) -> io::Result<()>
+where
+    T: Read + Write,
+    H: Fn(&mut ServerRequest) -> ServerResponse,
+{
+    let mut r = BufReader::new(conn);
+    let mut buf = Vec::new();
+    loop {
+        if let Some((signal, id)) = drain {
+            // idle until the next request starts
+            if !signal.set_busy(id, false) {
+                return Ok(());
+            }
+            match r.fill_buf() {
+                Ok([]) | Err(_) => return Ok(()),
+                Ok(_) => {}
+            }
+            signal.set_busy(id, true);
+        }
+        let mut req = match read_request(&mut r, limits, &mut buf) {
+            Ok(Some(req)) => req,
+            // closed between requests
+            Ok(None) => return Ok(()),
+            Err(HttpError::Io(e)) => return Err(e),
+            Err(HttpError::Timeout) => return Ok(()),
+            Err(e) => {
+                let mut resp = ServerResponse::new(error_status(&e));
+                resp.body(e.to_string());
+                write_response(r.get_mut(), HttpVersion::Http11, false, false, resp)?;
+                return Ok(());
+            }
+        };
+        let head = req.method == HttpMethod::Head;
+        let resp = handler(&mut req);
+        // NOTE: a request finished while draining closes the connection
+        let keep_alive = req.is_keep_alive() && !drain.is_some_and(|(s, _)| s.is_shutdown());
+        if !write_response(r.get_mut(), req.version, head, keep_alive, resp)? {
+            return Ok(());
+        }
+    }
+}
+
+// status of a request which can't be read
+fn error_status(e: &HttpError) -> StatusCode {
+    match e {
+        HttpError::TooManyHeaders(_) | HttpError::HeaderLineTooLong(_) => {
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        }
+        HttpError::BodyTooLarge(_) | HttpError::ChunkTooLarge(_) => StatusCode::CONTENT_TOO_LARGE,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+// the next request of r with its body. None when r is closed before it starts
+fn read_request<T: Read + Write>(
+    r: &mut BufReader<T>,
+    limits: &Limits,
+    buf: &mut Vec<u8>,
+) -> Result<Option<ServerRequest>, HttpError> {
+    let Some((mut req, length)) = read_request_head(r, limits, buf)? else {
+        return Ok(None);
+    };
+    continue_if_expected(r.get_mut(), &req, length)?;
+    let mut body = Vec::new();
+    match length {
+        None => {
+            let mut chunks = ChunkedReader::new(&mut *r);
+            chunks.limits(*limits);
+            let read = match limits.max_body_size {
+                Some(max) => (&mut chunks).take(max + 1).read_to_end(&mut body)?,
+                None => chunks.read_to_end(&mut body)?,
+            };
+            if let Some(max) = limits.max_body_size.filter(|max| read as u64 > *max) {
+                return Err(HttpError::BodyTooLarge(max));
+            }
+            req.trailers = chunks.trailers().take();
+        }
+        Some(length) => {
+            (&mut *r).take(length).read_to_end(&mut body)?;
+            if (body.len() as u64) < length {
+                return Err(HttpError::Parse("unexpected endof".into()));
+            }
+        }
+    }
+    req.body = body.into();
+    Ok(Some(req))
+}
+
+// the request line and header fields of the next request of r, and the
+// Content-Length of the body which follows. None for a chunked body
+pub fn read_request_head<R: BufRead>(
+    r: &mut R,
+    limits: &Limits,
+    buf: &mut Vec<u8>,
+) -> Result<Option<(ServerRequest, Option<u64>)>, HttpError> {
+    // e.g. GET /_ping HTTP/1.1
+    buf.clear();
+    if crate::read_header_line(r, buf, limits.max_header_line)? == 0 {
+        return Ok(None);
+    }
+    let line = std::str::from_utf8(buf)
+        .map_err(|_| HttpError::Parse("cannot convert bytes to string".into()))?;
+    let mut cols = line.trim_end_matches(['\r', '\n']).split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (cols.next(), cols.next(), cols.next(), cols.next())
+    else {
+        return Err(HttpError::Parse(format!("invalid request line: {}", line)));
+    };
+    if method.is_empty() || target.is_empty() {
+        return Err(HttpError::Parse(format!("invalid request line: {}", line)));
+    }
+    let method = HttpMethod::from(method);
+    let target = target.to_string();
+    let version = version.parse::<HttpVersion>().map_err(HttpError::Parse)?;
+    let header = crate::read_header_fields(r, limits, buf)?;
+
+    let chunked = header.contains(headers::TRANSFER_ENCODING);
+    let length = match header.get(headers::CONTENT_LENGTH) {
+        // NOTE: both are rejected as a request smuggling attempt (RFC 9112 section 6.3)
+        Some(_) if chunked => {
+            return Err(HttpError::Parse(
+                "both Transfer-Encoding and Content-Length".into(),
+            ))
+        }
+        Some(v) => v
+            .parse::<u64>()
+            .map_err(|_| HttpError::Parse(format!("invalid content-length: {}", v)))?,
+        None => 0,
+    };
+    if chunked && !header.has_token(headers::TRANSFER_ENCODING, "chunked") {
+        return Err(HttpError::Parse("unsupported transfer-encoding".into()));
+    }
+    if let Some(max) = limits.max_body_size.filter(|max| length > *max) {
+        return Err(HttpError::BodyTooLarge(max));
+    }
+
+    let req = ServerRequest {
+        method,
+        target,
+        version,
+        header,
+        body: Bytes::new(),
+        trailers: HttpHeader::new(),
+    };
+    Ok(Some((req, if chunked { None } else { Some(length) })))
+}
+
+// let the client send the body of a request with `Expect: 100-continue`
+pub fn continue_if_expected<W: Write>(
+    w: &mut W,
+    req: &ServerRequest,
+    length: Option<u64>,
+) -> io::Result<()> {
+    if length != Some(0) && req.header.has_token(headers::EXPECT, "100-continue") {
+        w.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
+// returns whether the connection is kept alive after resp
+fn write_response<W: Write>(
+    w: &mut W,
+    version: HttpVersion,
+    head: bool,
+    keep_alive: bool,
+    resp: ServerResponse,
+) -> io::Result<bool> {
+    let ServerResponse {
+        status,
+        header,
+        body,
+    } = resp;
+    match body {
+        ServerBody::Bytes(body) => write_message(
+            w,
+            version,
+            head,
+            keep_alive,
+            status,
+            header,
+            Payload::Bytes(&body),
+        ),
+        ServerBody::Reader(mut r) => write_message(
+            w,
+            version,
+            head,
+            keep_alive,
+            status,
+            header,
+            Payload::Reader(&mut r),
+        ),
+    }
+}
+
+// the body of write_message
+pub enum Payload<'a> {
+    Bytes(&'a [u8]),
+    // sent with chunked transfer coding unless Content-Length is set
+    Reader(&'a mut dyn Read),
+}
+
+// write a response framed for the client of version. returns whether the
+// connection is kept alive after it
+pub fn write_message<W: Write>(
+    w: &mut W,
+    version: HttpVersion,
+    head: bool,
+    mut keep_alive: bool,
+    status: StatusCode,
+    mut header: HttpHeader,
+    body: Payload<'_>,
+) -> io::Result<bool> {
+    // 1xx, 204 and 304 never have a body
+    let has_body = !status.is_informational()
+        && status != StatusCode::NO_CONTENT
+        && status != StatusCode::NOT_MODIFIED;
+    let mut chunked = false;
+    match &body {
+        _ if !has_body => {}
+        Payload::Bytes(body) => {
+            if !header.contains(headers::CONTENT_LENGTH) {
+                header.add(headers::CONTENT_LENGTH, &body.len().to_string());
+            }
+        }
+        Payload::Reader(_) if header.contains(headers::CONTENT_LENGTH) => {}
+        Payload::Reader(_) if version == HttpVersion::Http10 => {
+            // the body ends when the connection is closed
+            keep_alive = false;
+        }
+        Payload::Reader(_) => {
+            header.add(headers::TRANSFER_ENCODING, "chunked");
+            chunked = true;
+        }
+    }
+    if header.has_token(headers::CONNECTION, "close") {
+        keep_alive = false;
+    }
+    match (keep_alive, version) {
+        (false, _) => header.add(headers::CONNECTION, "close"),
+        (true, HttpVersion::Http10) => header.add(headers::CONNECTION, "keep-alive"),
+        (true, _) => {}
+    }
+
+    let mut w = BufWriter::new(w);
+    let reason = status.canonical_reason().unwrap_or_default();
+    write!(w, "HTTP/1.1 {} {}\r\n", status.as_u16(), reason)?;
+    for (k, v) in header.iter() {
+        write!(w, "{}: {}\r\n", k, v)?;
+    }
+    w.write_all(b"\r\n")?;
+    if has_body && !head {
+        match body {
+            Payload::Bytes(body) => w.write_all(body)?,
+            Payload::Reader(r) if chunked => {
+                let mut chunk = vec![0u8; 8 * 1024];
+                loop {
+                    let n = r.read(&mut chunk)?;
+                    if n == 0 {
+                        break;
+                    }
+                    write!(w, "{:x}\r\n", n)?;
+                    w.write_all(&chunk[..n])?;
+                    w.write_all(b"\r\n")?;
+                    // NOTE: every chunk is sent as soon as it's read
+                    w.flush()?;
+                }
+                w.write_all(b"0\r\n\r\n")?;
+            }
+            Payload::Reader(r) => {
+                io::copy(r, &mut w)?;
+            }
+        }
+    }
+    w.flush()?;
+    Ok(keep_alive)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{HttpClient, Request};
+
+    // in-memory connection with the requests to read, keeping what is written
+    struct Conn(Cursor<Vec<u8>>, Vec<u8>);
+
+    impl Read for Conn {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for Conn {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn serve(requests: &str, limits: &Limits) -> String {
+        let mut conn = Conn(Cursor::new(requests.as_bytes().to_vec()), Vec::new());
+        serve_connection(&mut conn, limits, &|req: &mut ServerRequest| {
+            let mut resp = ServerResponse::new(StatusCode::OK);
+            match req.path() {
+                "/stream" => resp.body_reader(Cursor::new(b"streamed".to_vec())),
+                "/empty" => resp.status(StatusCode::NO_CONTENT),
+                _ => resp.body(req.body.clone()),
+            };
+            resp
+        })
+        .unwrap();
+        String::from_utf8(conn.1).unwrap()
+    }
+
+    #[test]
+    fn server_framing() {
+        let limits = Limits::default();
+        let got = serve(
+            "POST /echo HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi\
+             POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n\
+             GET /stream HTTP/1.1\r\n\r\n\
+             HEAD /echo HTTP/1.1\r\n\r\n\
+             GET /empty HTTP/1.1\r\n\r\n\
+             GET /stream HTTP/1.0\r\n\r\n\
+             GET /never HTTP/1.1\r\n\r\n",
+            &limits,
+        );
+        let want = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi\
+                    HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc\
+                    HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n\
+                    HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n\
+                    HTTP/1.1 204 No Content\r\n\r\n\
+                    HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nstreamed";
+        assert_eq!(got, want);
+
+        let got = serve(
+            "GET / HTTP/1.1\r\nConnection: close\r\n\r\nGET /never HTTP/1.1\r\n\r\n",
+            &limits,
+        );
+        assert_eq!(
+            got,
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        let got = serve(
+            "POST / HTTP/1.0\r\nConnection: keep-alive\r\nExpect: 100-continue\r\nContent-Length: 1\r\n\r\nx",
+            &limits,
+        );
+        assert_eq!(
+            got,
+            "HTTP/1.1 100 Continue\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 1\r\nConnection: keep-alive\r\n\r\nx"
+        );
+    }
+
+    #[test]
+    fn server_bad_request() {
+        let limits = Limits {
+            max_body_size: Some(4),
+            ..Default::default()
+        };
+        let tests = [
+            ("GET /\r\n\r\n", "400 Bad Request"),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                "400 Bad Request",
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+                "413 Content Too Large",
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+                "413 Content Too Large",
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nab",
+                "400 Bad Request",
+            ),
+        ];
+        for (req, status) in tests {
+            let got = serve(req, &limits);
+            assert!(
+                got.starts_with(&format!("HTTP/1.1 {}\r\n", status)),
+                "{}: {}",
+                req,
+                got
+            );
+            assert!(got.contains("Connection: close\r\n"), "{}", got);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn server_unix() {
+        let path = std::env::temp_dir().join(format!("server-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = HttpServer::new(UnixListener::bind(&path).unwrap());
+        let signal = server.shutdown_signal();
+        let serving = thread::spawn(move || {
+            server.serve(|req| {
+                let params = req.params().unwrap();
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.header("Content-Type", "text/plain").body(format!(
+                    "{} {} {}",
+                    req.method,
+                    req.path(),
+                    params.get("name").unwrap_or_default()
+                ));
+                resp
+            })
+        });
+
+        let mut client = HttpClient::new(UnixStream::connect(&path).unwrap());
+        for _ in 0..2 {
+            let mut req = Request::get("/containers/json?name=a+b%21");
+            let resp = client.execute_request(&mut req).unwrap();
+            assert_eq!(resp.body, Some(b"GET /containers/json a b!".into()));
+        }
+        // both requests on one connection
+        assert_eq!(client.requests, 2);
+
+        signal.shutdown();
+        serving.join().unwrap().unwrap();
+        assert!(!path.exists());
+        // the idle connection was closed
+        let mut req = Request::get("/containers/json");
+        assert!(client.execute_request(&mut req).is_err());
+    }
+
+    #[test]
+    fn server_shutdown() {
+        use std::sync::mpsc;
+
+        let (started, on_started) = mpsc::channel();
+        let (release, on_release) = mpsc::channel::<Duration>();
+        let on_release = Mutex::new(on_release);
+        let mut server = HttpServer::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        server.grace_period(Duration::from_millis(200));
+        let addr = server.get_ref().local_addr().unwrap();
+        let signal = server.shutdown_signal();
+        let serving = thread::spawn(move || {
+            server.serve(move |_| {
+                started.send(()).unwrap();
+                thread::sleep(on_release.lock().unwrap().recv().unwrap());
+                let mut resp = ServerResponse::new(StatusCode::OK);
+                resp.body("done");
+                resp
+            })
+        });
+
+        // a request in flight is answered with Connection: close
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        on_started.recv().unwrap();
+        signal.shutdown();
+        release.send(Duration::ZERO).unwrap();
+        let mut resp = String::new();
+        conn.read_to_string(&mut resp).unwrap();
+        assert_eq!(
+            resp,
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndone"
+        );
+        serving.join().unwrap().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn server_grace_period() {
+        let mut server = HttpServer::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        server.grace_period(Duration::from_millis(100));
+        let addr = server.get_ref().local_addr().unwrap();
+        let signal = server.shutdown_signal();
+        let serving = thread::spawn(move || {
+            server.serve(|_| {
+                thread::sleep(Duration::from_secs(1));
+                ServerResponse::new(StatusCode::OK)
+            })
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        signal.shutdown();
+        serving.join().unwrap().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(900));
+        // shut down before the response
+        let mut resp = Vec::new();
+        let _ = conn.read_to_end(&mut resp);
+        assert!(resp.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::HttpError;
+use crate::middleware::{Middleware, Next};
+use crate::sha256::hex;
+use crate::{sha256, HttpHeader, Request, Response};
+
+// signs requests with HMAC-SHA256 over the method, the request-target, a
+// timestamp, selected headers and the SHA-256 of the body:
+//
+//     let mut signer = HmacSigner::new("key-1", secret);
+//     signer.signed_headers(&["content-type", "x-request-id"]);
+//     client.middleware(signer);
+//
+// the signed string is the lines
+//
+//     POST
+//     /containers/create?name=web
+//     1700000000
+//     content-type:application/json
+//     x-request-id:42
+//     <hex sha256 of the body>
+//
+// NOTE: only headers of the request itself can be signed, headers like Host
+// and Content-Length are added by the client later
+pub struct HmacSigner {
+    key_id: String,
+    secret: Vec<u8>,
+    headers: Vec<String>,
+    header_name: String,
+    format: String,
+}
+
+impl HmacSigner {
+    pub fn new(key_id: &str, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key_id: key_id.into(),
+            secret: secret.into(),
+            headers: Vec::new(),
+            header_name: "X-Signature".into(),
+            format: "keyId={key_id},ts={timestamp},headers={headers},sig={signature}".into(),
+        }
+    }
+
+    // names of the headers to sign, in this order. a missing header is signed
+    // with an empty value
+    pub fn signed_headers(&mut self, p: &[&str]) -> &mut Self {
+        self.headers = p.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    // the header to put the signature into, X-Signature by default
+    pub fn header_name(&mut self, p: &str) -> &mut Self {
+        self.header_name = p.into();
+        self
+    }
+
+    // the value of the header, where {key_id}, {timestamp}, {headers} (space
+    // separated names) and {signature} (hex) are replaced, e.g.
+    // `t={timestamp},v1={signature}`
+    pub fn format(&mut self, p: &str) -> &mut Self {
+        self.format = p.into();
+        self
+    }
+
+    // the value of the signature header for req at timestamp (unix seconds)
+    pub fn sign(&self, req: &Request, timestamp: u64) -> Result<String, HttpError> {
+        if req.has_body_reader() {
+            return Err(HttpError::Other(
+                "cannot sign a request with a body reader".into(),
+            ));
+        }
+        let target = req.target()?;
+        let mut lines = vec![req.method.to_string(), target, timestamp.to_string()];
+        for name in &self.headers {
+            let value = req
+                .header
+                .as_ref()
+                .map(|h| h.get_all(name).collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            lines.push(format!("{}:{}", name, value.trim()));
+        }
+        lines.push(hex(&sha256::digest(
+            req.body.as_deref().unwrap_or_default(),
+        )));
+        let signature = hex(&sha256::hmac(&self.secret, lines.join("\n")));
+        Ok(self
+            .format
+            .replace("{key_id}", &self.key_id)
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{headers}", &self.headers.join(" "))
+            .replace("{signature}", &signature))
+    }
+}
+
+impl Middleware for HmacSigner {
+    fn handle(&self, req: &mut Request, mut next: Next<'_>) -> Result<Response, HttpError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let value = self.sign(req, timestamp)?;
+        req.header
+            .get_or_insert_with(HttpHeader::new)
+            .add(&self.header_name, &value);
+        next.run(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockConn;
+    use crate::{HttpClient, HttpMethod};
+
+    #[test]
+    fn signing_sign() {
+        let mut signer = HmacSigner::new("key-1", "s3cret");
+        signer.signed_headers(&["Content-Type", "X-Request-Id"]);
+        let mut req = Request::new("/containers/create?name=web");
+        let header: HttpHeader = [("Content-Type", "application/json")].into_iter().collect();
+        req.method(HttpMethod::Post)
+            .header(header)
+            .body(r#"{"Image":"nginx"}"#);
+
+        let signed = [
+            "POST",
+            "/containers/create?name=web",
+            "1700000000",
+            "content-type:application/json",
+            "x-request-id:",
+            &hex(&sha256::digest(r#"{"Image":"nginx"}"#)),
+        ]
+        .join("\n");
+        let want = format!(
+            "keyId=key-1,ts=1700000000,headers=content-type x-request-id,sig={}",
+            hex(&sha256::hmac("s3cret", signed))
+        );
+        assert_eq!(signer.sign(&req, 1_700_000_000).unwrap(), want);
+
+        req.body_reader(std::io::empty());
+        assert!(signer.sign(&req, 0).is_err());
+    }
+
+    #[test]
+    fn signing_client() {
+        let resp = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let mut client = HttpClient::new(MockConn::new(resp));
+        let mut signer = HmacSigner::new("key-1", "s3cret");
+        signer
+            .header_name("Webhook-Signature")
+            .format("t={timestamp},v1={signature}");
+        client.middleware(signer);
+        client.execute_request(&mut Request::get("/_ping")).unwrap();
+
+        let sent = String::from_utf8(client.conn.get_ref().output.clone()).unwrap();
+        let line = sent
+            .lines()
+            .find_map(|l| l.strip_prefix("Webhook-Signature: t="))
+            .unwrap();
+        let (timestamp, signature) = line.split_once(",v1=").unwrap();
+        let signed = format!("GET\n/_ping\n{}\n{}", timestamp, hex(&sha256::digest("")));
+        assert_eq!(signature, hex(&sha256::hmac("s3cret", signed)));
+    }
+}
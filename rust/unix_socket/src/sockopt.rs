@@ -0,0 +1,178 @@
+use std::io;
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+// tuning of a connection applied by transport::connect_with_options. None
+// keeps the default of the OS. the read and write timeouts, SO_RCVTIMEO and
+// SO_SNDTIMEO, are set by Timeouts
+// NOTE: only nodelay is applied on windows
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketOptions {
+    // TCP_NODELAY, tcp only
+    pub nodelay: Option<bool>,
+    // SO_KEEPALIVE, tcp only
+    pub keepalive: Option<Keepalive>,
+    // SO_SNDBUF and SO_RCVBUF in bytes
+    pub send_buffer: Option<usize>,
+    pub recv_buffer: Option<usize>,
+}
+
+// probes of an idle connection, so that a dead peer is noticed, e.g. behind a
+// NAT which drops idle connections. None keeps the default of the OS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keepalive {
+    // idle time before the first probe, TCP_KEEPIDLE
+    pub idle: Option<Duration>,
+    // time between probes, TCP_KEEPINTVL
+    pub interval: Option<Duration>,
+    // probes before the connection is dropped, TCP_KEEPCNT
+    pub count: Option<u32>,
+}
+
+impl SocketOptions {
+    pub fn apply_tcp(&self, conn: &TcpStream) -> io::Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            conn.set_nodelay(nodelay)?;
+        }
+        #[cfg(unix)]
+        {
+            if let Some(keepalive) = &self.keepalive {
+                keepalive.apply(conn.as_raw_fd())?;
+            }
+            self.apply_buffers(conn.as_raw_fd())?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn apply_unix(&self, conn: &UnixStream) -> io::Result<()> {
+        self.apply_buffers(conn.as_raw_fd())
+    }
+
+    #[cfg(unix)]
+    fn apply_buffers(&self, fd: libc::c_int) -> io::Result<()> {
+        if let Some(size) = self.send_buffer {
+            setsockopt(fd, libc::SOL_SOCKET, libc::SO_SNDBUF, clamp(size))?;
+        }
+        if let Some(size) = self.recv_buffer {
+            setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, clamp(size))?;
+        }
+        Ok(())
+    }
+}
+
+impl Keepalive {
+    #[cfg(unix)]
+    fn apply(&self, fd: libc::c_int) -> io::Result<()> {
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPALIVE;
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        const TCP_KEEPIDLE: libc::c_int = libc::TCP_KEEPIDLE;
+
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+        if let Some(idle) = self.idle {
+            setsockopt(fd, libc::IPPROTO_TCP, TCP_KEEPIDLE, secs(idle))?;
+        }
+        if let Some(interval) = self.interval {
+            setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, secs(interval))?;
+        }
+        if let Some(count) = self.count {
+            setsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                libc::TCP_KEEPCNT,
+                clamp(count as usize),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// the options take whole seconds, at least one
+#[cfg(unix)]
+fn secs(d: Duration) -> libc::c_int {
+    clamp(d.as_secs().max(1) as usize)
+}
+
+#[cfg(unix)]
+fn clamp(n: usize) -> libc::c_int {
+    n.try_into().unwrap_or(libc::c_int::MAX)
+}
+
+#[cfg(unix)]
+fn setsockopt(
+    fd: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[cfg(unix)]
+    fn getsockopt(fd: libc::c_int, level: libc::c_int, name: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                level,
+                name,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        assert_eq!(ret, 0);
+        value
+    }
+
+    #[test]
+    fn sockopt_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let options = SocketOptions {
+            nodelay: Some(true),
+            keepalive: Some(Keepalive {
+                idle: Some(Duration::from_secs(30)),
+                interval: Some(Duration::from_secs(5)),
+                count: Some(3),
+            }),
+            send_buffer: Some(64 * 1024),
+            recv_buffer: None,
+        };
+        options.apply_tcp(&conn).unwrap();
+        assert!(conn.nodelay().unwrap());
+
+        #[cfg(unix)]
+        {
+            let fd = conn.as_raw_fd();
+            assert_eq!(getsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 1);
+            assert_eq!(getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL), 5);
+            assert_eq!(getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT), 3);
+            // linux doubles the size for the bookkeeping
+            assert!(getsockopt(fd, libc::SOL_SOCKET, libc::SO_SNDBUF) >= 64 * 1024);
+        }
+    }
+}
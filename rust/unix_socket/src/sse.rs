@@ -0,0 +1,203 @@
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::error::HttpError;
+
+// media type of Server-Sent Events
+pub const MIME: &str = "text/event-stream";
+
+// one event of text/event-stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    // `message` when the event field is not set
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+    // reconnection time requested by the server
+    pub retry: Option<Duration>,
+}
+
+// iterate over events of text/event-stream. the stream doesn't end until the
+// server closes it
+pub struct EventStream<R> {
+    r: R,
+    // sent as Last-Event-ID when reconnecting
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    first_line: bool,
+    done: bool,
+}
+
+impl<R: BufRead> EventStream<R> {
+    pub fn new(r: R) -> Self {
+        Self {
+            r,
+            last_event_id: None,
+            retry: None,
+            first_line: true,
+            done: false,
+        }
+    }
+
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    // NOTE: lines are terminated by LF or CRLF. a bare CR is not a line break
+    fn read_line(&mut self) -> Result<Option<String>, HttpError> {
+        let mut buf = Vec::new();
+        if self.r.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+        if buf.ends_with(b"\n") {
+            buf.pop();
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
+        }
+        let line = String::from_utf8(buf)
+            .map_err(|e| HttpError::Decode(format!("invalid utf-8 event: {}", e.utf8_error())))?;
+        if std::mem::take(&mut self.first_line) {
+            if let Some(line) = line.strip_prefix('\u{feff}') {
+                return Ok(Some(line.to_string()));
+            }
+        }
+        Ok(Some(line))
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event>, HttpError> {
+        let mut event = String::new();
+        let mut data: Option<String> = None;
+        let mut retry = None;
+        loop {
+            let Some(line) = self.read_line()? else {
+                // NOTE: an incomplete event at the end of the stream is discarded
+                return Ok(None);
+            };
+            if line.is_empty() {
+                let Some(data) = data.take() else {
+                    event.clear();
+                    retry = None;
+                    continue;
+                };
+                return Ok(Some(Event {
+                    event: if event.is_empty() {
+                        "message".into()
+                    } else {
+                        event
+                    },
+                    data,
+                    id: self.last_event_id.clone(),
+                    retry,
+                }));
+            }
+            if line.starts_with(':') {
+                // comment, e.g. keep-alive ping
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line.as_str(), ""),
+            };
+            match field {
+                "event" => event = value.into(),
+                "data" => match &mut data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.into()),
+                },
+                "id" if !value.contains('\0') => self.last_event_id = Some(value.into()),
+                "retry" => {
+                    if let Ok(ms) = value.parse::<u64>() {
+                        retry = Some(Duration::from_millis(ms));
+                        self.retry = retry;
+                    }
+                }
+                // unknown fields are ignored
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EventStream<R> {
+    type Item = Result<Event, HttpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn event_stream() {
+        let input = [
+            "\u{feff}: ping\n",
+            "data: first\n",
+            "\n",
+            "event: container\r\n",
+            "id: 1\r\n",
+            "retry: 3000\r\n",
+            "data: {\"status\":\"start\"}\r\n",
+            "data:second line\r\n",
+            "\r\n",
+            "id\n",
+            "\n",
+            "data\n",
+            "\n",
+            "data: incomplete\n",
+        ]
+        .concat();
+        let mut stream = EventStream::new(Cursor::new(input));
+        let events = stream.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                Event {
+                    event: "message".into(),
+                    data: "first".into(),
+                    id: None,
+                    retry: None,
+                },
+                Event {
+                    event: "container".into(),
+                    data: "{\"status\":\"start\"}\nsecond line".into(),
+                    id: Some("1".into()),
+                    retry: Some(Duration::from_secs(3)),
+                },
+                Event {
+                    event: "message".into(),
+                    data: "".into(),
+                    id: Some("".into()),
+                    retry: None,
+                },
+            ]
+        );
+        assert_eq!(stream.last_event_id(), Some(""));
+        assert_eq!(stream.retry(), Some(Duration::from_secs(3)));
+    }
+}
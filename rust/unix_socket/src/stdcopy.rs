@@ -0,0 +1,160 @@
+use std::io::{self, Read, Write};
+
+use crate::error::HttpError;
+
+// stream of a frame of attach/exec output when the container has no TTY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Stdin,
+    Stdout,
+    Stderr,
+    // error message of the daemon, e.g. when exec fails to start
+    Systemerr,
+}
+
+impl TryFrom<u8> for StreamKind {
+    type Error = HttpError;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0 => Ok(Self::Stdin),
+            1 => Ok(Self::Stdout),
+            2 => Ok(Self::Stderr),
+            3 => Ok(Self::Systemerr),
+            v => Err(HttpError::Parse(format!("unknown stream type: {}", v))),
+        }
+    }
+}
+
+// split multiplexed stdout/stderr into frames. each frame is prefixed by an
+// 8 bytes header: stream type, 3 zero bytes and big endian u32 length
+pub struct StreamDemux<R> {
+    r: R,
+    done: bool,
+}
+
+impl<R: Read> StreamDemux<R> {
+    pub fn new(r: R) -> Self {
+        Self { r, done: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(StreamKind, Vec<u8>)>, HttpError> {
+        let mut head = [0u8; 8];
+        let mut n = 0;
+        while n < head.len() {
+            match self.r.read(&mut head[n..]) {
+                Ok(0) if n == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(m) => n += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let kind = StreamKind::try_from(head[0])?;
+        let length = u32::from_be_bytes([head[4], head[5], head[6], head[7]]) as u64;
+        // NOTE: the length is not trusted for allocation
+        let mut frame = Vec::new();
+        (&mut self.r).take(length).read_to_end(&mut frame)?;
+        if (frame.len() as u64) < length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(Some((kind, frame)))
+    }
+}
+
+impl<R: Read> Iterator for StreamDemux<R> {
+    type Item = Result<(StreamKind, Vec<u8>), HttpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.next_frame().transpose();
+        if !matches!(frame, Some(Ok(_))) {
+            self.done = true;
+        }
+        frame
+    }
+}
+
+// like stdcopy.StdCopy of Docker: write stdout and stderr frames of r to the
+// writers until EOF. a Systemerr frame is returned as an error. returns the
+// number of bytes written
+pub fn std_copy<R: Read, W: Write, E: Write>(
+    stdout: &mut W,
+    stderr: &mut E,
+    r: R,
+) -> Result<u64, HttpError> {
+    let mut written = 0;
+    for frame in StreamDemux::new(r) {
+        let (kind, data) = frame?;
+        match kind {
+            StreamKind::Stdin | StreamKind::Stdout => stdout.write_all(&data)?,
+            StreamKind::Stderr => stderr.write_all(&data)?,
+            StreamKind::Systemerr => {
+                return Err(HttpError::Other(format!(
+                    "error from daemon in stream: {}",
+                    String::from_utf8_lossy(&data)
+                )))
+            }
+        }
+        written += data.len() as u64;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![kind, 0, 0, 0];
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn stream_demux() {
+        let input = [frame(1, b"out\n"), frame(2, b"err\n"), frame(1, b"")].concat();
+        let frames = StreamDemux::new(input.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            frames,
+            [
+                (StreamKind::Stdout, b"out\n".to_vec()),
+                (StreamKind::Stderr, b"err\n".to_vec()),
+                (StreamKind::Stdout, b"".to_vec()),
+            ]
+        );
+
+        let input = [frame(1, b"out\n"), frame(1, b"truncated")].concat();
+        let mut frames = StreamDemux::new(&input[..input.len() - 1]);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+
+        let mut frames = StreamDemux::new(&[9u8, 0, 0, 0, 0, 0, 0, 0][..]);
+        let err = frames.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "unknown stream type: 9");
+    }
+
+    #[test]
+    fn std_copy_frames() {
+        let input = [frame(1, b"a"), frame(2, b"bc"), frame(1, b"d")].concat();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let n = std_copy(&mut stdout, &mut stderr, input.as_slice()).unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(stdout, b"ad");
+        assert_eq!(stderr, b"bc");
+
+        let input = [frame(1, b"a"), frame(3, b"exec failed")].concat();
+        let err = std_copy(&mut stdout, &mut stderr, input.as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "error from daemon in stream: exec failed");
+    }
+}
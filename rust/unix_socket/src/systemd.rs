@@ -0,0 +1,196 @@
+use std::io;
+use std::mem;
+use std::net::TcpListener;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixListener;
+
+// the first fd passed by systemd, sd_listen_fds(3)
+const LISTEN_FDS_START: RawFd = 3;
+
+// a listening socket passed by systemd socket activation, named by
+// FileDescriptorName= of the .socket unit
+#[derive(Debug)]
+pub struct ListenFd {
+    pub name: String,
+    pub fd: OwnedFd,
+}
+
+impl ListenFd {
+    // e.g. a socket unit with ListenStream=/run/app.sock:
+    //
+    //     let fd = systemd::listen_fds()?.into_iter().next().ok_or("not socket activated")?;
+    //     let mut server = HttpServer::new(fd.into_unix_listener()?);
+    //     server.cleanup(false);
+    pub fn into_unix_listener(self) -> io::Result<UnixListener> {
+        check_listener(&self, &[libc::AF_UNIX])?;
+        Ok(UnixListener::from(self.fd))
+    }
+
+    pub fn into_tcp_listener(self) -> io::Result<TcpListener> {
+        check_listener(&self, &[libc::AF_INET, libc::AF_INET6])?;
+        Ok(TcpListener::from(self.fd))
+    }
+}
+
+// take the sockets passed to this process by LISTEN_FDS and LISTEN_FDNAMES.
+// empty when the process is not socket activated
+// NOTE: the variables are removed from the environment, so the fds are taken
+// only once and are not inherited by children. like std::env::remove_var, call
+// it before other threads read the environment
+pub fn listen_fds() -> io::Result<Vec<ListenFd>> {
+    let var = |key| std::env::var(key).ok();
+    let fds = parse(
+        var("LISTEN_PID").as_deref(),
+        var("LISTEN_FDS").as_deref(),
+        var("LISTEN_FDNAMES").as_deref(),
+        std::process::id(),
+    )?;
+    for key in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(key);
+    }
+    let mut listen_fds = Vec::with_capacity(fds.len());
+    for (fd, name) in fds {
+        // SAFETY: systemd passes the fds to this process only, and the
+        // variables were removed so they are not taken twice
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        listen_fds.push(ListenFd { name, fd });
+    }
+    Ok(listen_fds)
+}
+
+// the fds and names of the variables, if they are meant for the process of pid
+fn parse(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    names: Option<&str>,
+    pid: u32,
+) -> io::Result<Vec<(RawFd, String)>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Ok(Vec::new());
+    };
+    let listen_pid = listen_pid
+        .parse::<u32>()
+        .map_err(|_| invalid("invalid LISTEN_PID"))?;
+    // the variables were inherited from the parent
+    if listen_pid != pid {
+        return Ok(Vec::new());
+    }
+    let n = listen_fds
+        .parse::<RawFd>()
+        .ok()
+        .filter(|n| (0..=RawFd::MAX - LISTEN_FDS_START).contains(n))
+        .ok_or_else(|| invalid("invalid LISTEN_FDS"))?;
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    let names = match names {
+        Some(names) => names.split(':').map(String::from).collect(),
+        None => vec!["unknown".to_string(); n as usize],
+    };
+    if names.len() != n as usize {
+        return Err(invalid("LISTEN_FDNAMES doesn't match LISTEN_FDS"));
+    }
+    Ok((LISTEN_FDS_START..).zip(names).collect())
+}
+
+// fd must be a listening stream socket of one of the families
+fn check_listener(fd: &ListenFd, families: &[libc::c_int]) -> io::Result<()> {
+    let raw = fd.fd.as_raw_fd();
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockname(
+            raw,
+            &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stream = getsockopt(raw, libc::SO_TYPE)? == libc::SOCK_STREAM;
+    let listening = getsockopt(raw, libc::SO_ACCEPTCONN)? != 0;
+    if !families.contains(&(addr.ss_family as libc::c_int)) || !stream || !listening {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "fd {} ({}) is not a listener of the expected kind",
+                raw, fd.name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn getsockopt(fd: RawFd, opt: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            opt,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+
+    #[test]
+    fn listen_fds_parse() {
+        let fds = parse(Some("42"), Some("2"), Some("http:admin"), 42).unwrap();
+        assert_eq!(fds, [(3, "http".to_string()), (4, "admin".to_string())]);
+        let fds = parse(Some("42"), Some("1"), None, 42).unwrap();
+        assert_eq!(fds, [(3, "unknown".to_string())]);
+
+        // not for this process, or not activated
+        assert!(parse(Some("41"), Some("1"), None, 42).unwrap().is_empty());
+        assert!(parse(None, None, None, 42).unwrap().is_empty());
+
+        assert!(parse(Some("x"), Some("1"), None, 42).is_err());
+        assert!(parse(Some("42"), Some("-1"), None, 42).is_err());
+        assert!(parse(Some("42"), Some("2"), Some("http"), 42).is_err());
+    }
+
+    #[test]
+    fn listen_fd_adopt() {
+        let path = std::env::temp_dir().join(format!("listenfd-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let fd = ListenFd {
+            name: "http".into(),
+            fd: listener.try_clone().unwrap().into(),
+        };
+        let adopted = fd.into_unix_listener().unwrap();
+        let _conn = UnixStream::connect(&path).unwrap();
+        adopted.accept().unwrap();
+
+        let fd = ListenFd {
+            name: "http".into(),
+            fd: listener.into(),
+        };
+        let err = fd.into_tcp_listener().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let (conn, _) = UnixStream::pair().unwrap();
+        let fd = ListenFd {
+            name: "conn".into(),
+            fd: conn.into(),
+        };
+        assert!(fd.into_unix_listener().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
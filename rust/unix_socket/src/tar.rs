@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::dockerignore::DockerIgnore;
+
+const BLOCK_SIZE: usize = 512;
+
+enum Entry {
+    Bytes(Cursor<Vec<u8>>),
+    // file contents are read when the archive is read, so that the context
+    // is not loaded into memory
+    File { path: PathBuf, size: u64 },
+}
+
+// tar archive (ustar with GNU long names) which is built while it's read
+#[derive(Default)]
+pub struct Archive {
+    entries: VecDeque<Entry>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add the directory tree under dir, e.g. the build context of `docker build`.
+    // entries excluded by ignore are skipped
+    pub fn append_dir_all(
+        &mut self,
+        dir: impl AsRef<Path>,
+        ignore: &DockerIgnore,
+    ) -> io::Result<&mut Self> {
+        self.walk(dir.as_ref(), "", ignore)?;
+        Ok(self)
+    }
+
+    fn walk(&mut self, dir: &Path, prefix: &str, ignore: &DockerIgnore) -> io::Result<()> {
+        let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        // NOTE: sorted so that the archive is reproducible for the build cache
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let path = format!("{}{}", prefix, name);
+            let meta = fs::symlink_metadata(entry.path())?;
+            let excluded = ignore.is_excluded(&path);
+            if meta.is_dir() {
+                if !excluded {
+                    self.append(&format!("{}/", path), &meta, b'5', 0, "");
+                }
+                // an exception may include something under the excluded directory
+                if !excluded || ignore.has_exceptions() {
+                    self.walk(&entry.path(), &format!("{}/", path), ignore)?;
+                }
+            } else if excluded {
+                continue;
+            } else if meta.is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                self.append(&path, &meta, b'2', 0, &target.to_string_lossy());
+            } else if meta.is_file() {
+                self.append(&path, &meta, b'0', meta.len(), "");
+                self.entries.push_back(Entry::File {
+                    path: entry.path(),
+                    size: meta.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, path: &str, meta: &fs::Metadata, typeflag: u8, size: u64, link: &str) {
+        let mut head = Vec::new();
+        if path.len() > 100 {
+            head.extend(long_name(b'L', path));
+        }
+        if link.len() > 100 {
+            head.extend(long_name(b'K', link));
+        }
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        head.extend(header(path, mode(meta), size, mtime, typeflag, link));
+        self.entries.push_back(Entry::Bytes(Cursor::new(head)));
+    }
+
+    pub fn into_reader(mut self) -> ArchiveReader {
+        // end of archive
+        self.entries
+            .push_back(Entry::Bytes(Cursor::new(vec![0u8; BLOCK_SIZE * 2])));
+        ArchiveReader {
+            entries: self.entries,
+            current: None,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+// windows has no permission bits, so directories are executable and files are not
+#[cfg(not(unix))]
+fn mode(meta: &fs::Metadata) -> u32 {
+    if meta.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+fn header(
+    path: &str,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+    link: &str,
+) -> [u8; BLOCK_SIZE] {
+    let mut h = [0u8; BLOCK_SIZE];
+    put_str(&mut h[0..100], path);
+    put_octal(&mut h[100..108], mode as u64);
+    put_octal(&mut h[108..116], 0);
+    put_octal(&mut h[116..124], 0);
+    put_size(&mut h[124..136], size);
+    put_octal(&mut h[136..148], mtime);
+    h[156] = typeflag;
+    put_str(&mut h[157..257], link);
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    // checksum is computed with the field filled with spaces
+    h[148..156].fill(b' ');
+    let sum: u32 = h.iter().map(|&b| b as u32).sum();
+    put_octal(&mut h[148..155], sum as u64);
+    h
+}
+
+// GNU extension: the name is stored in the data of a preceding entry
+fn long_name(typeflag: u8, name: &str) -> Vec<u8> {
+    let mut data = name.as_bytes().to_vec();
+    data.push(0);
+    let mut entry = header("././@LongLink", 0, data.len() as u64, 0, typeflag, "").to_vec();
+    entry.extend_from_slice(&data);
+    entry.resize(entry.len() + padding(data.len() as u64), 0);
+    entry
+}
+
+// names longer than the field are truncated. the full name is in the long name entry
+fn put_str(field: &mut [u8], s: &str) {
+    let n = s.len().min(field.len());
+    field[..n].copy_from_slice(&s.as_bytes()[..n]);
+}
+
+// zero padded octal terminated by NUL
+fn put_octal(field: &mut [u8], v: u64) {
+    let n = field.len() - 1;
+    put_str(&mut field[..n], &format!("{:0width$o}", v, width = n));
+    field[n] = 0;
+}
+
+// sizes over 8GiB don't fit in octal, so they are stored in base-256
+fn put_size(field: &mut [u8], size: u64) {
+    if size < 1 << 33 {
+        return put_octal(field, size);
+    }
+    field.fill(0);
+    field[4..].copy_from_slice(&size.to_be_bytes());
+    field[0] = 0x80;
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+pub struct ArchiveReader {
+    entries: VecDeque<Entry>,
+    current: Option<Box<dyn Read + Send>>,
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(r) = self.current.as_mut() {
+                let n = r.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+                self.current = None;
+            }
+            self.current = match self.entries.pop_front() {
+                Some(Entry::Bytes(r)) => Some(Box::new(r)),
+                Some(Entry::File { path, size }) => Some(Box::new(FileEntry::open(path, size)?)),
+                None => return Ok(0),
+            };
+        }
+    }
+}
+
+// contents of the file padded to the block size. the size must not change
+// after the header is written
+struct FileEntry {
+    file: io::Take<File>,
+    path: PathBuf,
+    remaining: u64,
+    padding: Cursor<Vec<u8>>,
+}
+
+impl FileEntry {
+    fn open(path: PathBuf, size: u64) -> io::Result<Self> {
+        let file = File::open(&path)?.take(size);
+        Ok(Self {
+            file,
+            path,
+            remaining: size,
+            padding: Cursor::new(vec![0u8; padding(size)]),
+        })
+    }
+}
+
+impl Read for FileEntry {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return self.padding.read(buf);
+        }
+        let n = self.file.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} was truncated while archiving", self.path.display()),
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // names and contents of the entries
+    pub(crate) fn entries(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut entries = Vec::new();
+        let mut long_name = None;
+        let mut pos = 0;
+        while pos + BLOCK_SIZE <= data.len() && data[pos] != 0 {
+            let h = &data[pos..pos + BLOCK_SIZE];
+            let sum: u32 = h[..148]
+                .iter()
+                .chain(&[b' '; 8])
+                .chain(&h[156..])
+                .map(|&b| b as u32)
+                .sum();
+            let cstr = |b: &[u8]| {
+                String::from_utf8(b.split(|&c| c == 0).next().unwrap().to_vec()).unwrap()
+            };
+            assert_eq!(u32::from_str_radix(&cstr(&h[148..156]), 8).unwrap(), sum);
+            let size = u64::from_str_radix(&cstr(&h[124..136]), 8).unwrap() as usize;
+            let body = data[pos + BLOCK_SIZE..pos + BLOCK_SIZE + size].to_vec();
+            pos += BLOCK_SIZE + size + padding(size as u64);
+            if h[156] == b'L' {
+                long_name = Some(cstr(&body));
+                continue;
+            }
+            let name = long_name.take().unwrap_or_else(|| cstr(&h[..100]));
+            entries.push((name, body));
+        }
+        assert_eq!(data.len(), pos + BLOCK_SIZE * 2);
+        entries
+    }
+
+    #[test]
+    fn archive_dir() {
+        let dir = std::env::temp_dir().join(format!("unix_socket_tar_{}", std::process::id()));
+        let long = "d".repeat(120);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("Dockerfile"), "FROM alpine\n").unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("target/app"), "binary").unwrap();
+        fs::write(dir.join(&long), "").unwrap();
+
+        let mut archive = Archive::new();
+        archive
+            .append_dir_all(&dir, &DockerIgnore::parse("target"))
+            .unwrap();
+        let mut data = Vec::new();
+        archive.into_reader().read_to_end(&mut data).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            entries(&data),
+            [
+                ("Dockerfile".to_string(), b"FROM alpine\n".to_vec()),
+                (long, b"".to_vec()),
+                ("src/".to_string(), b"".to_vec()),
+                ("src/main.rs".to_string(), b"fn main() {}\n".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn size_field() {
+        let mut field = [0u8; 12];
+        put_size(&mut field, 1 << 33);
+        assert_eq!(field, [0x80, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0]);
+        put_size(&mut field, 8);
+        assert_eq!(&field, b"00000000010\0");
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+
+use crate::status::StatusCode;
+use crate::{headers, vcr, HttpVersion};
+
+// in-memory connection for testing code which uses HttpClient. each flush of a
+// request releases the next canned response, and everything the client wrote
+// is kept:
+//
+//     let mut conn = MockTransport::new();
+//     conn.push(ResponseBuilder::new(StatusCode::OK).body("OK").build());
+//     let mut client = HttpClient::new(conn);
+#[derive(Default)]
+pub struct MockTransport {
+    responses: VecDeque<Result<Vec<u8>, io::ErrorKind>>,
+    current: Cursor<Vec<u8>>,
+    // a response fails reads with this error
+    error: Option<io::ErrorKind>,
+    written: Vec<u8>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // raw bytes of a response, which may be malformed on purpose
+    pub fn push(&mut self, response: impl Into<Vec<u8>>) -> &mut Self {
+        self.responses.push_back(Ok(response.into()));
+        self
+    }
+
+    // the response to the next request fails with kind, e.g. ConnectionReset
+    pub fn push_error(&mut self, kind: io::ErrorKind) -> &mut Self {
+        self.responses.push_back(Err(kind));
+        self
+    }
+
+    // responses which are not released yet
+    pub fn pending(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    // the written bytes split into requests
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        let mut requests = Vec::new();
+        let mut rest = &self.written[..];
+        while let Ok(Some((_, len))) = vcr::parse_request(rest) {
+            requests.push(rest[..len].to_vec());
+            rest = &rest[len..];
+        }
+        requests
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(kind) = self.error {
+            return Err(kind.into());
+        }
+        // NOTE: after the last response, reads return EOF like a closed connection
+        self.current.read(buf)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.responses.pop_front() {
+            Some(Ok(response)) => {
+                // keep what the client has not read yet
+                let pos = self.current.position() as usize;
+                let mut data = self.current.get_ref()[pos..].to_vec();
+                data.extend_from_slice(&response);
+                self.current = Cursor::new(data);
+            }
+            Some(Err(kind)) => self.error = Some(kind),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+// raw bytes of a response for MockTransport. Content-Length is added unless
+// the body is chunked or the response is close-delimited
+pub struct ResponseBuilder {
+    version: HttpVersion,
+    status: StatusCode,
+    reason: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    chunk_size: Option<usize>,
+    close: bool,
+}
+
+impl ResponseBuilder {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            version: HttpVersion::Http11,
+            status,
+            reason: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+            chunk_size: None,
+            close: false,
+        }
+    }
+
+    pub fn version(&mut self, p: HttpVersion) -> &mut Self {
+        self.version = p;
+        self
+    }
+
+    // the canonical reason of the status by default
+    pub fn reason(&mut self, p: &str) -> &mut Self {
+        self.reason = Some(p.into());
+        self
+    }
+
+    pub fn header(&mut self, key: &str, value: &str) -> &mut Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn body(&mut self, p: impl Into<Vec<u8>>) -> &mut Self {
+        self.body = p.into();
+        self
+    }
+
+    // send the body with chunked transfer coding in chunks of size bytes
+    pub fn chunked(&mut self, size: usize) -> &mut Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+
+    // `Connection: close` without Content-Length, so the body ends at EOF
+    pub fn close(&mut self) -> &mut Self {
+        self.close = true;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let reason = self
+            .reason
+            .as_deref()
+            .or(self.status.canonical_reason())
+            .unwrap_or_default();
+        let mut out = format!("{} {} {}\r\n", self.version, self.status.as_u16(), reason);
+        for (k, v) in &self.headers {
+            out += &format!("{}: {}\r\n", k, v);
+        }
+        if self.close {
+            out += &format!("{}: close\r\n", headers::CONNECTION);
+        }
+        match self.chunk_size {
+            Some(_) => out += &format!("{}: chunked\r\n", headers::TRANSFER_ENCODING),
+            None if self.close => {}
+            None => out += &format!("{}: {}\r\n", headers::CONTENT_LENGTH, self.body.len()),
+        }
+        out += "\r\n";
+        let mut out = out.into_bytes();
+        match self.chunk_size {
+            Some(size) => {
+                for chunk in self.body.chunks(size) {
+                    out.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                    out.extend_from_slice(chunk);
+                    out.extend_from_slice(b"\r\n");
+                }
+                out.extend_from_slice(b"0\r\n\r\n");
+            }
+            None => out.extend_from_slice(&self.body),
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::HttpError;
+    use crate::{HttpClient, Request};
+
+    #[test]
+    fn response_builder() {
+        let resp = ResponseBuilder::new(StatusCode::NOT_FOUND)
+            .header("Content-Type", "text/plain")
+            .body("no such container")
+            .build();
+        let want = "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\n\
+                    Content-Length: 17\r\n\r\nno such container";
+        assert_eq!(String::from_utf8(resp).unwrap(), want);
+
+        let resp = ResponseBuilder::new(StatusCode::OK)
+            .body("hello")
+            .chunked(2)
+            .build();
+        let want = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    2\r\nhe\r\n2\r\nll\r\n1\r\no\r\n0\r\n\r\n";
+        assert_eq!(String::from_utf8(resp).unwrap(), want);
+
+        let resp = ResponseBuilder::new(StatusCode::from_u16(299).unwrap())
+            .reason("Odd")
+            .close()
+            .build();
+        let want = "HTTP/1.1 299 Odd\r\nConnection: close\r\n\r\n";
+        assert_eq!(String::from_utf8(resp).unwrap(), want);
+    }
+
+    #[test]
+    fn mock_transport() {
+        let mut conn = MockTransport::new();
+        conn.push(
+            ResponseBuilder::new(StatusCode::OK)
+                .body("OK")
+                .chunked(1)
+                .build(),
+        )
+        .push("HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n")
+        .push_error(io::ErrorKind::ConnectionReset);
+        let mut client = HttpClient::new(conn);
+
+        let resp = client.execute_request(&mut Request::get("/_ping")).unwrap();
+        assert_eq!(resp.body, Some(b"OK".into()));
+        assert!(client.execute_request(&mut Request::get("/a")).is_err());
+        let mut client = HttpClient::new(client.conn.into_inner().into_inner());
+        let err = client.execute_request(&mut Request::get("/b")).unwrap_err();
+        assert!(matches!(err, HttpError::Io(e) if e.kind() == io::ErrorKind::ConnectionReset));
+
+        let conn = client.conn.get_ref();
+        assert_eq!(conn.pending(), 0);
+        let requests = conn.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with(b"GET /a HTTP/1.1\r\n"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// limits reads and writes of the inner stream to rate bytes per second, e.g.
+// so that a background image pull doesn't saturate the network. None is no
+// limit. each read or write is at most a second worth of bytes, and sleeps
+// until its bytes are within the rate
+pub struct Throttle<T> {
+    inner: T,
+    rate: Option<u64>,
+    // when the next byte may be transferred
+    next: Option<Instant>,
+}
+
+impl<T> Throttle<T> {
+    pub fn new(inner: T, rate: Option<u64>) -> Self {
+        Self {
+            inner,
+            rate: rate.map(|r| r.max(1)),
+            next: None,
+        }
+    }
+
+    fn max_len(&self, len: usize) -> usize {
+        match self.rate {
+            Some(rate) => len.min(rate.try_into().unwrap_or(usize::MAX)),
+            None => len,
+        }
+    }
+
+    // sleep for n bytes transferred
+    fn pace(&mut self, n: usize) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+        let now = Instant::now();
+        let next = self.next.filter(|next| *next > now).unwrap_or(now)
+            + Duration::from_secs_f64(n as f64 / rate as f64);
+        self.next = Some(next);
+        thread::sleep(next - now);
+    }
+}
+
+impl<T: Read> Read for Throttle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.max_len(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.pace(n);
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Throttle<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.max_len(buf.len());
+        let n = self.inner.write(&buf[..len])?;
+        self.pace(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn throttle_read() {
+        let mut r = Throttle::new(Cursor::new(vec![0u8; 300]), Some(1000));
+        let start = Instant::now();
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len(), 300);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let mut r = Throttle::new(Cursor::new(vec![0u8; 300]), None);
+        let start = Instant::now();
+        r.read_to_end(&mut buf).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn throttle_write() {
+        let mut w = Throttle::new(Vec::new(), Some(10_000));
+        let start = Instant::now();
+        w.write_all(&[0u8; 1500]).unwrap();
+        assert_eq!(w.inner.len(), 1500);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
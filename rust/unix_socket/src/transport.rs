@@ -0,0 +1,442 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::error::HttpError;
+use crate::happy_eyeballs;
+#[cfg(windows)]
+use crate::npipe;
+use crate::resolver::{Resolver, SystemResolver};
+use crate::sockopt::SocketOptions;
+use crate::socks5::Socks5Stream;
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsConfig};
+use crate::tunnel::{Proxy, ProxyEnv};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring::UringStream;
+use crate::url::Url;
+#[cfg(target_os = "linux")]
+use crate::vsock::{self, VsockStream};
+use crate::ReadWriter;
+
+pub type Transport = Box<dyn ReadWriter + Send>;
+
+// a clone of the socket of a connection, to change its read timeout while the
+// connection is used by a client, see HttpClient::continue_timeout
+pub trait ReadTimeout: Send {
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ReadTimeout for UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+// a connection opened by connect_with_proxy_env or connect_via_proxy
+pub struct Connection {
+    pub conn: Transport,
+    // the proxy which requests are forwarded to, see is_forwarded
+    pub proxy: Option<Proxy>,
+    // of tcp and unix sockets, also under TLS
+    pub socket: Option<Box<dyn ReadTimeout>>,
+}
+
+impl Connection {
+    fn new(conn: Transport) -> Self {
+        Self {
+            conn,
+            proxy: None,
+            socket: None,
+        }
+    }
+
+    // e.g. `Connection::with_socket(conn.try_clone()?, Box::new(conn))`
+    fn with_socket(socket: impl ReadTimeout + 'static, conn: Transport) -> Self {
+        Self {
+            conn,
+            proxy: None,
+            socket: Some(Box::new(socket)),
+        }
+    }
+}
+
+// None means no timeout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+}
+
+// open a connection for the url. supported schemes are
+//   - unix:///var/run/docker.sock
+//   - unix-abstract:name or unix://@name (linux only)
+//   - npipe:////./pipe/docker_engine (windows only)
+//   - vsock://<cid>:<port> (linux only)
+//   - tcp://127.0.0.1:2375 (http:// is treated the same)
+//   - https://127.0.0.1:2376 (requires tls feature, DOCKER_CERT_PATH is used if set)
+pub fn connect(url: &Url) -> Result<Transport, HttpError> {
+    connect_with(url, &Timeouts::default())
+}
+
+// NOTE: the connect timeout is only applied to tcp, other transports are local
+pub fn connect_with(url: &Url, timeouts: &Timeouts) -> Result<Transport, HttpError> {
+    connect_with_options(url, timeouts, &SocketOptions::default())
+}
+
+// like connect_with, tuning the socket of unix, tcp and https urls
+pub fn connect_with_options(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+) -> Result<Transport, HttpError> {
+    connect_with_resolver(url, timeouts, options, &SystemResolver)
+}
+
+// like connect_with_options, resolving the host of tcp and https urls by
+// resolver, e.g. a CachingResolver
+pub fn connect_with_resolver(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+) -> Result<Transport, HttpError> {
+    dial(url, timeouts, options, resolver).map(|c| c.conn)
+}
+
+fn dial(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+) -> Result<Connection, HttpError> {
+    let result = dial_scheme(url, timeouts, options, resolver);
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(_) => tracing::debug!(%url, "connected"),
+        Err(e) => tracing::debug!(%url, error = %e, "connect failed"),
+    }
+    result
+}
+
+fn dial_scheme(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+) -> Result<Connection, HttpError> {
+    match url.scheme.as_deref() {
+        #[cfg(unix)]
+        Some("unix") | Some("unix-abstract") => {
+            let conn = connect_unix(url, timeouts, options)?;
+            Ok(Connection::with_socket(conn.try_clone()?, Box::new(conn)))
+        }
+        #[cfg(windows)]
+        Some("npipe") => {
+            let path = url
+                .socket_path
+                .as_ref()
+                .ok_or_else(|| "missing pipe path".to_string())?;
+            // NOTE: named pipes opened as files have no read/write timeout
+            let conn =
+                npipe::connect(path).map_err(|e| HttpError::Connect(npipe::pipe_name(path), e))?;
+            Ok(Connection::new(Box::new(conn)))
+        }
+        #[cfg(target_os = "linux")]
+        Some("vsock") => {
+            let (cid, port) = vsock::addr(url)?;
+            let conn = VsockStream::connect(cid, port)
+                .map_err(|e| HttpError::Connect(format!("vsock {}:{}", cid, port), e))?;
+            conn.set_read_timeout(timeouts.read)?;
+            conn.set_write_timeout(timeouts.write)?;
+            Ok(Connection::new(Box::new(conn)))
+        }
+        Some("tcp") | Some("http") => {
+            let conn = connect_tcp_with(url, timeouts, resolver)?;
+            options.apply_tcp(&conn)?;
+            Ok(Connection::with_socket(conn.try_clone()?, Box::new(conn)))
+        }
+        #[cfg(feature = "tls")]
+        Some("https") => {
+            let config = TlsConfig::from_env().unwrap_or_default();
+            tls_with_options(url, &config, timeouts, options, resolver)
+        }
+        #[cfg(not(feature = "tls"))]
+        Some("https") => Err("https requires the tls feature".into()),
+        Some(scheme) => Err(format!("unsupported scheme: {}", scheme).into()),
+        None => Err(format!("missing scheme: {}", url).into()),
+    }
+}
+
+// the socket of a unix:// or unix-abstract: url, e.g. to check its peer
+// credentials before sending requests
+#[cfg(unix)]
+pub fn connect_unix(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+) -> Result<UnixStream, HttpError> {
+    let conn = match url.scheme.as_deref() {
+        Some("unix") => {
+            let path = url
+                .socket_path
+                .as_ref()
+                .ok_or_else(|| "missing socket path".to_string())?;
+            UnixStream::connect(path).map_err(|e| HttpError::Connect(path.clone(), e))?
+        }
+        #[cfg(target_os = "linux")]
+        Some("unix-abstract") => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let name = url
+                .socket_path
+                .as_ref()
+                .ok_or_else(|| "missing socket name".to_string())?;
+            let addr = SocketAddr::from_abstract_name(name.as_bytes())
+                .map_err(|e| format!("invalid abstract socket name {}: {}", name, e))?;
+            UnixStream::connect_addr(&addr)
+                .map_err(|e| HttpError::Connect(format!("@{}", name), e))?
+        }
+        _ => return Err(format!("not a unix socket url: {}", url).into()),
+    };
+    conn.set_read_timeout(timeouts.read)?;
+    conn.set_write_timeout(timeouts.write)?;
+    options.apply_unix(&conn)?;
+    Ok(conn)
+}
+
+// like connect, but reads and writes go through io_uring
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub fn connect_uring(url: &Url) -> Result<Transport, HttpError> {
+    let uring_err = |e| HttpError::Connect(format!("io_uring for {}", url), e);
+    match url.scheme.as_deref() {
+        Some("unix") => {
+            let path = url
+                .socket_path
+                .as_ref()
+                .ok_or_else(|| "missing socket path".to_string())?;
+            let conn =
+                UnixStream::connect(path).map_err(|e| HttpError::Connect(path.clone(), e))?;
+            Ok(Box::new(UringStream::new(conn).map_err(uring_err)?))
+        }
+        Some("tcp") | Some("http") => {
+            let conn = connect_tcp(url, &Timeouts::default())?;
+            Ok(Box::new(UringStream::new(conn).map_err(uring_err)?))
+        }
+        Some(scheme) => Err(format!("unsupported scheme for io_uring: {}", scheme).into()),
+        None => Err(format!("missing scheme: {}", url).into()),
+    }
+}
+
+pub fn connect_tcp(url: &Url, timeouts: &Timeouts) -> Result<TcpStream, HttpError> {
+    connect_tcp_with(url, timeouts, &SystemResolver)
+}
+
+pub fn connect_tcp_with(
+    url: &Url,
+    timeouts: &Timeouts,
+    resolver: &dyn Resolver,
+) -> Result<TcpStream, HttpError> {
+    let host = url.host.as_ref().ok_or("missing host")?;
+    let port = url.port.or(url.default_port()).unwrap_or(80);
+    let addr = format!("{}:{}", host, port);
+    let conn = dial_tcp(host, port, timeouts.connect, resolver)
+        .map_err(|e| HttpError::Connect(addr, e))?;
+    conn.set_read_timeout(timeouts.read)?;
+    conn.set_write_timeout(timeouts.write)?;
+    Ok(conn)
+}
+
+// race the resolved addresses, e.g. of both A and AAAA records
+fn dial_tcp(
+    host: &str,
+    port: u16,
+    timeout: Option<Duration>,
+    resolver: &dyn Resolver,
+) -> io::Result<TcpStream> {
+    // e.g. [::1]
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => resolver.resolve(host)?.addrs,
+    };
+    let addrs = addrs.into_iter().map(|ip| SocketAddr::new(ip, port));
+    happy_eyeballs::connect(addrs.collect(), timeout)
+}
+
+// open a TLS connection for tcp:// or https:// url
+#[cfg(feature = "tls")]
+pub fn connect_tls(url: &Url, config: &TlsConfig) -> Result<Transport, HttpError> {
+    connect_tls_with(url, config, &Timeouts::default())
+}
+
+#[cfg(feature = "tls")]
+pub fn connect_tls_with(
+    url: &Url,
+    config: &TlsConfig,
+    timeouts: &Timeouts,
+) -> Result<Transport, HttpError> {
+    let options = SocketOptions::default();
+    tls_with_options(url, config, timeouts, &options, &SystemResolver).map(|c| c.conn)
+}
+
+#[cfg(feature = "tls")]
+fn tls_with_options(
+    url: &Url,
+    config: &TlsConfig,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+) -> Result<Connection, HttpError> {
+    match url.scheme.as_deref() {
+        Some("tcp") | Some("https") => {}
+        _ => return Err(format!("tls is not supported for {}", url).into()),
+    }
+    let mut url = url.clone();
+    url.port = url.port.or(Some(443));
+    let conn = connect_tcp_with(&url, timeouts, resolver)?;
+    options.apply_tcp(&conn)?;
+    let socket = conn.try_clone()?;
+    let host = url.host.as_deref().unwrap_or_default();
+    let conn = tls::connect(conn, host, config)?;
+    Ok(Connection::with_socket(socket, Box::new(conn)))
+}
+
+// open a connection for tcp://, http:// or https:// url through proxy. https
+// is tunneled with CONNECT, the others are connected to the proxy itself and
+// their requests must be forwarded in absolute form, see is_forwarded
+pub fn connect_via_proxy(
+    url: &Url,
+    proxy: &Proxy,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+) -> Result<Connection, HttpError> {
+    match url.scheme.as_deref() {
+        Some("tcp") | Some("http") => {
+            let conn = proxy.dial(timeouts, resolver)?;
+            options.apply_tcp(&conn)?;
+            let mut c = Connection::with_socket(conn.try_clone()?, Box::new(conn));
+            c.proxy = Some(proxy.clone());
+            Ok(c)
+        }
+        #[cfg(feature = "tls")]
+        Some("https") => {
+            let conn = proxy.connect(url, timeouts, resolver)?;
+            options.apply_tcp(&conn)?;
+            let socket = conn.try_clone()?;
+            let host = url.host.as_deref().unwrap_or_default();
+            let config = TlsConfig::from_env().unwrap_or_default();
+            let conn = tls::connect(conn, host, &config)?;
+            Ok(Connection::with_socket(socket, Box::new(conn)))
+        }
+        _ => Err(format!("cannot connect to {} through proxy", url).into()),
+    }
+}
+
+// whether requests for url are sent to a proxy in absolute form, e.g.
+// `GET http://docker:2375/_ping HTTP/1.1`, rather than through a tunnel
+pub fn is_forwarded(url: &Url) -> bool {
+    matches!(url.scheme.as_deref(), Some("tcp") | Some("http"))
+}
+
+// connect_with_resolver, or connect_via_proxy when env has a proxy for url
+pub fn connect_with_proxy_env(
+    url: &Url,
+    timeouts: &Timeouts,
+    options: &SocketOptions,
+    resolver: &dyn Resolver,
+    env: &ProxyEnv,
+) -> Result<Connection, HttpError> {
+    match env.proxy_for(url)? {
+        Some(proxy) => connect_via_proxy(url, &proxy, timeouts, options, resolver),
+        None => dial(url, timeouts, options, resolver),
+    }
+}
+
+// open a connection for tcp://, http:// or https:// url through a SOCKS5 proxy
+pub fn connect_via_socks5(url: &Url, proxy: &Url) -> Result<Transport, HttpError> {
+    match url.scheme.as_deref() {
+        Some("tcp") | Some("http") => Ok(Box::new(Socks5Stream::connect(proxy, url)?)),
+        #[cfg(feature = "tls")]
+        Some("https") => {
+            let conn = Socks5Stream::connect(proxy, url)?;
+            let host = url.host.as_deref().unwrap_or_default();
+            let config = TlsConfig::from_env().unwrap_or_default();
+            Ok(Box::new(tls::connect(conn, host, &config)?))
+        }
+        _ => Err(format!("cannot connect to {} through proxy", url).into()),
+    }
+}
+
+// where requests are sent, e.g. for Request::to_curl
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportInfo {
+    pub unix_socket: Option<String>,
+    // linux abstract namespace, without the leading @
+    pub abstract_unix_socket: Option<String>,
+    // scheme and authority of the request url, e.g. http://localhost
+    pub origin: String,
+}
+
+impl TransportInfo {
+    // e.g. unix:///var/run/docker.sock or tcp://127.0.0.1:2375
+    pub fn from_url(url: &Url) -> Self {
+        let mut info = Self::default();
+        match url.scheme.as_deref() {
+            Some("unix") => info.unix_socket = url.socket_path.clone(),
+            Some("unix-abstract") => info.abstract_unix_socket = url.socket_path.clone(),
+            _ => {}
+        }
+        let scheme = match url.scheme.as_deref() {
+            Some("https") => "https",
+            _ => "http",
+        };
+        let authority = match url.scheme.as_deref() {
+            // cid and port are not meaningful as host
+            Some("vsock") => None,
+            _ => url.authority(),
+        };
+        info.origin = format!(
+            "{}://{}",
+            scheme,
+            authority.as_deref().unwrap_or("localhost")
+        );
+        info
+    }
+}
+
+// base url which relative requests on the connection are resolved against
+pub fn base_url(url: &Url) -> Url {
+    let mut base = url.clone();
+    match base.scheme.as_deref() {
+        Some("tcp") => base.scheme = Some("http".into()),
+        // cid and port are not meaningful as Host header
+        Some("vsock") => {
+            base.host = None;
+            base.port = None;
+        }
+        _ => {}
+    }
+    base
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Chain, Cursor, Read, Write};
+
+// raw bidirectional stream after `101 Switching Protocols`, e.g. stdin and
+// stdout of `docker attach`. bytes read ahead with the response head are
+// returned first
+pub struct Upgraded<T> {
+    r: Chain<Cursor<Vec<u8>>, T>,
+}
+
+impl<T: Read + Write> Upgraded<T> {
+    pub fn new(buffered: Vec<u8>, conn: T) -> Self {
+        Self {
+            r: Cursor::new(buffered).chain(conn),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.r.get_ref().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.r.get_mut().1
+    }
+
+    // the bytes which are not read yet from the buffer and the connection
+    pub fn into_parts(self) -> (Vec<u8>, T) {
+        let (buffered, conn) = self.r.into_inner();
+        let pos = buffered.position() as usize;
+        let mut buffered = buffered.into_inner();
+        buffered.drain(..pos.min(buffered.len()));
+        (buffered, conn)
+    }
+}
+
+impl<T: Read + Write> Read for Upgraded<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.r.read(buf)
+    }
+}
+
+impl<T: Read + Write> Write for Upgraded<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgraded_into_parts() {
+        let conn = Cursor::new(b"conn".to_vec());
+        let mut upgraded = Upgraded::new(b"buffered".to_vec(), conn);
+        let mut buf = [0u8; 3];
+        upgraded.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"buf");
+
+        let (buffered, mut conn) = upgraded.into_parts();
+        assert_eq!(buffered, b"fered");
+        let mut rest = String::new();
+        conn.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "conn");
+    }
+}
@@ -0,0 +1,285 @@
+use std::io::{self, IoSlice, Read, Write};
+use std::os::fd::AsRawFd;
+
+use io_uring::{cqueue, opcode, types, IoUring};
+
+const RING_ENTRIES: u32 = 8;
+const READ_BUF_SIZE: usize = 64 * 1024;
+// queued writes are flushed once they grow larger than this
+const MAX_PENDING: usize = 1024 * 1024;
+
+const WRITE: u64 = 1;
+const READ: u64 = 2;
+const CANCEL: u64 = 3;
+
+// stream whose reads and writes are done by io_uring. writes are queued until
+// flush, which submits them as one writev together with the recv of the
+// response, so a request and its response cost one submission
+// NOTE: read and write timeouts of the socket are not applied
+pub struct UringStream<S: AsRawFd> {
+    ring: IoUring,
+    sock: S,
+    pending: Vec<Vec<u8>>,
+    pending_len: usize,
+    // target of the recv in flight. it must not move until the recv completes
+    read_buf: Box<[u8]>,
+    read_pos: usize,
+    read_len: usize,
+    read_in_flight: bool,
+    // result of the recv completed while waiting for something else
+    read_result: Option<i32>,
+}
+
+impl<S: AsRawFd> UringStream<S> {
+    // fails when io_uring is not available, e.g. blocked by seccomp
+    pub fn new(sock: S) -> io::Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(RING_ENTRIES)?,
+            sock,
+            pending: Vec::new(),
+            pending_len: 0,
+            read_buf: vec![0u8; READ_BUF_SIZE].into_boxed_slice(),
+            read_pos: 0,
+            read_len: 0,
+            read_in_flight: false,
+            read_result: None,
+        })
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sock
+    }
+
+    fn fd(&self) -> types::Fd {
+        types::Fd(self.sock.as_raw_fd())
+    }
+
+    fn push(&mut self, entry: io_uring::squeue::Entry) -> io::Result<()> {
+        // SAFETY: the buffers referenced by the entry outlive the operation,
+        // see read_buf and flush
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.ring.submit()?;
+        }
+        Ok(())
+    }
+
+    fn push_recv(&mut self) -> io::Result<()> {
+        if self.read_in_flight || self.read_result.is_some() {
+            return Ok(());
+        }
+        let buf = self.read_buf.as_mut_ptr();
+        let entry = opcode::Recv::new(self.fd(), buf, self.read_buf.len() as u32)
+            .build()
+            .user_data(READ);
+        self.push(entry)?;
+        self.read_in_flight = true;
+        Ok(())
+    }
+
+    // submit the queued entries and wait for the completion of user_data
+    fn wait(&mut self, user_data: u64) -> io::Result<i32> {
+        loop {
+            if user_data == READ {
+                if let Some(result) = self.read_result.take() {
+                    return Ok(result);
+                }
+            }
+            match self.ring.submit_and_wait(1) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            };
+            let completed = self.ring.completion().collect::<Vec<cqueue::Entry>>();
+            let mut found = None;
+            for cqe in completed {
+                match cqe.user_data() {
+                    READ => {
+                        self.read_in_flight = false;
+                        self.read_result = Some(cqe.result());
+                    }
+                    id if id == user_data => found = Some(cqe.result()),
+                    _ => {}
+                }
+            }
+            if let Some(result) = found {
+                return Ok(result);
+            }
+        }
+    }
+}
+
+fn check(result: i32) -> io::Result<usize> {
+    if result < 0 {
+        return Err(io::Error::from_raw_os_error(-result));
+    }
+    Ok(result as usize)
+}
+
+impl<S: AsRawFd> Read for UringStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read_pos == self.read_len {
+            self.push_recv()?;
+            let n = match check(self.wait(READ)?) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                n => n?,
+            };
+            if n == 0 {
+                return Ok(0);
+            }
+            self.read_pos = 0;
+            self.read_len = n;
+        }
+        let n = buf.len().min(self.read_len - self.read_pos);
+        buf[..n].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + n]);
+        self.read_pos += n;
+        Ok(n)
+    }
+}
+
+impl<S: AsRawFd> Write for UringStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.pending.last_mut() {
+            // coalesce small writes, e.g. chunk framing
+            Some(last) if last.len() + buf.len() <= READ_BUF_SIZE => last.extend_from_slice(buf),
+            _ => self.pending.push(buf.to_vec()),
+        }
+        self.pending_len += buf.len();
+        if self.pending_len > MAX_PENDING {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_len = 0;
+        let mut slices = pending.iter().map(|b| IoSlice::new(b)).collect::<Vec<_>>();
+        let mut slices = &mut slices[..];
+        // the response is read in the same submission as the request
+        let mut recv = self.read_pos == self.read_len;
+        while !slices.is_empty() {
+            let entry = opcode::Writev::new(
+                self.fd(),
+                slices.as_ptr().cast::<libc::iovec>(),
+                slices.len().min(libc::UIO_MAXIOV as usize) as u32,
+            )
+            .build()
+            .user_data(WRITE);
+            self.push(entry)?;
+            if recv {
+                self.push_recv()?;
+                recv = false;
+            }
+            let n = match check(self.wait(WRITE)?) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                n => n?,
+            };
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            IoSlice::advance_slices(&mut slices, n);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsRawFd> Drop for UringStream<S> {
+    fn drop(&mut self) {
+        // the kernel may still write into read_buf, so the recv must finish
+        // before the buffer is freed
+        if self.read_in_flight {
+            let entry = opcode::AsyncCancel::new(READ).build().user_data(CANCEL);
+            if self.push(entry).is_ok() {
+                let _ = self.wait(READ);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::net::UnixStream;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{HttpClient, Request};
+
+    fn uring_stream(sock: UnixStream) -> Option<UringStream<UnixStream>> {
+        match UringStream::new(sock) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                eprintln!("io_uring is not available: {}", e);
+                None
+            }
+        }
+    }
+
+    // serve `count` responses with a body of `size` bytes
+    fn serve(mut conn: UnixStream, count: usize, size: usize) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let body = vec![b'x'; size];
+            let mut buf = [0u8; 4096];
+            for _ in 0..count {
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = conn.read(&mut buf).unwrap();
+                    assert!(n > 0);
+                    request.extend_from_slice(&buf[..n]);
+                }
+                write!(conn, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", size).unwrap();
+                conn.write_all(&body).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn uring_execute_request() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let Some(conn) = uring_stream(client) else {
+            return;
+        };
+        let server = serve(server, 2, 100_000);
+        let mut client = HttpClient::new(conn);
+        for _ in 0..2 {
+            let resp = client
+                .execute_request(&mut Request::get("/containers/a/logs"))
+                .unwrap();
+            assert_eq!(resp.body.unwrap().len(), 100_000);
+        }
+        server.join().unwrap();
+    }
+
+    // cargo test --release --features io-uring uring_bench -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn uring_bench() {
+        const COUNT: usize = 2000;
+        const SIZE: usize = 256 * 1024;
+        fn run<T: crate::ReadWriter>(name: &str, conn: T, server: UnixStream) {
+            let server = serve(server, COUNT, SIZE);
+            let mut client = HttpClient::new(conn);
+            let start = Instant::now();
+            for _ in 0..COUNT {
+                let resp = client
+                    .execute_streaming(&mut Request::get("/logs"))
+                    .unwrap();
+                resp.copy_to(&mut io::sink()).unwrap();
+            }
+            let elapsed = start.elapsed();
+            server.join().unwrap();
+            let mb = (COUNT * SIZE) as f64 / 1024.0 / 1024.0;
+            println!(
+                "{}: {:?}, {:.0} MiB/s",
+                name,
+                elapsed,
+                mb / elapsed.as_secs_f64()
+            );
+        }
+
+        let (client, server) = UnixStream::pair().unwrap();
+        run("std", client, server);
+        let (client, server) = UnixStream::pair().unwrap();
+        if let Some(conn) = uring_stream(client) {
+            run("io_uring", conn, server);
+        }
+    }
+}
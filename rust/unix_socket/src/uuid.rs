@@ -0,0 +1,42 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// NOTE: RandomState is seeded randomly for each instance, which is unique
+// enough for ids, nonces and jitter but not a cryptographic source
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+// random UUID (RFC 9562 version 4), e.g. `1b4e28ba-2fa1-41d2-883f-0016d3cca427`
+pub fn v4() -> String {
+    let mut b = [0u8; 16];
+    b[..8].copy_from_slice(&random_u64().to_be_bytes());
+    b[8..].copy_from_slice(&random_u64().to_be_bytes());
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex = b.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uuid_v4() {
+        let id = v4();
+        let groups = id.split('-').map(|g| g.len()).collect::<Vec<_>>();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert!(id.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]));
+        assert_ne!(v4(), id);
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::HttpError;
+use crate::limits::Limits;
+use crate::proto::{self, Event, ResponseDecoder};
+use crate::sha256::hex;
+use crate::transport::Transport;
+use crate::{headers, sha1, ReadWriter};
+
+// transport which records the traffic of a real connection to a cassette file,
+// and later answers the same requests from the cassette without a connection.
+// requests are matched by method, request-target and the sha1 of the body:
+//
+//     let conn = Vcr::open("tests/cassettes/ping.vcr", || {
+//         transport::connect(&Url::parse(DOCKER_HOST)?)
+//     })?;
+//     let mut client = HttpClient::new(conn);
+//
+// the cassette is a list of the raw responses, each preceded by a line
+// `<method> <target> <body sha1> <response length>`
+// NOTE: responses to HEAD requests can't be recorded, they have no body to frame
+pub struct Vcr {
+    // None when replaying
+    inner: Option<Transport>,
+    path: PathBuf,
+    interactions: Vec<Interaction>,
+    // written by the client and not parsed as a request yet
+    written: Vec<u8>,
+    // recording: requests waiting for their response
+    requests: VecDeque<Key>,
+    // recording: responses read before their request was written
+    responses: VecDeque<Vec<u8>>,
+    // recording: bytes of the response being read
+    raw: Vec<u8>,
+    decode_buf: Vec<u8>,
+    decoder: ResponseDecoder,
+    // replaying: responses to be read by the client
+    replay: VecDeque<u8>,
+    // replaying: a request without a recorded response
+    missing: Option<Key>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    method: String,
+    target: String,
+    body_hash: String,
+}
+
+struct Interaction {
+    key: Key,
+    response: Vec<u8>,
+    // replaying: already answered, so that repeated requests are answered in
+    // the recorded order
+    used: bool,
+}
+
+impl Vcr {
+    // record the traffic of inner to the file at path
+    pub fn record(inner: Transport, path: impl AsRef<Path>) -> Self {
+        Self::new(Some(inner), path.as_ref().into(), Vec::new())
+    }
+
+    // answer requests from the cassette at path
+    pub fn replay(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let interactions = read_cassette(&fs::read(path)?)?;
+        Ok(Self::new(None, path.into(), interactions))
+    }
+
+    // replay when the cassette exists, otherwise connect and record
+    pub fn open(
+        path: impl AsRef<Path>,
+        connect: impl FnOnce() -> Result<Transport, HttpError>,
+    ) -> Result<Self, HttpError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Ok(Self::replay(path)?);
+        }
+        Ok(Self::record(connect()?, path))
+    }
+
+    fn new(inner: Option<Transport>, path: PathBuf, interactions: Vec<Interaction>) -> Self {
+        Self {
+            inner,
+            path,
+            interactions,
+            written: Vec::new(),
+            requests: VecDeque::new(),
+            responses: VecDeque::new(),
+            raw: Vec::new(),
+            decode_buf: Vec::new(),
+            decoder: ResponseDecoder::new(Limits::default(), false),
+            replay: VecDeque::new(),
+            missing: None,
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    // parse the complete requests written so far
+    fn take_requests(&mut self) -> io::Result<()> {
+        while let Some((key, len)) = parse_request(&self.written)? {
+            self.written.drain(..len);
+            if !self.is_replaying() {
+                self.requests.push_back(key);
+                self.pair()?;
+                continue;
+            }
+            let found = self
+                .interactions
+                .iter_mut()
+                .find(|i| !i.used && i.key == key);
+            match found {
+                Some(interaction) => {
+                    interaction.used = true;
+                    self.replay.extend(&interaction.response);
+                }
+                None => self.missing = Some(key),
+            }
+        }
+        Ok(())
+    }
+
+    // feed bytes read from the connection. a complete response is saved
+    fn take_response(&mut self, data: &[u8], eof: bool) -> io::Result<()> {
+        self.raw.extend_from_slice(data);
+        self.decode_buf.extend_from_slice(data);
+        loop {
+            // the request of the response is written before it's read
+            if let Some(key) = self.requests.front() {
+                self.decoder.method(key.method.as_str().into());
+            }
+            match self.decoder.decode(&mut self.decode_buf, eof) {
+                Ok(Some(Event::End)) => {}
+                Ok(Some(_)) => continue,
+                Ok(None) => return Ok(()),
+                // NOTE: the client gets the same error from the same bytes
+                Err(_) => return Ok(()),
+            }
+            let len = self.raw.len() - self.decode_buf.len();
+            self.responses.push_back(self.raw.drain(..len).collect());
+            self.decoder = ResponseDecoder::new(Limits::default(), false);
+            self.pair()?;
+            if self.decode_buf.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    // save the requests which got their response
+    fn pair(&mut self) -> io::Result<()> {
+        let n = self.requests.len().min(self.responses.len());
+        if n == 0 {
+            return Ok(());
+        }
+        let paired = self.requests.drain(..n).zip(self.responses.drain(..n));
+        self.interactions
+            .extend(paired.map(|(key, response)| Interaction {
+                key,
+                response,
+                used: false,
+            }));
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut out = Vec::new();
+        for i in &self.interactions {
+            writeln!(
+                out,
+                "{} {} {} {}",
+                i.key.method,
+                i.key.target,
+                i.key.body_hash,
+                i.response.len()
+            )?;
+            out.extend_from_slice(&i.response);
+            out.push(b'\n');
+        }
+        if let Some(dir) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, out)
+    }
+}
+
+impl Read for Vcr {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(inner) = self.inner.as_mut() else {
+            if self.replay.is_empty() {
+                if let Some(key) = self.missing.take() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "no recorded response for {} {} in {}",
+                            key.method,
+                            key.target,
+                            self.path.display()
+                        ),
+                    ));
+                }
+            }
+            return self.replay.read(buf);
+        };
+        let n = inner.read(buf)?;
+        self.take_response(&buf[..n], n == 0)?;
+        Ok(n)
+    }
+}
+
+impl Write for Vcr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = match self.inner.as_mut() {
+            Some(inner) => inner.write(buf)?,
+            None => buf.len(),
+        };
+        self.written.extend_from_slice(&buf[..n]);
+        self.take_requests()?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// a complete request at the start of buf, and its length
+pub fn parse_request(buf: &[u8]) -> io::Result<Option<(Key, usize)>> {
+    let Some(head_len) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&buf[..head_len]).map_err(|_| invalid("invalid request"))?;
+    let mut lines = head.split("\r\n");
+    let mut cols = lines.next().unwrap_or_default().split(' ');
+    let method = cols.next().unwrap_or_default().to_string();
+    let target = cols.next().ok_or_else(|| invalid("invalid request line"))?;
+    let mut length = 0;
+    let mut chunked = false;
+    for line in lines {
+        let (k, v) = line.split_once(':').unwrap_or((line, ""));
+        if k.trim().eq_ignore_ascii_case(headers::CONTENT_LENGTH) {
+            length = v
+                .trim()
+                .parse()
+                .map_err(|_| invalid("invalid content-length"))?;
+        }
+        if k.trim().eq_ignore_ascii_case(headers::TRANSFER_ENCODING) {
+            chunked = v
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("chunked"));
+        }
+    }
+
+    let mut pos = head_len + 4;
+    let body = if chunked {
+        let mut body = Vec::new();
+        loop {
+            let mut r = &buf[pos..];
+            let mut line = Vec::new();
+            if r.read_until(b'\n', &mut line)? == 0 || !line.ends_with(b"\n") {
+                return Ok(None);
+            }
+            pos += line.len();
+            let size = proto::parse_chunk_size(&line)? as usize;
+            if size == 0 {
+                // trailers until the empty line
+                loop {
+                    let mut r = &buf[pos..];
+                    line.clear();
+                    if r.read_until(b'\n', &mut line)? == 0 || !line.ends_with(b"\n") {
+                        return Ok(None);
+                    }
+                    pos += line.len();
+                    if line == b"\r\n" || line == b"\n" {
+                        break;
+                    }
+                }
+                break;
+            }
+            if buf.len() < pos + size + 2 {
+                return Ok(None);
+            }
+            body.extend_from_slice(&buf[pos..pos + size]);
+            pos += size + 2;
+        }
+        body
+    } else {
+        if buf.len() < pos + length {
+            return Ok(None);
+        }
+        pos += length;
+        buf[pos - length..pos].to_vec()
+    };
+    let key = Key {
+        method,
+        target: target.to_string(),
+        body_hash: hex(&sha1::digest(&body)),
+    };
+    Ok(Some((key, pos)))
+}
+
+fn read_cassette(mut data: &[u8]) -> io::Result<Vec<Interaction>> {
+    let mut interactions = Vec::new();
+    while !data.is_empty() {
+        let mut line = String::new();
+        data.read_line(&mut line)?;
+        let cols = line.trim_end().split(' ').collect::<Vec<_>>();
+        let [method, target, body_hash, len] = cols[..] else {
+            return Err(invalid(&format!(
+                "invalid cassette line: {}",
+                line.trim_end()
+            )));
+        };
+        let len: usize = len
+            .parse()
+            .map_err(|_| invalid("invalid response length"))?;
+        if data.len() < len + 1 {
+            return Err(invalid("truncated cassette"));
+        }
+        interactions.push(Interaction {
+            key: Key {
+                method: method.into(),
+                target: target.into(),
+                body_hash: body_hash.into(),
+            },
+            response: data[..len].to_vec(),
+            used: false,
+        });
+        data = &data[len + 1..];
+    }
+    Ok(interactions)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test::MockConn;
+    use crate::{HttpClient, HttpMethod, Request};
+
+    fn cassette(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vcr-{}-{}.vcr", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn vcr_parse_request() {
+        let req =
+            b"POST /build HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\nGET";
+        let (key, len) = parse_request(req).unwrap().unwrap();
+        assert_eq!(key.method, "POST");
+        assert_eq!(key.target, "/build");
+        assert_eq!(key.body_hash, hex(&sha1::digest(b"abc")));
+        assert_eq!(&req[len..], b"GET");
+        assert!(parse_request(&req[..len - 1]).unwrap().is_none());
+
+        let req = b"PUT /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nh";
+        assert!(parse_request(req).unwrap().is_none());
+    }
+
+    #[test]
+    fn vcr_record_and_replay() {
+        let path = cassette("record");
+        let responses = [
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK",
+            "HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nagain",
+        ];
+        let send = |client: &mut HttpClient<Vcr>| {
+            let mut bodies = Vec::new();
+            let mut req = Request::new("/containers/create");
+            req.method(HttpMethod::Post).body("{}");
+            for req in [
+                &mut Request::get("/_ping"),
+                &mut req,
+                &mut Request::get("/_ping"),
+            ] {
+                let resp = client.execute_request(req).unwrap();
+                bodies.push(String::from_utf8(resp.body.unwrap().into_vec()).unwrap());
+            }
+            bodies
+        };
+
+        let conn = MockConn::new(&responses.concat());
+        let mut client = HttpClient::new(Vcr::record(Box::new(conn), &path));
+        assert_eq!(send(&mut client), ["OK", "{\"a\":1}", "again"]);
+        drop(client);
+
+        let vcr = Vcr::open(&path, || panic!("must not connect")).unwrap();
+        assert!(vcr.is_replaying());
+        let mut client = HttpClient::new(vcr);
+        assert_eq!(send(&mut client), ["OK", "{\"a\":1}", "again"]);
+
+        // the body doesn't match
+        let mut req = Request::new("/containers/create");
+        req.method(HttpMethod::Post).body("{\"Image\":\"alpine\"}");
+        let mut client = HttpClient::new(Vcr::replay(&path).unwrap());
+        let err = client.execute_request(&mut req).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("no recorded response for POST /containers/create"));
+        fs::remove_file(&path).unwrap();
+    }
+}
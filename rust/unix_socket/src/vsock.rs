@@ -0,0 +1,140 @@
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+use crate::url::Url;
+
+// connection over AF_VSOCK, used to talk between a VM guest and its host
+pub struct VsockStream {
+    fd: OwnedFd,
+}
+
+impl VsockStream {
+    pub fn connect(cid: u32, port: u32) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+        addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        addr.svm_cid = cid;
+        addr.svm_port = port;
+        let ret = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_timeout(libc::SO_RCVTIMEO, timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_timeout(libc::SO_SNDTIMEO, timeout)
+    }
+
+    // zero timeval means no timeout
+    fn set_timeout(&self, opt: libc::c_int, timeout: Option<Duration>) -> io::Result<()> {
+        let tv = match timeout {
+            Some(d) if d.is_zero() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot set a 0 duration timeout",
+                ));
+            }
+            Some(d) => libc::timeval {
+                tv_sec: d.as_secs() as libc::time_t,
+                tv_usec: d.subsec_micros() as libc::suseconds_t,
+            },
+            None => libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                opt,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+// `vsock://<cid>:<port>`. cid can also be `host` or `local`
+pub fn addr(url: &Url) -> Result<(u32, u32), String> {
+    let cid = match url.host.as_deref() {
+        Some("host") => libc::VMADDR_CID_HOST,
+        Some("local") => libc::VMADDR_CID_LOCAL,
+        Some(cid) => cid
+            .parse::<u32>()
+            .map_err(|_| format!("invalid vsock cid: {}", cid))?,
+        None => return Err("missing vsock cid".into()),
+    };
+    let port = url
+        .port
+        .ok_or_else(|| format!("missing vsock port: {}", url))?;
+    Ok((cid, port as u32))
+}
+
+impl AsRawFd for VsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Read for VsockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+}
+
+impl Write for VsockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = unsafe { libc::write(self.fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vsock_addr() {
+        let url = Url::parse("vsock://3:1024").unwrap();
+        assert_eq!(addr(&url), Ok((3, 1024)));
+        let url = Url::parse("vsock://host:8080").unwrap();
+        assert_eq!(addr(&url), Ok((libc::VMADDR_CID_HOST, 8080)));
+        let url = Url::parse("vsock://abc:8080").unwrap();
+        assert!(addr(&url).is_err());
+        let url = Url::parse("vsock://3").unwrap();
+        assert!(addr(&url).is_err());
+    }
+}
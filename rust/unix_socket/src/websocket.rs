@@ -0,0 +1,367 @@
+use std::io::{self, BufReader, Read, Write};
+
+use crate::error::HttpError;
+use crate::status::StatusCode;
+use crate::upgrade::Upgraded;
+use crate::uuid::random_u64;
+use crate::{base64, headers, sha1, HttpClient, HttpHeader, ReadWriter, Request};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// messages larger than this are rejected by default
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // status code and reason. None when the peer sent no code
+    Close(Option<(u16, String)>),
+}
+
+// WebSocket client (RFC 6455). frames are masked as required for clients
+pub struct WebSocket<S: Read + Write> {
+    conn: BufReader<S>,
+    max_message_size: usize,
+    // opcode and data of a fragmented message being received
+    fragment: Option<(u8, Vec<u8>)>,
+}
+
+fn protocol_error(msg: impl Into<String>) -> HttpError {
+    HttpError::Parse(format!("websocket: {}", msg.into()))
+}
+
+// value of Sec-WebSocket-Accept for the key
+pub fn accept_key(key: &str) -> String {
+    base64::encode(sha1::digest(format!("{}{}", key, GUID)))
+}
+
+impl<T: ReadWriter> WebSocket<Upgraded<T>> {
+    // send the opening handshake with req, e.g. a GET of the attach endpoint
+    pub fn connect(client: HttpClient<T>, req: &mut Request) -> Result<Self, HttpError> {
+        let mut nonce = [0u8; 16];
+        nonce[..8].copy_from_slice(&random_u64().to_be_bytes());
+        nonce[8..].copy_from_slice(&random_u64().to_be_bytes());
+        let key = base64::encode(nonce);
+
+        let header = req.header.get_or_insert_with(HttpHeader::new);
+        header.add(headers::CONNECTION, "Upgrade");
+        header.add(headers::UPGRADE, "websocket");
+        header.add(headers::SEC_WEBSOCKET_VERSION, "13");
+        header.add(headers::SEC_WEBSOCKET_KEY, &key);
+        let (resp, conn) = client.execute_request_upgrade(req)?;
+
+        if resp.status != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(HttpError::Status(resp.status));
+        }
+        if !resp.header.has_token(headers::UPGRADE, "websocket") {
+            return Err(protocol_error("missing upgrade to websocket"));
+        }
+        if resp
+            .header
+            .get(headers::SEC_WEBSOCKET_ACCEPT)
+            .map(|v| v.trim())
+            != Some(accept_key(&key).as_str())
+        {
+            return Err(protocol_error("invalid Sec-WebSocket-Accept"));
+        }
+        Ok(Self::new(conn))
+    }
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    // conn must be already upgraded
+    pub fn new(conn: S) -> Self {
+        Self {
+            conn: BufReader::new(conn),
+            max_message_size: MAX_MESSAGE_SIZE,
+            fragment: None,
+        }
+    }
+
+    pub fn max_message_size(&mut self, p: usize) -> &mut Self {
+        self.max_message_size = p;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.conn.get_ref()
+    }
+
+    pub fn send(&mut self, msg: &Message) -> Result<(), HttpError> {
+        let close;
+        let (opcode, payload) = match msg {
+            Message::Text(s) => (OP_TEXT, s.as_bytes()),
+            Message::Binary(b) => (OP_BINARY, b.as_slice()),
+            Message::Ping(b) => (OP_PING, b.as_slice()),
+            Message::Pong(b) => (OP_PONG, b.as_slice()),
+            Message::Close(reason) => {
+                close = match reason {
+                    Some((code, reason)) => [&code.to_be_bytes()[..], reason.as_bytes()].concat(),
+                    None => Vec::new(),
+                };
+                (OP_CLOSE, close.as_slice())
+            }
+        };
+        if opcode >= OP_CLOSE && payload.len() > 125 {
+            return Err(protocol_error("control frame too long"));
+        }
+        self.write_frame(opcode, payload)
+    }
+
+    // send Close with the code, e.g. 1000 for a normal closure
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), HttpError> {
+        self.send(&Message::Close(Some((code, reason.into()))))
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), HttpError> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            n if n < 126 => frame.push(0x80 | n as u8),
+            n if n <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        let mask = (random_u64() as u32).to_be_bytes();
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        let conn = self.conn.get_mut();
+        conn.write_all(&frame)?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    // returns (fin, opcode, payload)
+    fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>), HttpError> {
+        let mut head = [0u8; 2];
+        self.conn.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits are set"));
+        }
+        let opcode = head[0] & 0x0f;
+        let masked = head[1] & 0x80 != 0;
+        let length = match head[1] & 0x7f {
+            126 => {
+                let mut b = [0u8; 2];
+                self.conn.read_exact(&mut b)?;
+                u16::from_be_bytes(b) as u64
+            }
+            127 => {
+                let mut b = [0u8; 8];
+                self.conn.read_exact(&mut b)?;
+                u64::from_be_bytes(b)
+            }
+            n => n as u64,
+        };
+        if length > self.max_message_size as u64 {
+            return Err(protocol_error(format!(
+                "message too large (max {} bytes)",
+                self.max_message_size
+            )));
+        }
+        // NOTE: servers must not mask, but masked frames are accepted anyway
+        let mut mask = [0u8; 4];
+        if masked {
+            self.conn.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0u8; length as usize];
+        self.conn.read_exact(&mut payload)?;
+        if masked {
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[i % 4];
+            }
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    // read the next message. fragmented messages are reassembled, and control
+    // frames between the fragments are returned first
+    pub fn recv(&mut self) -> Result<Message, HttpError> {
+        loop {
+            let (fin, opcode, payload) = match self.read_frame() {
+                Err(HttpError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(HttpError::ConnectionClosed)
+                }
+                frame => frame?,
+            };
+            match opcode {
+                OP_PING => return Ok(Message::Ping(payload)),
+                OP_PONG => return Ok(Message::Pong(payload)),
+                OP_CLOSE => {
+                    let reason = match payload.as_slice() {
+                        [] => None,
+                        [a, b, reason @ ..] => Some((
+                            u16::from_be_bytes([*a, *b]),
+                            String::from_utf8_lossy(reason).into_owned(),
+                        )),
+                        _ => return Err(protocol_error("invalid close frame")),
+                    };
+                    return Ok(Message::Close(reason));
+                }
+                OP_TEXT | OP_BINARY if self.fragment.is_none() => {
+                    self.fragment = Some((opcode, payload))
+                }
+                OP_CONTINUATION => match &mut self.fragment {
+                    Some((_, data)) => {
+                        if data.len() + payload.len() > self.max_message_size {
+                            return Err(protocol_error(format!(
+                                "message too large (max {} bytes)",
+                                self.max_message_size
+                            )));
+                        }
+                        data.extend_from_slice(&payload);
+                    }
+                    None => return Err(protocol_error("unexpected continuation frame")),
+                },
+                OP_TEXT | OP_BINARY => return Err(protocol_error("expected continuation frame")),
+                opcode => return Err(protocol_error(format!("unknown opcode: {}", opcode))),
+            }
+            if !fin {
+                continue;
+            }
+            return match self.fragment.take() {
+                Some((OP_TEXT, data)) => String::from_utf8(data)
+                    .map(Message::Text)
+                    .map_err(|_| protocol_error("invalid utf-8 text")),
+                Some((_, data)) => Ok(Message::Binary(data)),
+                None => Err(protocol_error("unexpected continuation frame")),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, Cursor};
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    struct Conn {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Conn {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Conn {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn accept_key_rfc6455() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn websocket_frames() {
+        let input = [
+            // "Hel" + ping + "lo" in fragments
+            &[0x01, 0x03][..],
+            b"Hel",
+            &[0x89, 0x00],
+            &[0x80, 0x02],
+            b"lo",
+            // masked binary with 16 bit length
+            &[0x82, 0xfe, 0x00, 0x7e, 1, 2, 3, 4],
+            &[1, 2, 3, 4].repeat(126 / 4 + 1)[..126],
+            &[0x88, 0x04, 0x03, 0xe8],
+            b"ok",
+        ]
+        .concat();
+        let mut ws = WebSocket::new(Conn {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        });
+        assert_eq!(ws.recv().unwrap(), Message::Ping(vec![]));
+        assert_eq!(ws.recv().unwrap(), Message::Text("Hello".into()));
+        assert_eq!(ws.recv().unwrap(), Message::Binary(vec![0; 126]));
+        assert_eq!(
+            ws.recv().unwrap(),
+            Message::Close(Some((1000, "ok".into())))
+        );
+        assert!(matches!(ws.recv(), Err(HttpError::ConnectionClosed)));
+
+        ws.send(&Message::Text("hi".into())).unwrap();
+        let out = &ws.get_ref().output;
+        assert_eq!(out[..2], [0x81, 0x82]);
+        let mask = &out[2..6];
+        assert_eq!([out[6] ^ mask[0], out[7] ^ mask[1]], *b"hi");
+        assert!(ws.send(&Message::Ping(vec![0; 126])).is_err());
+    }
+
+    #[test]
+    fn websocket_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut r = BufReader::new(conn.try_clone().unwrap());
+            let mut key = String::new();
+            loop {
+                let mut line = String::new();
+                r.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(v) = line.strip_prefix("Sec-WebSocket-Key: ") {
+                    key = v.trim().to_string();
+                }
+            }
+            write!(
+                conn,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)
+            )
+            .unwrap();
+            // echo one unmasked text frame
+            let mut head = [0u8; 6];
+            r.read_exact(&mut head).unwrap();
+            let mut payload = vec![0u8; (head[1] & 0x7f) as usize];
+            r.read_exact(&mut payload).unwrap();
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= head[2 + i % 4];
+            }
+            conn.write_all(&[0x81, payload.len() as u8]).unwrap();
+            conn.write_all(&payload).unwrap();
+        });
+
+        let conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut ws = WebSocket::connect(
+            HttpClient::new(conn),
+            &mut Request::get("/containers/a/attach/ws?stream=1"),
+        )
+        .unwrap();
+        ws.send(&Message::Text("echo".into())).unwrap();
+        assert_eq!(ws.recv().unwrap(), Message::Text("echo".into()));
+        server.join().unwrap();
+    }
+}